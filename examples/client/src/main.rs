@@ -5,20 +5,27 @@ use netstack::{
         Event,
     },
     transport::UdpTransport,
+    packets::DEFAULT_MTU,
     time::Clock,
     security::{
         Secret,
         ConnectionToken
     },
-    packets::OutgoingPacket,
     monitoring::EmptyClientMonitor,
 };
 use std::net::SocketAddr;
 use std::time::Duration;
-use std::io::Write;
 use serde::{Deserialize, Serialize};
 use base58::FromBase58;
 
+// Must be the same on the client and the server, "netstack" in ASCII
+const PROTOCOL_ID: u64 = 0x6e65_7473_7461_636b;
+
+#[derive(Debug, netstack_derive::Serialize, netstack_derive::Deserialize)]
+pub struct Ping {
+    pub tick: u64,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ConnectionInfo {
     token: String,
@@ -45,9 +52,14 @@ fn main() {
     let transport = UdpTransport::new(local_address).unwrap();
 
     let config = Configuration {
+        protocol_id: PROTOCOL_ID,
         max_connections: 6,
         timeout: 120,
         heartbeat: 60,
+        mtu: DEFAULT_MTU,
+        max_message_size: 64 * 1024,
+        max_fragment_groups: 8,
+        fragment_timeout: 60,
     };
 
     let monitor = EmptyClientMonitor::new();
@@ -62,6 +74,7 @@ fn main() {
     let server = client.connect(remote_address, secret, connection_token).unwrap();
 
     let mut connected = false;
+    let mut tick = 0;
     loop {
         if clock.update() {
             let events = client.update();
@@ -76,9 +89,16 @@ fn main() {
                         connected = false;
                         println!("disconnected from a server");
                     },
-                    Event::Message { .. } => {
-                        println!("got a message from a server");
+                    Event::ConnectionFailed { error, .. } => {
+                        println!("could not connect to a server: {}", error);
+                    },
+                    Event::Message { payload, .. } => {
+                        match payload.deserialize::<Ping>() {
+                            Ok(ping) => println!("got {:?} from a server", ping),
+                            Err(error) => println!("got an invalid message from a server: {}", error),
+                        }
                     },
+                    Event::MessagesSent { .. } => {},
                     Event::MessageAcknowledged{ connection, sequence_number } => {
                         println!("Message {} sent to {} got acknowledged", sequence_number, connection);
                     },
@@ -86,10 +106,8 @@ fn main() {
             }
             
             if connected {
-                let mut packet = OutgoingPacket::new();
-                packet.write(&[0x1, 0x2, 0x3, 0x4]).unwrap();
-                
-                let sequence_number = client.send(packet, server).unwrap();
+                tick += 1;
+                let sequence_number = client.send_message(&Ping { tick }, server).unwrap();
                 println!("Sent Message {} to server", sequence_number);
             }
         }
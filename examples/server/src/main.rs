@@ -7,15 +7,14 @@ use netstack::{
         Event,
     },
     transport::UdpTransport,
+    packets::DEFAULT_MTU,
     time::Clock,
     security::{
         Secret,
         ConnectionToken,
     },
-    packets::OutgoingPacket,
 };
 use netstack_prometheus::PrometheusMonitor;
-use std::io::Write;
 use std::thread;
 use serde::{Deserialize, Serialize};
 use std::sync::mpsc::{sync_channel, SyncSender};
@@ -36,6 +35,14 @@ fn generate_connection_token() -> ConnectionToken {
     ConnectionToken::from_bytes(random_bytes)
 }
 
+// Must be the same on the client and the server, "netstack" in ASCII
+const PROTOCOL_ID: u64 = 0x6e65_7473_7461_636b;
+
+#[derive(Debug, netstack_derive::Serialize, netstack_derive::Deserialize)]
+pub struct Ping {
+    pub tick: u64,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ConnectionInfo {
     token: String,
@@ -88,10 +95,15 @@ fn main() {
     let mut clock = Clock::new(Duration::from_millis(16));
 
     let config = Configuration {
+        protocol_id: PROTOCOL_ID,
         max_connections: 64,
         timeout: 120,
         heartbeat: 60,
         reserved_timeout: 600,
+        mtu: DEFAULT_MTU,
+        max_message_size: 64 * 1024,
+        max_fragment_groups: 8,
+        fragment_timeout: 60,
     };
 
     let monitor = PrometheusMonitor::new();
@@ -120,14 +132,22 @@ fn main() {
                     Event::Disconnected { connection } => {
                         println!("A client disconnected from its slot {}", connection);
                     },
-                    Event::Message{ connection, payload } => {
-                        println!("Message from {}", connection);
-
-                        let mut packet = OutgoingPacket::new();
-                        packet.write(payload.get_buffer()).unwrap();
-
-                        let sequence_number = server.send(packet, connection).unwrap();
-                        println!("Sent Message {} to client {}", sequence_number, connection);
+                    Event::Message{ connection, payload, .. } => {
+                        let ping = match payload.deserialize::<Ping>() {
+                            Ok(ping) => ping,
+                            Err(error) => {
+                                println!("Invalid message from {}: {}", connection, error);
+                                continue;
+                            },
+                        };
+
+                        println!("{:?} from {}", ping, connection);
+
+                        let number = server.queue_message(&ping, connection).unwrap();
+                        println!("Queued Message {} for client {}", number, connection);
+                    },
+                    Event::MessagesSent{ connection, sequence_number, messages } => {
+                        println!("Sent queued Messages {:?} to client {} in Message {}", messages, connection, sequence_number);
                     },
                     Event::MessageAcknowledged{ connection, sequence_number } => {
                         println!("Message {} sent to {} got acknowledged", sequence_number, connection);
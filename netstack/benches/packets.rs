@@ -0,0 +1,84 @@
+use std::io::Write;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use netstack::packets::{IncomingPacket, OutgoingPacket, PacketBuffer, PacketPool, Payload, RawPacket, DEFAULT_MTU, HEADER_SIZE};
+
+const DATAGRAM_SIZE: usize = HEADER_SIZE + 100;
+
+// How packets were passed around before `PacketPool`, every move copies the whole array.
+struct ArrayPacket {
+    buffer: [u8; DEFAULT_MTU],
+    length: usize,
+}
+
+struct ArrayIncomingPacket {
+    packet: ArrayPacket,
+}
+
+struct ArrayPayload {
+    packet: ArrayPacket,
+}
+
+#[inline(never)]
+fn receive_array(datagram: &[u8]) -> ArrayPayload {
+    let mut buffer = [0; DEFAULT_MTU];
+    buffer[0..datagram.len()].copy_from_slice(datagram);
+
+    let packet = black_box(ArrayPacket { buffer, length: datagram.len() });
+    let incoming = black_box(ArrayIncomingPacket { packet });
+    black_box(ArrayPayload { packet: incoming.packet })
+}
+
+#[inline(never)]
+fn receive_buffer(mut buffer: PacketBuffer, datagram: &[u8]) -> Payload {
+    buffer[0..datagram.len()].copy_from_slice(datagram);
+
+    let packet = black_box(RawPacket::new(buffer, datagram.len()));
+    let incoming = black_box(IncomingPacket::from_raw_packet(packet));
+    black_box(incoming.into_payload())
+}
+
+fn receive(c: &mut Criterion) {
+    let datagram = [0x7; DATAGRAM_SIZE];
+    let pool = PacketPool::default();
+    let mut group = c.benchmark_group("receive");
+
+    group.bench_function("array by value", |b| b.iter(|| {
+        let payload = receive_array(black_box(&datagram));
+        black_box(&payload.packet.buffer[HEADER_SIZE..payload.packet.length]);
+    }));
+
+    group.bench_function("unpooled buffer", |b| b.iter(|| {
+        let payload = receive_buffer(PacketBuffer::unpooled(DEFAULT_MTU), black_box(&datagram));
+        black_box(payload.get_buffer());
+    }));
+
+    group.bench_function("pooled buffer", |b| b.iter(|| {
+        let payload = receive_buffer(pool.acquire(), black_box(&datagram));
+        black_box(payload.get_buffer());
+    }));
+
+    group.finish();
+}
+
+fn send(c: &mut Criterion) {
+    let pool = PacketPool::default();
+    let body = [0x7; 100];
+    let mut group = c.benchmark_group("send");
+
+    group.bench_function("unpooled buffer", |b| b.iter(|| {
+        let mut packet = OutgoingPacket::new();
+        packet.write_all(black_box(&body)).unwrap();
+        black_box(packet)
+    }));
+
+    group.bench_function("pooled buffer", |b| b.iter(|| {
+        let mut packet = OutgoingPacket::with_buffer(pool.acquire());
+        packet.write_all(black_box(&body)).unwrap();
+        black_box(packet)
+    }));
+
+    group.finish();
+}
+
+criterion_group!(benches, receive, send);
+criterion_main!(benches);
@@ -0,0 +1,166 @@
+use std::collections::VecDeque;
+use std::ops::Range;
+use crate::packets::{OutgoingPacket, PacketPool, HEADER_SIZE};
+use crate::serialization::{Deserializer, Reader, Serialize, SerializationError, Writer, var_uint_size};
+
+/// Collects the small messages of a connection until they are packed into as few packets as possible.
+///
+/// The body of a `PacketType::Aggregate` packet is a sequence of length prefixed messages.
+/// Every queued message gets a number, counting up from zero, so it can be traced back to the
+/// sequence number of the packet that carried it.
+pub struct SendQueue {
+    messages: VecDeque<Vec<u8>>,
+    next_number: u64,
+}
+
+impl SendQueue {
+    pub fn new() -> Self {
+        Self {
+            messages: VecDeque::new(),
+            next_number: 0,
+        }
+    }
+
+    /// Returns the number of messages waiting to be packed.
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Serializes a message into the queue and returns its number.
+    /// Fails with `SerializationError::BufferFull` if the message doesn't fit into a packet of `mtu` bytes on its own.
+    pub fn push(&mut self, message: &impl Serialize, mtu: usize) -> Result<u64, SerializationError> {
+        let size = message.encoded_size()?;
+        if var_uint_size(size as u64) + size > mtu - HEADER_SIZE {
+            return Err(SerializationError::BufferFull);
+        }
+
+        let mut bytes = vec![0; size];
+        message.serialize(&mut Writer::new(&mut bytes))?;
+        self.messages.push_back(bytes);
+
+        let number = self.next_number;
+        self.next_number += 1;
+
+        Ok(number)
+    }
+
+    /// Packs all queued messages into packets from the pool, in the order they were queued.
+    /// Returns each packet along with the numbers of the messages it carries.
+    pub fn pack(&mut self, pool: &PacketPool) -> Vec<(OutgoingPacket, Range<u64>)> {
+        let mut packets = Vec::new();
+        let mut first = self.next_number - self.messages.len() as u64;
+
+        while !self.messages.is_empty() {
+            let mut packet = OutgoingPacket::with_buffer(pool.acquire());
+            let mut count = 0;
+
+            while let Some(message) = self.messages.front() {
+                if packet.serialize(&message.as_slice()).is_err() {
+                    break;
+                }
+
+                self.messages.pop_front();
+                count += 1;
+            }
+
+            assert!(count > 0, "Every queued message fits into an empty packet");
+            packets.push((packet, first..first + count));
+            first += count;
+        }
+
+        packets
+    }
+}
+
+impl Default for SendQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Splits the body of a `PacketType::Aggregate` packet into the ranges of its messages.
+pub fn unpack(body: &[u8]) -> Result<Vec<Range<usize>>, SerializationError> {
+    let mut reader = Reader::new(body);
+    let mut messages = Vec::new();
+
+    while reader.remaining() > 0 {
+        let length = reader.deserialize_bytes()?.len();
+        let end = reader.get_position();
+        messages.push(end - length..end);
+    }
+
+    Ok(messages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::{PacketType, Protocol, DEFAULT_MTU};
+    use crate::security::Secret;
+
+    fn body(packet: OutgoingPacket) -> Vec<u8> {
+        let raw = packet.write_header_and_sign(1, 0, [0; 4], PacketType::Aggregate.to_u8(), &Secret::from_bytes([0; 32]), Protocol::new(0));
+        raw.get_body().to_vec()
+    }
+
+    #[test]
+    fn it_packs_messages_into_as_few_packets_as_possible() {
+        let pool = PacketPool::new(DEFAULT_MTU);
+        let mut queue = SendQueue::new();
+        let message = vec![0x7u8; 400];
+
+        for number in 0..7 {
+            assert_eq!(queue.push(&message, DEFAULT_MTU).unwrap(), number);
+        }
+
+        let packets = queue.pack(&pool);
+        assert!(queue.is_empty());
+
+        let ranges: Vec<_> = packets.iter().map(|(_, range)| range.clone()).collect();
+        assert_eq!(ranges, vec![0..3, 3..6, 6..7], "Three 404 byte messages fit into a packet");
+
+        queue.push(&1u8, DEFAULT_MTU).unwrap();
+        let packets = queue.pack(&pool);
+        assert_eq!(packets[0].1, 7..8);
+    }
+
+    #[test]
+    fn it_unpacks_what_it_packed() {
+        let pool = PacketPool::new(DEFAULT_MTU);
+        let mut queue = SendQueue::new();
+
+        queue.push(&"hello", DEFAULT_MTU).unwrap();
+        queue.push(&300u16, DEFAULT_MTU).unwrap();
+        queue.push(&Vec::<u8>::new(), DEFAULT_MTU).unwrap();
+
+        let (packet, range) = queue.pack(&pool).pop().unwrap();
+        assert_eq!(range, 0..3);
+
+        let body = body(packet);
+        let messages = unpack(&body).unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(Reader::new(&body[messages[0].clone()]).deserialize_string().unwrap(), "hello");
+        assert_eq!(Reader::new(&body[messages[1].clone()]).deserialize_u16().unwrap(), 300);
+        assert_eq!(messages[2], 11..12);
+    }
+
+    #[test]
+    fn it_rejects_messages_larger_than_a_packet() {
+        let mut queue = SendQueue::new();
+        let message = vec![0x7u8; DEFAULT_MTU - HEADER_SIZE - 3];
+
+        assert!(queue.push(&message, DEFAULT_MTU).is_err(), "The message and its two length prefixes don't fit");
+        assert!(queue.push(&&message[1..], DEFAULT_MTU).is_ok());
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn it_rejects_a_truncated_aggregate() {
+        assert!(unpack(&[2, 0x1, 0x2, 3, 0x1]).is_err());
+        assert_eq!(unpack(&[]).unwrap().len(), 0);
+    }
+}
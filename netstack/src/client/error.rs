@@ -16,4 +16,12 @@ pub enum ClientError {
 
     #[fail(display = "Connection not found")]
     ConnectionNotFound,
+
+    #[fail(display = "Server speaks protocol {:#x} version {}, the client speaks protocol {:#x} version {}", server_protocol_id, server_version, protocol_id, version)]
+    ProtocolMismatch {
+        protocol_id: u64,
+        version: u8,
+        server_protocol_id: u64,
+        server_version: u8,
+    },
 }
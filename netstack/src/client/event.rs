@@ -1,7 +1,9 @@
+use std::ops::Range;
 use crate::{
     connection::Connection,
     packets::Payload,
 };
+use super::ClientError;
 
 pub enum Event {
     Connected {
@@ -10,10 +12,24 @@ pub enum Event {
     Disconnected {
         connection: Connection,
     },
+    /// The server refused the connection, it is removed like after a disconnect.
+    ConnectionFailed {
+        connection: Connection,
+        error: ClientError,
+    },
+    /// A message arrived in the packet with the given sequence number, fragmented messages report the
+    /// fragment that completed them and every message of an aggregate packet reports the same one.
     Message {
         connection: Connection,
+        sequence_number: u64,
         payload: Payload,
     },
+    /// The queued messages numbered `messages` were sent in the packet with the given sequence number.
+    MessagesSent {
+        connection: Connection,
+        sequence_number: u64,
+        messages: Range<u64>,
+    },
     MessageAcknowledged {
         connection: Connection,
         sequence_number: u64,
@@ -1,9 +1,12 @@
 use failure::Error;
+use std::convert::TryInto;
 use crate::connection::*;
 use super::transport::Transport;
 use std::net::SocketAddr;
 use std::collections::HashMap;
-use crate::packets::RawPacket;
+use crate::packets::{RawPacket, Payload, Protocol, HEADER_SIZE};
+use crate::fragmentation::{self, Reassembly};
+use crate::aggregation::{self, SendQueue};
 
 mod configuration;
 pub use configuration::Configuration;
@@ -15,8 +18,9 @@ mod error;
 pub use error::ClientError;
 
 use crate::security::{Secret, ConnectionToken, ReplayBuffer};
-use crate::packets::{OutgoingPacket, PacketType};
+use crate::packets::{OutgoingPacket, PacketPool, PacketType};
 use crate::monitoring::ClientMonitor;
+use crate::serialization::Serialize;
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum ConnectionState {
@@ -39,12 +43,24 @@ pub struct Client {
     ack_buffers: ConnectionDataList<ReplayBuffer>,
     connection_tokens: ConnectionDataList<ConnectionToken>,
     address_to_connection: HashMap<SocketAddr, Connection>,
+    reassemblies: ConnectionDataList<Reassembly>,
+    fragment_ids: ConnectionDataList<u16>,
+    send_queues: ConnectionDataList<SendQueue>,
+    pool: PacketPool,
+    protocol: Protocol,
     monitor: Box<dyn ClientMonitor>,
 }
 
 impl Client {
+    /// # Panics
+    ///
+    /// If `configuration.mtu` is smaller than `fragmentation::MIN_MTU`.
     pub fn new(configuration: Configuration, transport: Box<dyn Transport>, monitor: Box<dyn ClientMonitor>) -> Self {
+        assert!(configuration.mtu >= fragmentation::MIN_MTU, "The MTU must be at least {} bytes to leave room for fragments", fragmentation::MIN_MTU);
+
         let max_connections = configuration.max_connections;
+        let pool = PacketPool::new(configuration.mtu);
+        let protocol = Protocol::new(configuration.protocol_id);
 
         Self {
             configuration,
@@ -60,6 +76,11 @@ impl Client {
             ack_buffers: ConnectionDataList::new(max_connections),
             connection_tokens: ConnectionDataList::new(max_connections),
             address_to_connection: HashMap::new(),
+            reassemblies: ConnectionDataList::new(max_connections),
+            fragment_ids: ConnectionDataList::new(max_connections),
+            send_queues: ConnectionDataList::new(max_connections),
+            pool,
+            protocol,
             monitor,
         }
     }
@@ -82,6 +103,13 @@ impl Client {
             self.replay_buffers.set(connection, ReplayBuffer::new());
             self.ack_buffers.set(connection, ReplayBuffer::new());
             self.connection_tokens.set(connection, connection_token);
+            self.reassemblies.set(connection, Reassembly::new(
+                self.configuration.max_message_size,
+                self.configuration.max_fragment_groups,
+                self.configuration.fragment_timeout,
+            ));
+            self.fragment_ids.set(connection, 0);
+            self.send_queues.set(connection, SendQueue::new());
 
             self.send_connection_message(connection)?;
 
@@ -101,7 +129,7 @@ impl Client {
         self.monitor.tick();
         
         while poll_again {
-            let mut buffer = [0; 1500];
+            let mut buffer = self.pool.acquire();
             match self.transport.poll(&mut buffer) {
                 Ok(Some((length, address))) => {
                     let packet = RawPacket::new(buffer, length);
@@ -127,19 +155,7 @@ impl Client {
             // manage timeouts
             let timeout = self.timeouts.get(connection).expect("No timeout set for connection") - 1;
             if timeout == 0 {
-                let address = self.addresses.get(connection).unwrap();
-                
-                self.address_to_connection.remove(address);
-                self.states.set(connection, ConnectionState::Disconnected);
-                self.addresses.remove(connection);
-                self.timeouts.remove(connection);
-                self.heartbeats.remove(connection);
-                self.sequence_numbers.remove(connection);
-                self.secrets.remove(connection);
-                self.connection_tokens.remove(connection);
-
-                self.connections.delete_connection(connection).unwrap();
-                self.monitor.disconnected();
+                self.remove_connection(connection);
                 events.push(Event::Disconnected { connection });
                 continue;
 
@@ -147,6 +163,8 @@ impl Client {
                 self.timeouts.set(connection, timeout);
             }
 
+            self.reassemblies.get_mut(connection).expect("No reassembly set for connection").update();
+
             // manage heartbeats
             let heartbeat = self.heartbeats.get(connection).expect("No heartbeat set for connection") - 1;
             if heartbeat == 0 {
@@ -168,14 +186,104 @@ impl Client {
             }
         }
 
+        let (sent, errors) = self.flush();
+        events.extend(sent);
+        for error in errors {
+            println!("could not send queued messages: {}", error);
+        }
+
         events
     }
 
+    /// Creates an empty packet with a buffer from the pool, which is reused once the packet is sent.
+    pub fn create_packet(&self) -> OutgoingPacket {
+        OutgoingPacket::with_buffer(self.pool.acquire())
+    }
+
     /// Sends a packet to the given connection and returns the packet's sequence number
     pub fn send(&mut self, packet: OutgoingPacket, connection: Connection) -> Result<u64, Error> {
+        self.check_connected(connection)?;
+
+        self.send_internal(packet, connection, PacketType::Payload)
+    }
+
+    /// Queues a small message to be sent along with the other queued messages of the connection
+    /// on the next `flush` or `update`. Returns the number of the message, `Event::MessagesSent`
+    /// tells which packet carried it.
+    pub fn queue_message(&mut self, message: &impl Serialize, connection: Connection) -> Result<u64, Error> {
+        self.check_connected(connection)?;
+
+        let send_queue = self.send_queues.get_mut(connection).expect("No send queue for connection found");
+        Ok(send_queue.push(message, self.pool.get_mtu())?)
+    }
+
+    /// Sends the queued messages of all connections in as few packets as possible.
+    /// Returns an event for every packet that was sent and an error for every packet that wasn't,
+    /// a failed send doesn't hold back the other packets.
+    pub fn flush(&mut self) -> (Vec<Event>, Vec<Error>) {
+        let mut events = Vec::new();
+        let mut errors = Vec::new();
+
+        let connections: Vec<Connection> = self.connections.into_iter().collect();
+        for connection in connections {
+            let packets = match self.send_queues.get_mut(connection) {
+                Some(send_queue) => send_queue.pack(&self.pool),
+                None => continue,
+            };
+
+            for (packet, messages) in packets {
+                match self.send_internal(packet, connection, PacketType::Aggregate) {
+                    Ok(sequence_number) => events.push(Event::MessagesSent {
+                        connection,
+                        sequence_number,
+                        messages,
+                    }),
+                    Err(error) => errors.push(error),
+                }
+            }
+        }
+
+        (events, errors)
+    }
+
+    /// Serializes a message of up to `Configuration::max_message_size` bytes and sends it to the given connection,
+    /// split into fragments if it doesn't fit into a single packet. Returns the sequence numbers of all packets sent.
+    pub fn send_fragmented(&mut self, message: &impl Serialize, connection: Connection) -> Result<Vec<u64>, Error> {
+        use std::io::Write;
+
+        self.check_connected(connection)?;
+
+        let bytes = fragmentation::serialize_message(message, self.configuration.max_message_size)?;
+
+        if bytes.len() <= self.pool.get_mtu() - HEADER_SIZE {
+            let mut packet = self.create_packet();
+            packet.write_all(&bytes)?;
+            return Ok(vec![self.send_internal(packet, connection, PacketType::Payload)?]);
+        }
+
+        let id = *self.fragment_ids.get(connection).expect("No fragment id for connection found");
+        self.fragment_ids.set(connection, id.wrapping_add(1));
+
+        let mut sequence_numbers = Vec::new();
+        for packet in fragmentation::write_fragments(&bytes, id, &self.pool)? {
+            sequence_numbers.push(self.send_internal(packet, connection, PacketType::Fragment)?);
+        }
+
+        Ok(sequence_numbers)
+    }
+
+    /// Serializes a message into a new packet, sends it to the given connection and returns the packet's sequence number
+    pub fn send_message(&mut self, message: &impl Serialize, connection: Connection) -> Result<u64, Error> {
+        let mut packet = self.create_packet();
+        packet.serialize(message)?;
+
+        self.send(packet, connection)
+    }
+
+    fn check_connected(&self, connection: Connection) -> Result<(), Error> {
         match self.get_connection_state(connection) {
             Some(ConnectionState::Connected) => {
-                Ok(self.send_internal(packet, connection, PacketType::Payload)?)
+                Ok(())
             },
             Some(ConnectionState::Connecting) => {
                 Err(ClientError::ConnectionStillConnecting.into())
@@ -196,7 +304,7 @@ impl Client {
 
         let (ack_sequence_number, ack_bits) = self.replay_buffers.get(connection).expect("no replay buffer for connection").get_ack_bits();
 
-        let raw = packet.write_header_and_sign(sequence_number, ack_sequence_number, ack_bits, packet_type.to_u8(), secret);
+        let raw = packet.write_header_and_sign(sequence_number, ack_sequence_number, ack_bits, packet_type.to_u8(), secret, self.protocol);
         let address = self.addresses.get(connection).expect("No address for connection found");
 
         // TODO check bytes sent?
@@ -208,6 +316,25 @@ impl Client {
         Ok(sequence_number)
     }
 
+    fn remove_connection(&mut self, connection: Connection) {
+        let address = self.addresses.get(connection).unwrap();
+
+        self.address_to_connection.remove(address);
+        self.states.set(connection, ConnectionState::Disconnected);
+        self.addresses.remove(connection);
+        self.timeouts.remove(connection);
+        self.heartbeats.remove(connection);
+        self.sequence_numbers.remove(connection);
+        self.secrets.remove(connection);
+        self.connection_tokens.remove(connection);
+        self.reassemblies.remove(connection);
+        self.fragment_ids.remove(connection);
+        self.send_queues.remove(connection);
+
+        self.connections.delete_connection(connection).unwrap();
+        self.monitor.disconnected();
+    }
+
     fn find_connection(&self, address: &SocketAddr) -> Option<Connection> {
         match self.address_to_connection.get(&address) {
             Some(c) => Some(*c),
@@ -225,71 +352,154 @@ impl Client {
     fn handle_message(&mut self, connection: Connection, packet: RawPacket, events: &mut Vec<Event>) {
         let secret = self.secrets.get(connection).expect("Secret for connection not found");
 
-        if let Some(incoming) = packet.verify(secret) {
-            let state = self.states.get(connection).expect("State for connection not found").clone();
-
-            let sequence_number = incoming.get_sequence_number();
-            let replay_buffer = self.replay_buffers.get_mut(connection).expect("No replay buffer for connection");
+        match packet.verify(secret, self.protocol) {
+            Ok(incoming) => {
+                let state = self.states.get(connection).expect("State for connection not found").clone();
 
-            if replay_buffer.acknowledge(sequence_number) {
-                let ack_sequence_number = incoming.get_ack_sequence_number();
-                let ack_bits = incoming.get_ack_bits();
+                let sequence_number = incoming.get_sequence_number();
+                let replay_buffer = self.replay_buffers.get_mut(connection).expect("No replay buffer for connection");
 
-                let ack_buffer = self.ack_buffers.get_mut(connection).expect("no replay buffer for connection");
-                let acked = ack_buffer.set_ack_bits(ack_sequence_number, ack_bits);
-
-                for sequence_number in acked {
-                    self.monitor.message_acknowledged();
-                    events.push(Event::MessageAcknowledged {
-                        connection,
-                        sequence_number,
-                    });
-                }
+                if replay_buffer.acknowledge(sequence_number) {
+                    if incoming.get_packet_type() == Some(PacketType::Rejected) {
+                        self.handle_rejection(connection, state, incoming.into_payload(), events);
+                        return;
+                    }
 
-                if state == ConnectionState::Connecting {
-                    self.states.set(connection, ConnectionState::Connected);
-                    self.timeouts.set(connection, self.configuration.timeout);
-                    self.heartbeats.set(connection, self.configuration.heartbeat);
-                    self.connection_tokens.remove(connection);
+                    let ack_sequence_number = incoming.get_ack_sequence_number();
+                    let ack_bits = incoming.get_ack_bits();
 
-                    self.monitor.connected();
-                    events.push(Event::Connected { connection });
+                    let ack_buffer = self.ack_buffers.get_mut(connection).expect("no replay buffer for connection");
+                    let acked = ack_buffer.set_ack_bits(ack_sequence_number, ack_bits);
 
-                    if incoming.get_packet_type() == Some(PacketType::Payload) {
-                        self.monitor.message_received();
-                        events.push(Event::Message {
+                    for sequence_number in acked {
+                        self.monitor.message_acknowledged();
+                        events.push(Event::MessageAcknowledged {
                             connection,
-                            payload: incoming.into_payload(),
+                            sequence_number,
                         });
                     }
-                } else {
 
-                    match incoming.get_packet_type() {
-                        Some(PacketType::Payload) => {
-                            self.timeouts.set(connection, self.configuration.timeout);
-
-                            self.monitor.message_received();
-                            events.push(Event::Message {
-                                connection,
-                                payload: incoming.into_payload(),
-                            });
-                        },
-                        Some(PacketType::Heartbeat) => {
-                            self.timeouts.set(connection, self.configuration.timeout);
-                        },
-                        Some(packet_type) => {
-                            println!("got unexpected packet type {:?}", packet_type);
+                    if state == ConnectionState::Connecting {
+                        self.states.set(connection, ConnectionState::Connected);
+                        self.timeouts.set(connection, self.configuration.timeout);
+                        self.heartbeats.set(connection, self.configuration.heartbeat);
+                        self.connection_tokens.remove(connection);
+
+                        self.monitor.connected();
+                        events.push(Event::Connected { connection });
+
+                        match incoming.get_packet_type() {
+                            Some(PacketType::Payload) => {
+                                self.monitor.message_received();
+                                events.push(Event::Message {
+                                    connection,
+                                    sequence_number,
+                                    payload: incoming.into_payload(),
+                                });
+                            },
+                            Some(PacketType::Fragment) => {
+                                self.handle_fragment(connection, sequence_number, incoming.into_payload(), events);
+                            },
+                            Some(PacketType::Aggregate) => {
+                                self.handle_aggregate(connection, sequence_number, incoming.into_payload(), events);
+                            },
+                            _ => {},
                         }
-                        _ => {
-                            println!("got invalid packet type");
+                    } else {
+
+                        match incoming.get_packet_type() {
+                            Some(PacketType::Payload) => {
+                                self.timeouts.set(connection, self.configuration.timeout);
+
+                                self.monitor.message_received();
+                                events.push(Event::Message {
+                                    connection,
+                                    sequence_number,
+                                    payload: incoming.into_payload(),
+                                });
+                            },
+                            Some(PacketType::Fragment) => {
+                                self.timeouts.set(connection, self.configuration.timeout);
+                                self.handle_fragment(connection, sequence_number, incoming.into_payload(), events);
+                            },
+                            Some(PacketType::Aggregate) => {
+                                self.timeouts.set(connection, self.configuration.timeout);
+                                self.handle_aggregate(connection, sequence_number, incoming.into_payload(), events);
+                            },
+                            Some(PacketType::Heartbeat) => {
+                                self.timeouts.set(connection, self.configuration.timeout);
+                            },
+                            Some(packet_type) => {
+                                println!("got unexpected packet type {:?}", packet_type);
+                            }
+                            _ => {
+                                println!("got invalid packet type");
+                            }
                         }
                     }
+                } else {
+                    println!("got packet with unusable sequence number");
                 }
-            } else {
-                println!("got packet with unusable sequence number");
-            }
-        } else {
-            println!("got invalid packet");
+            },
+            Err(error) => {
+                println!("rejected packet: {}", error);
+            },
+        }
+    }
+
+    fn handle_rejection(&mut self, connection: Connection, state: ConnectionState, rejection: Payload, events: &mut Vec<Event>) {
+        // the server's protocol id followed by its version
+        let body = rejection.get_buffer();
+        if state != ConnectionState::Connecting || body.len() != 9 {
+            println!("got unexpected rejection");
+            return;
+        }
+
+        let error = ClientError::ProtocolMismatch {
+            protocol_id: self.protocol.id,
+            version: self.protocol.version,
+            server_protocol_id: u64::from_le_bytes(body[0..8].try_into().expect("The body has 9 bytes")),
+            server_version: body[8],
+        };
+
+        self.remove_connection(connection);
+        events.push(Event::ConnectionFailed { connection, error });
+    }
+
+    fn handle_aggregate(&mut self, connection: Connection, sequence_number: u64, aggregate: Payload, events: &mut Vec<Event>) {
+        match aggregation::unpack(aggregate.get_buffer()) {
+            Ok(messages) => {
+                for payload in aggregate.split(messages) {
+                    self.monitor.message_received();
+                    events.push(Event::Message {
+                        connection,
+                        sequence_number,
+                        payload,
+                    });
+                }
+            },
+            Err(error) => {
+                println!("dropped aggregate packet: {}", error);
+            },
+        }
+    }
+
+    fn handle_fragment(&mut self, connection: Connection, sequence_number: u64, fragment: Payload, events: &mut Vec<Event>) {
+        let reassembly = self.reassemblies.get_mut(connection).expect("No reassembly for connection");
+
+        match reassembly.insert(fragment.get_buffer()) {
+            Ok(Some(message)) => {
+                self.monitor.message_received();
+                events.push(Event::Message {
+                    connection,
+                    sequence_number,
+                    payload: Payload::from_bytes(message),
+                });
+            },
+            Ok(None) => {},
+            Err(error) => {
+                println!("dropped fragment: {}", error);
+            },
         }
     }
 
@@ -298,8 +508,9 @@ impl Client {
         
         let connection_token = self.connection_tokens.get(connection).expect("No connection token for connection");
 
-        let mut packet = OutgoingPacket::new();
-        packet.write(connection_token.get_bytes())?;
+        let mut packet = self.create_packet();
+        packet.write_all(connection_token.get_bytes())?;
+        packet.write_all(&self.protocol.id.to_le_bytes())?;
         
         self.send_internal(packet, connection, PacketType::Connection)?;
 
@@ -307,7 +518,7 @@ impl Client {
     }
 
     fn send_heartbeat_message(&mut self, connection: Connection) -> Result<(), Error> {
-        let packet = OutgoingPacket::new();
+        let packet = self.create_packet();
         self.send_internal(packet, connection, PacketType::Heartbeat)?;
 
         Ok(())
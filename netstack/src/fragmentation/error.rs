@@ -0,0 +1,37 @@
+#[derive(Debug, Fail)]
+pub enum FragmentationError {
+    #[fail(display = "Message of {} bytes exceeds the maximum message size of {} bytes", size, max)]
+    MessageTooLarge {
+        size: usize,
+        max: usize,
+    },
+
+    #[fail(display = "Fragment is too short to contain a fragment header")]
+    MissingHeader,
+
+    #[fail(display = "Fragment index {} is not within the fragment count of {}", index, count)]
+    InvalidIndex {
+        index: u16,
+        count: u16,
+    },
+
+    #[fail(display = "Fragment {} of message {} has {} bytes, only the last fragment may have fewer than {} bytes", index, id, size, fragment_size)]
+    InvalidFragmentSize {
+        id: u16,
+        index: u16,
+        size: usize,
+        fragment_size: usize,
+    },
+
+    #[fail(display = "Fragment {} of message {} was already received", index, id)]
+    DuplicateFragment {
+        id: u16,
+        index: u16,
+    },
+
+    #[fail(display = "MTU of {} bytes leaves no room for fragments, it must be at least {} bytes", mtu, min)]
+    MtuTooSmall {
+        mtu: usize,
+        min: usize,
+    },
+}
@@ -0,0 +1,164 @@
+mod error;
+mod reassembly;
+
+pub use error::FragmentationError;
+pub use reassembly::Reassembly;
+
+use failure::Error;
+use std::io::{self, Write};
+use crate::packets::{OutgoingPacket, PacketPool, HEADER_SIZE};
+use crate::serialization::{Serialize, Writer};
+
+/// The number of bytes in front of the data of every fragment.
+pub const FRAGMENT_HEADER_SIZE: usize = 8;
+
+/// The smallest MTU that leaves room for at least one byte of data in a fragment.
+pub const MIN_MTU: usize = HEADER_SIZE + FRAGMENT_HEADER_SIZE + 1;
+
+/// Returns how many bytes of a message fit into each fragment of a packet of `mtu` bytes,
+/// at most `u16::MAX` so the size fits into the `FragmentHeader`.
+pub fn fragment_size(mtu: usize) -> Result<usize, FragmentationError> {
+    match mtu.checked_sub(HEADER_SIZE + FRAGMENT_HEADER_SIZE) {
+        Some(size) if size > 0 => Ok(size.min(u16::MAX as usize)),
+        _ => Err(FragmentationError::MtuTooSmall { mtu, min: MIN_MTU }),
+    }
+}
+
+/// Identifies a fragment of a message that was split across several packets of type `PacketType::Fragment`.
+///
+/// All fragments of a message share the same `id`, the receiver puts them back together by `index`
+/// once all `count` fragments have arrived. Every fragment but the last carries exactly `size` bytes,
+/// the sender picks it from its own MTU, so peers don't need to agree on one.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct FragmentHeader {
+    pub id: u16,
+    pub index: u16,
+    pub count: u16,
+    pub size: u16,
+}
+
+impl FragmentHeader {
+    /// Splits the body of a fragment into its header and data, `None` if the body is too short.
+    pub fn read(body: &[u8]) -> Option<(Self, &[u8])> {
+        if body.len() < FRAGMENT_HEADER_SIZE {
+            return None;
+        }
+
+        let header = Self {
+            id: u16::from_le_bytes([body[0], body[1]]),
+            index: u16::from_le_bytes([body[2], body[3]]),
+            count: u16::from_le_bytes([body[4], body[5]]),
+            size: u16::from_le_bytes([body[6], body[7]]),
+        };
+
+        Some((header, &body[FRAGMENT_HEADER_SIZE..]))
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> Result<(), io::Error> {
+        let mut bytes = [0; FRAGMENT_HEADER_SIZE];
+        bytes[0..2].copy_from_slice(&self.id.to_le_bytes());
+        bytes[2..4].copy_from_slice(&self.index.to_le_bytes());
+        bytes[4..6].copy_from_slice(&self.count.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.size.to_le_bytes());
+
+        writer.write_all(&bytes)
+    }
+}
+
+/// Splits `bytes` into fragments of at most `fragment_size` bytes.
+/// Fails with `FragmentationError::MessageTooLarge` if that takes more than `u16::MAX` fragments.
+pub fn split(bytes: &[u8], id: u16, fragment_size: usize) -> Result<Vec<(FragmentHeader, &[u8])>, FragmentationError> {
+    assert!(fragment_size > 0, "Fragments must have room for at least one byte");
+    assert!(fragment_size <= u16::MAX as usize, "The fragment size must fit into the fragment header");
+
+    let max = u16::MAX as usize * fragment_size;
+    if bytes.len() > max {
+        return Err(FragmentationError::MessageTooLarge { size: bytes.len(), max });
+    }
+
+    let count = bytes.len().div_ceil(fragment_size).max(1) as u16;
+    let size = fragment_size as u16;
+    let mut fragments: Vec<_> = bytes.chunks(fragment_size)
+        .enumerate()
+        .map(|(index, data)| (FragmentHeader { id, index: index as u16, count, size }, data))
+        .collect();
+
+    // an empty message still takes one fragment
+    if fragments.is_empty() {
+        fragments.push((FragmentHeader { id, index: 0, count, size }, bytes));
+    }
+
+    Ok(fragments)
+}
+
+/// Serializes a message into a buffer of its exact size, as long as it isn't larger than `max_message_size`.
+pub(crate) fn serialize_message(message: &impl Serialize, max_message_size: usize) -> Result<Vec<u8>, Error> {
+    let size = message.encoded_size()?;
+    if size > max_message_size {
+        return Err(FragmentationError::MessageTooLarge { size, max: max_message_size }.into());
+    }
+
+    let mut bytes = vec![0; size];
+    message.serialize(&mut Writer::new(&mut bytes))?;
+
+    Ok(bytes)
+}
+
+/// Writes `bytes` into as many packets from the pool as needed, each starting with its `FragmentHeader`.
+pub(crate) fn write_fragments(bytes: &[u8], id: u16, pool: &PacketPool) -> Result<Vec<OutgoingPacket>, Error> {
+    let fragment_size = fragment_size(pool.get_mtu())?;
+    let mut packets = Vec::new();
+
+    for (header, data) in split(bytes, id, fragment_size)? {
+        let mut packet = OutgoingPacket::with_buffer(pool.acquire());
+        header.write(&mut packet)?;
+        packet.write_all(data)?;
+        packets.push(packet);
+    }
+
+    Ok(packets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_writes_and_reads_a_fragment_header() {
+        let header = FragmentHeader { id: 0x0102, index: 3, count: 700, size: 1200 };
+        let mut body = Vec::new();
+        header.write(&mut body).unwrap();
+        body.extend_from_slice(&[0x7, 0x8]);
+
+        assert_eq!(body.len(), FRAGMENT_HEADER_SIZE + 2);
+        assert_eq!(FragmentHeader::read(&body), Some((header, &[0x7, 0x8][..])));
+        assert_eq!(FragmentHeader::read(&body[0..7]), None);
+    }
+
+    #[test]
+    fn it_rejects_an_mtu_without_room_for_fragments() {
+        assert_eq!(fragment_size(MIN_MTU).unwrap(), 1);
+        assert!(fragment_size(MIN_MTU - 1).is_err());
+        assert!(fragment_size(HEADER_SIZE).is_err());
+        assert_eq!(fragment_size(100_000).unwrap(), u16::MAX as usize);
+    }
+
+    #[test]
+    fn it_splits_bytes_into_fragments() {
+        let bytes: Vec<u8> = (0..10).collect();
+        let fragments = split(&bytes, 9, 4).unwrap();
+
+        assert_eq!(fragments.len(), 3);
+        assert_eq!(fragments[0], (FragmentHeader { id: 9, index: 0, count: 3, size: 4 }, &bytes[0..4]));
+        assert_eq!(fragments[2], (FragmentHeader { id: 9, index: 2, count: 3, size: 4 }, &bytes[8..10]));
+        assert_eq!(split(&[], 9, 4).unwrap(), vec![(FragmentHeader { id: 9, index: 0, count: 1, size: 4 }, &[][..])]);
+    }
+
+    #[test]
+    fn it_rejects_bytes_that_need_too_many_fragments() {
+        let bytes = vec![0; u16::MAX as usize + 1];
+
+        assert!(split(&bytes, 0, 1).is_err());
+        assert_eq!(split(&bytes, 0, 2).unwrap().len(), 32768);
+    }
+}
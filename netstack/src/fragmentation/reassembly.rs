@@ -0,0 +1,259 @@
+use std::collections::VecDeque;
+use super::{FragmentHeader, FragmentationError};
+
+struct FragmentGroup {
+    id: u16,
+    count: u16,
+    fragment_size: usize,
+    message: Vec<u8>,
+    received: Vec<bool>,
+    received_count: u16,
+    size: usize,
+    timeout: usize,
+}
+
+/// Puts fragmented messages of a single connection back together.
+///
+/// Every fragment but the last carries exactly the size given in its `FragmentHeader`, so each message is
+/// written into a single buffer allocated by its first fragment. At most `max_groups` messages are reassembled at the
+/// same time, a fragment of another message evicts the oldest incomplete one. Messages are dropped once
+/// they grow beyond `max_message_size` bytes or are still incomplete after `timeout` updates, so a
+/// connection never buffers more than `max_groups * max_message_size` bytes.
+pub struct Reassembly {
+    max_message_size: usize,
+    max_groups: usize,
+    timeout: usize,
+    groups: VecDeque<FragmentGroup>,
+}
+
+impl Reassembly {
+    pub fn new(max_message_size: usize, max_groups: usize, timeout: usize) -> Self {
+        assert!(max_groups > 0, "At least one message must be reassembled at a time");
+
+        Self {
+            max_message_size,
+            max_groups,
+            timeout,
+            groups: VecDeque::with_capacity(max_groups),
+        }
+    }
+
+    /// Returns the number of messages waiting for more fragments.
+    pub fn get_group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Returns the number of bytes allocated for incomplete messages.
+    pub fn get_buffered_size(&self) -> usize {
+        self.groups.iter().map(|group| group.message.len()).sum()
+    }
+
+    /// Adds the body of a `PacketType::Fragment` packet and returns the message once all of its fragments arrived.
+    pub fn insert(&mut self, body: &[u8]) -> Result<Option<Vec<u8>>, FragmentationError> {
+        let (header, data) = FragmentHeader::read(body).ok_or(FragmentationError::MissingHeader)?;
+
+        if header.index >= header.count {
+            return Err(FragmentationError::InvalidIndex { index: header.index, count: header.count });
+        }
+
+        // only the last fragment may be shorter, and only an empty message has an empty one
+        let fragment_size = header.size as usize;
+        let last = header.index == header.count - 1;
+        let valid_size = if last {
+            data.len() <= fragment_size && (!data.is_empty() || header.count == 1)
+        } else {
+            data.len() == fragment_size
+        };
+
+        if fragment_size == 0 || !valid_size {
+            return Err(FragmentationError::InvalidFragmentSize {
+                id: header.id,
+                index: header.index,
+                size: data.len(),
+                fragment_size,
+            });
+        }
+
+        // all but the last fragment are full, so the count alone tells if the message could fit
+        let smallest = (header.count as usize - 1) * fragment_size;
+        if header.count > 1 && smallest >= self.max_message_size {
+            return Err(FragmentationError::MessageTooLarge { size: smallest + 1, max: self.max_message_size });
+        }
+
+        let position = match self.groups.iter().position(|group| group.id == header.id) {
+            Some(position) if self.groups[position].count == header.count && self.groups[position].fragment_size == fragment_size => position,
+            existing => {
+                // a different count or size means the id wrapped around and the old message is lost
+                if let Some(position) = existing {
+                    self.groups.remove(position);
+                } else if self.groups.len() == self.max_groups {
+                    self.groups.pop_front();
+                }
+
+                let capacity = (header.count as usize * fragment_size).min(self.max_message_size);
+                self.groups.push_back(FragmentGroup {
+                    id: header.id,
+                    count: header.count,
+                    fragment_size,
+                    message: vec![0; capacity],
+                    received: vec![false; header.count as usize],
+                    received_count: 0,
+                    size: 0,
+                    timeout: self.timeout,
+                });
+                self.groups.len() - 1
+            },
+        };
+
+        let group = &mut self.groups[position];
+
+        if group.received[header.index as usize] {
+            return Err(FragmentationError::DuplicateFragment { id: header.id, index: header.index });
+        }
+
+        let start = header.index as usize * fragment_size;
+        let end = start + data.len();
+        if end > group.message.len() {
+            self.groups.remove(position);
+            return Err(FragmentationError::MessageTooLarge { size: end, max: self.max_message_size });
+        }
+
+        group.message[start..end].copy_from_slice(data);
+        group.received[header.index as usize] = true;
+        group.received_count += 1;
+        group.size += data.len();
+
+        if group.received_count < group.count {
+            return Ok(None);
+        }
+
+        let group = self.groups.remove(position).expect("The group exists");
+        let mut message = group.message;
+        message.truncate(group.size);
+
+        Ok(Some(message))
+    }
+
+    /// Counts down the timeouts of incomplete messages and drops the expired ones.
+    /// Returns the number of dropped messages.
+    pub fn update(&mut self) -> usize {
+        let count = self.groups.len();
+
+        self.groups.retain(|group| group.timeout > 1);
+        for group in self.groups.iter_mut() {
+            group.timeout -= 1;
+        }
+
+        count - self.groups.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragment(id: u16, index: u16, count: u16, size: u16, data: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        FragmentHeader { id, index, count, size }.write(&mut body).unwrap();
+        body.extend_from_slice(data);
+        body
+    }
+
+    #[test]
+    fn it_reassembles_fragments_in_any_order() {
+        let mut reassembly = Reassembly::new(1024, 4, 10);
+
+        assert_eq!(reassembly.insert(&fragment(1, 2, 3, 2, &[5])).unwrap(), None);
+        assert_eq!(reassembly.insert(&fragment(1, 0, 3, 2, &[1, 2])).unwrap(), None);
+        assert_eq!(reassembly.get_buffered_size(), 6, "The message is allocated by its first fragment");
+        assert_eq!(reassembly.insert(&fragment(1, 1, 3, 2, &[3, 4])).unwrap(), Some(vec![1, 2, 3, 4, 5]));
+        assert_eq!(reassembly.get_group_count(), 0);
+    }
+
+    #[test]
+    fn it_rejects_invalid_and_duplicate_fragments() {
+        let mut reassembly = Reassembly::new(1024, 4, 10);
+
+        assert!(reassembly.insert(&[0, 0, 0]).is_err());
+        assert!(reassembly.insert(&fragment(1, 3, 3, 2, &[1, 2])).is_err());
+        assert_eq!(reassembly.insert(&fragment(2, 0, 1, 2, &[])).unwrap(), Some(vec![]));
+
+        reassembly.insert(&fragment(1, 0, 3, 2, &[1, 2])).unwrap();
+        assert!(reassembly.insert(&fragment(1, 0, 3, 2, &[1, 2])).is_err());
+        assert_eq!(reassembly.get_group_count(), 1);
+    }
+
+    #[test]
+    fn it_rejects_fragments_of_the_wrong_size() {
+        let mut reassembly = Reassembly::new(1024, 4, 10);
+
+        assert!(matches!(reassembly.insert(&fragment(1, 0, 3, 2, &[1])), Err(FragmentationError::InvalidFragmentSize { size: 1, .. })));
+        assert!(reassembly.insert(&fragment(1, 1, 3, 2, &[])).is_err());
+        assert!(reassembly.insert(&fragment(1, 2, 3, 2, &[])).is_err(), "Only an empty message has an empty last fragment");
+        assert!(reassembly.insert(&fragment(1, 2, 3, 2, &[1, 2, 3])).is_err());
+        assert_eq!(reassembly.get_group_count(), 0);
+    }
+
+    #[test]
+    fn it_rejects_a_fragment_count_beyond_the_maximum() {
+        let mut reassembly = Reassembly::new(4, 4, 10);
+
+        assert!(matches!(reassembly.insert(&fragment(1, 0, u16::MAX, 1, &[1])), Err(FragmentationError::MessageTooLarge { .. })));
+        assert!(reassembly.insert(&fragment(1, 0, 5, 1, &[1])).is_err());
+        assert_eq!(reassembly.get_group_count(), 0);
+
+        reassembly.insert(&fragment(1, 0, 4, 1, &[1])).unwrap();
+        assert_eq!(reassembly.get_buffered_size(), 4);
+    }
+
+    #[test]
+    fn it_drops_messages_larger_than_the_maximum() {
+        let mut reassembly = Reassembly::new(3, 4, 10);
+
+        reassembly.insert(&fragment(1, 0, 2, 2, &[1, 2])).unwrap();
+        assert!(reassembly.insert(&fragment(1, 1, 2, 2, &[3, 4])).is_err());
+        assert_eq!(reassembly.get_group_count(), 0);
+        assert_eq!(reassembly.get_buffered_size(), 0);
+    }
+
+    #[test]
+    fn it_evicts_the_oldest_message_when_too_many_are_in_flight() {
+        let mut reassembly = Reassembly::new(1024, 2, 10);
+
+        for id in 0..5 {
+            reassembly.insert(&fragment(id, 0, 2, 1, &[id as u8])).unwrap();
+        }
+        assert_eq!(reassembly.get_group_count(), 2);
+
+        assert_eq!(reassembly.insert(&fragment(0, 1, 2, 1, &[0])).unwrap(), None, "The first message was evicted");
+        assert_eq!(reassembly.insert(&fragment(4, 1, 2, 1, &[9])).unwrap(), Some(vec![4, 9]));
+    }
+
+    #[test]
+    fn it_drops_messages_that_time_out() {
+        let mut reassembly = Reassembly::new(1024, 4, 2);
+
+        reassembly.insert(&fragment(1, 0, 2, 1, &[1])).unwrap();
+        assert_eq!(reassembly.update(), 0);
+        reassembly.insert(&fragment(2, 0, 2, 1, &[2])).unwrap();
+        assert_eq!(reassembly.update(), 1);
+        assert_eq!(reassembly.get_group_count(), 1);
+
+        assert_eq!(reassembly.insert(&fragment(1, 1, 2, 1, &[1])).unwrap(), None, "The first message timed out");
+        assert_eq!(reassembly.insert(&fragment(2, 1, 2, 1, &[3])).unwrap(), Some(vec![2, 3]));
+    }
+
+    #[test]
+    fn it_takes_the_fragment_size_from_the_header() {
+        let mut reassembly = Reassembly::new(1024, 4, 10);
+
+        assert_eq!(reassembly.insert(&fragment(1, 1, 2, 3, &[4])).unwrap(), None);
+        assert_eq!(reassembly.insert(&fragment(2, 0, 2, 1, &[1])).unwrap(), None);
+        assert_eq!(reassembly.insert(&fragment(1, 0, 2, 3, &[1, 2, 3])).unwrap(), Some(vec![1, 2, 3, 4]));
+        assert!(matches!(reassembly.insert(&fragment(3, 0, 1, 0, &[])), Err(FragmentationError::InvalidFragmentSize { fragment_size: 0, .. })));
+
+        // a fragment with another size belongs to a new message with the same id
+        assert_eq!(reassembly.insert(&fragment(2, 0, 2, 2, &[1, 2])).unwrap(), None);
+        assert_eq!(reassembly.insert(&fragment(2, 1, 2, 2, &[3])).unwrap(), Some(vec![1, 2, 3]));
+    }
+}
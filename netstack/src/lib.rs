@@ -3,7 +3,11 @@ extern crate failure;
 
 pub mod packet;
 pub mod packets;
+pub mod fragmentation;
+pub mod aggregation;
 pub mod serialization;
+pub mod messages;
+pub mod schema;
 pub mod server;
 pub mod client;
 pub mod transport;
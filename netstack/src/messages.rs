@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use crate::connection::Connection;
+use crate::packets::Payload;
+use crate::serialization::{DeserializeOwned, Deserializer, Reader, Serialize, Serializer, SerializationError, LATEST_VERSION};
+
+/// A message with a stable id, the id is written in front of the message so the receiver knows
+/// which type to decode.
+///
+/// Usually derived with `#[derive(Message)]`, the id is either set with `#[netstack(id = 7)]` or
+/// calculated from the name of the type with `message_id`. Ids are written as varints, explicit
+/// small ids take up less space than hashed ones.
+pub trait Message: Serialize {
+    const ID: u32;
+}
+
+/// Calculates the id of a message from its name, using 32 bit FNV-1a.
+///
+/// Only the name of the type is hashed, so the id stays the same when the type is moved to
+/// another module or crate.
+pub const fn message_id(name: &str) -> u32 {
+    let bytes = name.as_bytes();
+    let mut hash: u32 = 0x811c_9dc5;
+    let mut index = 0;
+
+    while index < bytes.len() {
+        hash ^= bytes[index] as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+        index += 1;
+    }
+
+    hash
+}
+
+/// Writes the id of the message followed by the message.
+pub fn serialize_message<M>(message: &M, serializer: &mut impl Serializer) -> Result<(), SerializationError> where M: Message {
+    serializer.serialize_u32(&M::ID)?;
+    message.serialize(serializer)
+}
+
+/// Handles messages of type `M` passed on by a `Dispatcher`.
+pub trait Handler<M> {
+    fn handle(&mut self, connection: Connection, message: M);
+}
+
+type Dispatch<H> = for<'a> fn(&mut H, Connection, &mut Reader<'a>) -> Result<(), SerializationError>;
+
+/// Decodes payloads into the registered message types and passes them to the matching `Handler`.
+///
+/// ```ignore
+/// let mut dispatcher = Dispatcher::new();
+/// dispatcher.register::<Chat>()?.register::<Move>()?;
+///
+/// if let Event::Message { connection, payload, .. } = event {
+///     dispatcher.dispatch(&mut game, connection, &payload)?;
+/// }
+/// ```
+pub struct Dispatcher<H> {
+    handlers: HashMap<u32, Dispatch<H>>,
+}
+
+impl<H> Dispatcher<H> {
+    pub fn new() -> Self {
+        Self {
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Passes messages of type `M` to `H`'s `Handler<M>` implementation.
+    /// Fails with `SerializationError::DuplicateMessageId` if another type with the same id is registered.
+    pub fn register<M>(&mut self) -> Result<&mut Self, SerializationError> where M: Message + DeserializeOwned, H: Handler<M> {
+        match self.handlers.entry(M::ID) {
+            Entry::Occupied(_) => Err(SerializationError::DuplicateMessageId { id: M::ID }),
+            Entry::Vacant(entry) => {
+                entry.insert(dispatch::<H, M>);
+                Ok(self)
+            },
+        }
+    }
+
+    /// Returns whether a message type with the given id is registered.
+    pub fn is_registered(&self, id: u32) -> bool {
+        self.handlers.contains_key(&id)
+    }
+
+    /// Decodes the message in the payload and passes it to the handler.
+    /// Fails with `SerializationError::UnknownMessageId` if no type is registered for its id.
+    pub fn dispatch(&self, handler: &mut H, connection: Connection, payload: &Payload) -> Result<(), SerializationError> {
+        self.dispatch_versioned(handler, connection, payload, LATEST_VERSION)
+    }
+
+    /// Like `dispatch`, for payloads written by a peer using the given protocol version.
+    pub fn dispatch_versioned(&self, handler: &mut H, connection: Connection, payload: &Payload, version: u32) -> Result<(), SerializationError> {
+        let mut reader = Reader::new(payload.get_buffer()).with_version(version);
+        self.dispatch_from(handler, connection, &mut reader)
+    }
+
+    fn dispatch_from(&self, handler: &mut H, connection: Connection, reader: &mut Reader) -> Result<(), SerializationError> {
+        let id = reader.deserialize_u32()?;
+
+        match self.handlers.get(&id) {
+            Some(dispatch) => dispatch(handler, connection, reader),
+            None => Err(SerializationError::UnknownMessageId { id }),
+        }
+    }
+}
+
+impl<H> Default for Dispatcher<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn dispatch<H, M>(handler: &mut H, connection: Connection, reader: &mut Reader) -> Result<(), SerializationError> where H: Handler<M>, M: DeserializeOwned {
+    let message = M::deserialize(reader)?;
+    handler.handle(connection, message);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::{Deserialize, Writer};
+
+    #[derive(Debug, PartialEq)]
+    struct Chat(String);
+
+    impl Message for Chat {
+        const ID: u32 = 1;
+    }
+
+    impl Serialize for Chat {
+        fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+            self.0.serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Chat {
+        type Item = Chat;
+
+        fn deserialize(deserializer: &mut impl Deserializer<'de>) -> Result<Self::Item, SerializationError> {
+            Ok(Chat(String::deserialize(deserializer)?))
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Move(i32);
+
+    impl Message for Move {
+        const ID: u32 = message_id("Move");
+    }
+
+    impl Serialize for Move {
+        fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+            self.0.serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Move {
+        type Item = Move;
+
+        fn deserialize(deserializer: &mut impl Deserializer<'de>) -> Result<Self::Item, SerializationError> {
+            Ok(Move(i32::deserialize(deserializer)?))
+        }
+    }
+
+    struct AlsoOne;
+
+    impl Message for AlsoOne {
+        const ID: u32 = 1;
+    }
+
+    impl Serialize for AlsoOne {
+        fn serialize(&self, _serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+            Ok(())
+        }
+    }
+
+    impl<'de> Deserialize<'de> for AlsoOne {
+        type Item = AlsoOne;
+
+        fn deserialize(_deserializer: &mut impl Deserializer<'de>) -> Result<Self::Item, SerializationError> {
+            Ok(AlsoOne)
+        }
+    }
+
+    #[derive(Default)]
+    struct Game {
+        chats: Vec<Chat>,
+        moves: Vec<Move>,
+    }
+
+    impl Handler<Chat> for Game {
+        fn handle(&mut self, _connection: Connection, message: Chat) {
+            self.chats.push(message);
+        }
+    }
+
+    impl Handler<Move> for Game {
+        fn handle(&mut self, _connection: Connection, message: Move) {
+            self.moves.push(message);
+        }
+    }
+
+    impl Handler<AlsoOne> for Game {
+        fn handle(&mut self, _connection: Connection, _message: AlsoOne) {}
+    }
+
+    fn dispatch_message<M>(dispatcher: &Dispatcher<Game>, game: &mut Game, message: &M) -> Result<(), SerializationError> where M: Message {
+        let mut buffer = [0; 64];
+        let length = {
+            let mut writer = Writer::new(&mut buffer);
+            serialize_message(message, &mut writer)?;
+            writer.get_position()
+        };
+
+        let mut reader = Reader::new(&buffer[0..length]);
+        dispatcher.dispatch_from(game, Connection::new(0, 0), &mut reader)
+    }
+
+    #[test]
+    fn it_hashes_message_names() {
+        assert_eq!(message_id(""), 0x811c_9dc5);
+        assert_eq!(message_id("a"), 0xe40c_292c);
+        assert_ne!(message_id("Chat"), message_id("Move"));
+    }
+
+    #[test]
+    fn it_dispatches_messages_to_their_handler() {
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.register::<Chat>().unwrap().register::<Move>().unwrap();
+
+        let mut game = Game::default();
+        dispatch_message(&dispatcher, &mut game, &Chat("hello".to_owned())).unwrap();
+        dispatch_message(&dispatcher, &mut game, &Move(-3)).unwrap();
+        dispatch_message(&dispatcher, &mut game, &Move(4)).unwrap();
+
+        assert_eq!(game.chats, vec![Chat("hello".to_owned())]);
+        assert_eq!(game.moves, vec![Move(-3), Move(4)]);
+    }
+
+    #[test]
+    fn it_rejects_unknown_message_ids() {
+        let mut dispatcher = Dispatcher::new();
+        dispatcher.register::<Chat>().unwrap();
+
+        let result = dispatch_message(&dispatcher, &mut Game::default(), &Move(1));
+        assert!(matches!(result, Err(SerializationError::UnknownMessageId { id }) if id == Move::ID));
+    }
+
+    #[test]
+    fn it_rejects_duplicate_message_ids() {
+        let mut dispatcher = Dispatcher::<Game>::new();
+        dispatcher.register::<Chat>().unwrap();
+
+        assert!(matches!(dispatcher.register::<AlsoOne>(), Err(SerializationError::DuplicateMessageId { id: 1 })));
+        assert!(dispatcher.is_registered(1));
+    }
+}
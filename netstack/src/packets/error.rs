@@ -0,0 +1,22 @@
+#[derive(Debug, Fail)]
+pub enum PacketError {
+    #[fail(display = "Packet of {} bytes is shorter than the header", length)]
+    TooShort {
+        length: usize,
+    },
+
+    #[fail(display = "Body length of {} exceeds the {} bytes received after the header", body_length, received)]
+    BodyLengthTooLarge {
+        body_length: usize,
+        received: usize,
+    },
+
+    #[fail(display = "Packet uses version {} of the protocol, expected version {}", found, expected)]
+    VersionMismatch {
+        expected: u8,
+        found: u8,
+    },
+
+    #[fail(display = "Packet signature is invalid, it was tampered with or signed with another secret or protocol id")]
+    InvalidSignature,
+}
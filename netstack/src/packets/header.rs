@@ -1,5 +1,8 @@
 pub const HEADER_SIZE: usize = std::mem::size_of::<Header>();
 
+/// The number of bytes left for messages in a packet of `DEFAULT_MTU` bytes, 1444 with the 56 byte header.
+pub const MAX_PAYLOAD_SIZE: usize = super::DEFAULT_MTU - HEADER_SIZE;
+
 #[repr(C, packed)]
 pub struct Header {
     pub hmac: [u8; 32],
@@ -7,6 +10,6 @@ pub struct Header {
     pub ack_sequence_number: u64,
     pub ack_bits: [u8; 4],
     pub packet_type: u8,
-    pub padding: u8,
+    pub version: u8,
     pub body_length: u16,
 }
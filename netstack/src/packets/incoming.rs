@@ -30,24 +30,31 @@ impl IncomingPacket {
         PacketType::from_u8(self.buffer.get_header().packet_type)
     }
 
+    pub fn get_version(&self) -> u8 {
+        self.buffer.get_header().version
+    }
+
     pub fn get_body_length(&self) -> u16 {
         self.buffer.get_header().body_length
     }
 
+    /// Returns how many bytes of the body have not been read yet.
+    pub fn remaining(&self) -> usize {
+        HEADER_SIZE + self.get_body_length() as usize - self.bytes_read
+    }
+
     pub fn into_payload(self) -> Payload {
         Payload::from_raw_packet(self.buffer)
     }
 }
 
+/// Reads never go past the end of the body, a read returns fewer bytes than requested near the end
+/// and `0` once the whole body has been read.
 impl Read for IncomingPacket {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
-        let len = buf.len();
-
-        if self.bytes_read + len > self.buffer.get_buffer().len() {
-            panic!("TODO: cannot read beyond buffer");
-        }
+        let len = buf.len().min(self.remaining());
 
-        buf.copy_from_slice(&self.buffer.get_buffer()[self.bytes_read..self.bytes_read + len]);
+        buf[0..len].copy_from_slice(&self.buffer.get_buffer()[self.bytes_read..self.bytes_read + len]);
         self.bytes_read += len;
 
         Ok(len)
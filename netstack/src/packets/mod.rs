@@ -3,12 +3,18 @@ mod header;
 mod incoming;
 mod outgoing;
 mod payload;
+mod pool;
+mod protocol;
+mod error;
 
 pub use raw::*;
 pub use header::*;
 pub use incoming::*;
 pub use outgoing::*;
 pub use payload::*;
+pub use pool::*;
+pub use protocol::*;
+pub use error::PacketError;
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum PacketType {
@@ -17,6 +23,9 @@ pub enum PacketType {
     Heartbeat,
     Disconnect,
     Disconnected,
+    Fragment,
+    Aggregate,
+    Rejected,
 }
 
 impl PacketType {
@@ -27,6 +36,9 @@ impl PacketType {
             2 => Some(Self::Heartbeat),
             3 => Some(Self::Disconnect),
             4 => Some(Self::Disconnected),
+            5 => Some(Self::Fragment),
+            6 => Some(Self::Aggregate),
+            7 => Some(Self::Rejected),
             _ => None,
         }
     }
@@ -38,6 +50,9 @@ impl PacketType {
             Self::Heartbeat => 2,
             Self::Disconnect => 3,
             Self::Disconnected => 4,
+            Self::Fragment => 5,
+            Self::Aggregate => 6,
+            Self::Rejected => 7,
         }
     }
 }
@@ -60,9 +75,9 @@ mod tests {
 
         outgoing.write(&[0x1, 0x2, 0x3, 0x4, 0x5, 0x6]).expect("It writes into the buffer");
 
-        let buffer = outgoing.write_header_and_sign(15, 12, [0x3, 0x2, 0x1, 0x0], PacketType::Payload.to_u8(), &secret);
+        let buffer = outgoing.write_header_and_sign(15, 12, [0x3, 0x2, 0x1, 0x0], PacketType::Payload.to_u8(), &secret, Protocol::new(7));
 
-        let mut incoming = buffer.verify(&secret).expect("The verification succeeds");
+        let mut incoming = buffer.verify(&secret, Protocol::new(7)).expect("The verification succeeds");
 
         let mut read_into: [u8; 6] = [0; 6];
         incoming.read(&mut read_into).expect("It reads into the buffer");
@@ -72,6 +87,7 @@ mod tests {
         assert_eq!(incoming.get_ack_sequence_number(), 12);
         assert_eq!(incoming.get_ack_bits(), [0x3, 0x2, 0x1, 0x0]);
         assert_eq!(incoming.get_packet_type(), Some(PacketType::Payload));
+        assert_eq!(incoming.get_version(), PROTOCOL_VERSION);
         assert_eq!(incoming.get_body_length(), 6);
     }
 
@@ -89,11 +105,11 @@ mod tests {
 
         outgoing.write(&[0x1, 0x2, 0x3, 0x4, 0x5, 0x6]).expect("It writes into the buffer");
 
-        let mut buffer = outgoing.write_header_and_sign(0, 1, [0x0, 0x0, 0x0, 0x0], 1, &secret);
+        let mut buffer = outgoing.write_header_and_sign(0, 1, [0x0, 0x0, 0x0, 0x0], 1, &secret, Protocol::new(7));
 
         buffer.get_buffer_mut()[56] = 0x2;
 
-        assert!(buffer.verify(&secret).is_none(), "The packet is invalid");
+        assert!(buffer.verify(&secret, Protocol::new(7)).is_err(), "The packet is invalid");
     }
 
     #[test]
@@ -110,7 +126,7 @@ mod tests {
 
         outgoing.write(&[0x1, 0x2, 0x3, 0x4, 0x5, 0x6]).expect("It writes into the buffer");
 
-        let buffer = outgoing.write_header_and_sign(0, 1, [0x0, 0x0, 0x0, 0x0], 1, &secret);
+        let buffer = outgoing.write_header_and_sign(0, 1, [0x0, 0x0, 0x0, 0x0], 1, &secret, Protocol::new(7));
 
         let secret = Secret::from_bytes([
             0x5, 0x1, 0x2, 0x4, 0x8, 0x24, 0x2, 0x1,
@@ -118,6 +134,180 @@ mod tests {
             0x8, 0x24, 0x2, 0x1, 0x2, 0x4, 0x8, 0x24,
             0x2, 0x1, 0x2, 0x4, 0x8, 0x24, 0x0, 0x64]);
 
-        assert!(buffer.verify(&secret).is_none(), "The packet is invalid");
+        assert!(buffer.verify(&secret, Protocol::new(7)).is_err(), "The packet is invalid");
+    }
+
+    #[test]
+    fn it_fails_to_write_beyond_the_packet() {
+        use std::io::{ErrorKind, Write};
+
+        let mut outgoing = OutgoingPacket::new();
+        assert_eq!(outgoing.remaining_capacity(), 1500 - HEADER_SIZE);
+
+        outgoing.write_all(&[0x1; 1500 - HEADER_SIZE - 4]).expect("It writes into the buffer");
+        assert_eq!(outgoing.remaining_capacity(), 4);
+
+        let error = outgoing.write(&[0x2; 5]).expect_err("The write does not fit");
+        assert_eq!(error.kind(), ErrorKind::WriteZero);
+        assert_eq!(outgoing.remaining_capacity(), 4, "A failed write leaves the packet unchanged");
+
+        outgoing.write_all(&[0x3; 4]).expect("The remaining bytes can still be written");
+        assert_eq!(outgoing.write_all(&[0x4]).unwrap_err().kind(), ErrorKind::WriteZero);
+        assert_eq!(outgoing.write(&[]).unwrap(), 0);
+    }
+
+    #[test]
+    fn it_stops_reading_at_the_end_of_the_body() {
+        use std::io::{Read, Write};
+        use crate::security::Secret;
+
+        let secret = Secret::from_bytes([0x7; 32]);
+        let mut outgoing = OutgoingPacket::new();
+        outgoing.write_all(&[0x1, 0x2, 0x3, 0x4, 0x5, 0x6]).expect("It writes into the buffer");
+
+        let packet = outgoing.write_header_and_sign(1, 0, [0x0; 4], PacketType::Payload.to_u8(), &secret, Protocol::new(7));
+        let mut buffer = PacketBuffer::unpooled(DEFAULT_MTU);
+        buffer[0..HEADER_SIZE + 6].copy_from_slice(packet.get_buffer());
+        // unsigned bytes after the body are ignored
+        buffer[HEADER_SIZE + 6..HEADER_SIZE + 10].copy_from_slice(&[0xff; 4]);
+        let mut incoming = RawPacket::new(buffer, HEADER_SIZE + 10).verify(&secret, Protocol::new(7)).expect("The verification succeeds");
+
+        let mut read_into = [0; 4];
+        assert_eq!(incoming.read(&mut read_into).unwrap(), 4);
+        assert_eq!(incoming.remaining(), 2);
+        assert_eq!(incoming.read(&mut read_into).unwrap(), 2, "It returns a short read at the end of the body");
+        assert_eq!(read_into[0..2], [0x5, 0x6]);
+        assert_eq!(incoming.read(&mut read_into).unwrap(), 0);
+
+        assert_eq!(incoming.into_payload().get_buffer(), &[0x1, 0x2, 0x3, 0x4, 0x5, 0x6]);
+    }
+
+    #[test]
+    fn it_rejects_a_body_length_beyond_the_received_bytes() {
+        use std::io::Write;
+        use crate::security::Secret;
+
+        let secret = Secret::from_bytes([0x7; 32]);
+        let mut outgoing = OutgoingPacket::new();
+        outgoing.write_all(&[0x1, 0x2, 0x3]).expect("It writes into the buffer");
+
+        let mut packet = outgoing.write_header_and_sign(1, 0, [0x0; 4], PacketType::Payload.to_u8(), &secret, Protocol::new(7));
+        packet.get_header_mut().body_length = u16::MAX;
+
+        assert!(packet.verify(&secret, Protocol::new(7)).is_err(), "The packet is invalid");
+    }
+
+    #[test]
+    fn it_rejects_a_packet_of_another_protocol() {
+        use crate::security::Secret;
+
+        let secret = Secret::from_bytes([0x7; 32]);
+        let packet = OutgoingPacket::new().write_header_and_sign(1, 0, [0x0; 4], PacketType::Payload.to_u8(), &secret, Protocol::new(7));
+
+        assert!(matches!(packet.verify(&secret, Protocol::new(8)), Err(PacketError::InvalidSignature)));
+    }
+
+    #[test]
+    fn it_rejects_a_packet_of_another_version() {
+        use crate::security::Secret;
+
+        let secret = Secret::from_bytes([0x7; 32]);
+        let protocol = Protocol { id: 7, version: PROTOCOL_VERSION + 1 };
+        let packet = OutgoingPacket::new().write_header_and_sign(1, 0, [0x0; 4], PacketType::Payload.to_u8(), &secret, protocol);
+
+        let error = packet.verify(&secret, Protocol::new(7)).err().expect("The packet is invalid");
+        assert!(matches!(error, PacketError::VersionMismatch { expected: PROTOCOL_VERSION, found } if found == PROTOCOL_VERSION + 1));
+    }
+
+    #[test]
+    fn it_serializes_and_deserializes_a_message() {
+        use crate::security::Secret;
+
+        let secret = Secret::from_bytes([0x7; 32]);
+        let mut outgoing = OutgoingPacket::new();
+
+        outgoing.serialize(&(15u32, "Hello".to_owned())).expect("It serializes the message");
+
+        let buffer = outgoing.write_header_and_sign(1, 0, [0x0; 4], PacketType::Payload.to_u8(), &secret, Protocol::new(7));
+        let payload = buffer.verify(&secret, Protocol::new(7)).expect("The verification succeeds").into_payload();
+
+        assert_eq!(payload.deserialize::<(u32, String)>().unwrap(), (15, "Hello".to_owned()));
+    }
+
+    #[test]
+    fn it_serializes_a_message_for_an_older_version() {
+        use crate::messages::Message;
+        use crate::security::Secret;
+        use crate::serialization::{Deserialize, Deserializer, Serialize, Serializer, SerializationError};
+
+        #[derive(Debug, PartialEq)]
+        struct Join {
+            name: String,
+            locale: String,
+        }
+
+        // written like a derived message with `#[netstack(since = 2)]` on the locale
+        impl Serialize for Join {
+            fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+                serializer.serialize_string(&self.name)?;
+                if serializer.get_version() >= 2 {
+                    serializer.serialize_string(&self.locale)?;
+                }
+                Ok(())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for Join {
+            type Item = Join;
+
+            fn deserialize(deserializer: &mut impl Deserializer<'de>) -> Result<Self::Item, SerializationError> {
+                let name = deserializer.deserialize_string()?;
+                let locale = if deserializer.get_version() >= 2 { deserializer.deserialize_string()? } else { String::new() };
+                Ok(Join { name, locale })
+            }
+        }
+
+        impl Message for Join {
+            const ID: u32 = 7;
+        }
+
+        let secret = Secret::from_bytes([0x7; 32]);
+        let join = Join { name: "Ferris".to_owned(), locale: "de".to_owned() };
+
+        let mut outgoing = OutgoingPacket::new();
+        outgoing.serialize_message_versioned(&join, 1).expect("It serializes the message");
+
+        let buffer = outgoing.write_header_and_sign(1, 0, [0x0; 4], PacketType::Payload.to_u8(), &secret, Protocol::new(7));
+        let payload = buffer.verify(&secret, Protocol::new(7)).expect("The verification succeeds").into_payload();
+
+        assert_eq!(payload.get_buffer().len(), 1 + 7, "The id and the name, without the locale");
+        assert_eq!(payload.deserialize_message_versioned::<Join>(1).unwrap(), Join { locale: String::new(), ..join });
+    }
+
+    #[test]
+    fn it_splits_a_payload_without_copying_it() {
+        let payload = Payload::from_bytes(vec![0x1, 0x2, 0x3, 0x4, 0x5]);
+        let mut parts = payload.split(vec![0..2, 2..2, 2..5]);
+
+        assert_eq!(parts[0].get_buffer(), &[0x1, 0x2]);
+        assert!(parts[1].get_buffer().is_empty());
+        assert_eq!(parts[2].get_buffer(), &[0x3, 0x4, 0x5]);
+        assert_eq!(parts[2].get_buffer().as_ptr(), parts[0].get_buffer().as_ptr().wrapping_add(2), "The parts share the buffer");
+
+        parts[0].get_buffer_mut()[0] = 0x9;
+        assert_eq!(parts[0].get_buffer(), &[0x9, 0x2], "A changed part gets its own copy");
+        assert_eq!(parts[2].get_buffer(), &[0x3, 0x4, 0x5]);
+    }
+
+    #[test]
+    fn it_rejects_a_message_larger_than_the_packet() {
+        use crate::serialization::SerializationError;
+
+        let mut outgoing = OutgoingPacket::new();
+        let message = vec![0u8; 1500 - HEADER_SIZE];
+
+        assert!(matches!(outgoing.serialize(&message), Err(SerializationError::BufferFull)));
+
+        outgoing.serialize(&vec![0u8; 1500 - HEADER_SIZE - 2]).expect("A message that fits can still be written");
     }
 }
@@ -1,25 +1,68 @@
 use sha2::Sha256;
 use hmac::{Hmac, Mac};
 use std::io::{self, Write};
-use super::{RawPacket, HEADER_SIZE};
+use super::{PacketBuffer, Protocol, RawPacket, DEFAULT_MTU, HEADER_SIZE};
 use crate::security::Secret;
+use crate::messages::{Message, serialize_message};
+use crate::serialization::{Serialize, SerializationError, Writer, LATEST_VERSION};
 
 type HmacSha256 = Hmac<Sha256>;
 
 pub struct OutgoingPacket {
-    buffer: [u8; 1500],
+    buffer: PacketBuffer,
     bytes_written: usize,
 }
 
 impl OutgoingPacket {
+    /// Creates a packet with a buffer of `DEFAULT_MTU` bytes,
+    /// `Server::create_packet` and `Client::create_packet` reuse buffers instead.
     pub fn new() -> Self {
+        Self::with_buffer(PacketBuffer::unpooled(DEFAULT_MTU))
+    }
+
+    /// Creates a packet writing into the given buffer, usually one from a `PacketPool`.
+    pub fn with_buffer(buffer: PacketBuffer) -> Self {
         Self {
-            buffer: [0; 1500],
+            buffer,
             bytes_written: HEADER_SIZE,
         }
     }
 
-    pub(crate) fn write_header_and_sign(self, sequence_number: u64, ack_sequence_number: u64, ack_bits: [u8; 4], packet_type: u8, secret: &Secret) -> RawPacket {
+    /// Returns how many more bytes fit into the packet.
+    pub fn remaining_capacity(&self) -> usize {
+        self.buffer.len() - self.bytes_written
+    }
+
+    /// Serializes a message into the remaining space of the packet.
+    /// Fails with `SerializationError::BufferFull` if it doesn't fit, in which case nothing is written.
+    pub fn serialize(&mut self, message: &impl Serialize) -> Result<(), SerializationError> {
+        self.serialize_versioned(message, LATEST_VERSION)
+    }
+
+    /// Serializes a message for a peer using the given protocol version.
+    pub fn serialize_versioned(&mut self, message: &impl Serialize, version: u32) -> Result<(), SerializationError> {
+        let mut writer = Writer::new(&mut self.buffer[self.bytes_written..]).with_version(version);
+        message.serialize(&mut writer)?;
+        self.bytes_written += writer.get_position();
+
+        Ok(())
+    }
+
+    /// Serializes a message preceded by its id, so the receiver can pick the type with a `Dispatcher`.
+    pub fn serialize_message<M>(&mut self, message: &M) -> Result<(), SerializationError> where M: Message {
+        self.serialize_message_versioned(message, LATEST_VERSION)
+    }
+
+    /// Like `serialize_message`, for a peer using the given protocol version.
+    pub fn serialize_message_versioned<M>(&mut self, message: &M, version: u32) -> Result<(), SerializationError> where M: Message {
+        let mut writer = Writer::new(&mut self.buffer[self.bytes_written..]).with_version(version);
+        serialize_message(message, &mut writer)?;
+        self.bytes_written += writer.get_position();
+
+        Ok(())
+    }
+
+    pub(crate) fn write_header_and_sign(self, sequence_number: u64, ack_sequence_number: u64, ack_bits: [u8; 4], packet_type: u8, secret: &Secret, protocol: Protocol) -> RawPacket {
         let bytes_written = self.bytes_written;
         let mut packet = RawPacket::new(self.buffer, bytes_written);
         let mut header = packet.get_header_mut();
@@ -28,9 +71,11 @@ impl OutgoingPacket {
         header.ack_sequence_number = ack_sequence_number;
         header.ack_bits = ack_bits;
         header.packet_type = packet_type;
+        header.version = protocol.version;
         header.body_length = (bytes_written - HEADER_SIZE) as u16;
 
         let mut mac = HmacSha256::new_varkey(secret.get_bytes()).expect("HmacSha256 can take a key of any size");
+        mac.input(&protocol.id.to_le_bytes());
         mac.input(&packet.get_buffer()[32..bytes_written]);
 
         let mut header = packet.get_header_mut();
@@ -40,11 +85,16 @@ impl OutgoingPacket {
     }
 }
 
+/// Writes are all or nothing, if `buf` doesn't fit into the remaining capacity nothing is written
+/// and the write fails with `io::ErrorKind::WriteZero`.
 impl Write for OutgoingPacket {
     fn write(&mut self, buf: &[u8]) -> Result<usize, std::io::Error> {
         let len = buf.len();
-        if self.bytes_written + len > self.buffer.len() {
-            panic!("TODO: cannot write, would exceed buffer");
+        if len > self.remaining_capacity() {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                format!("cannot write {} bytes, the packet has {} bytes left", len, self.remaining_capacity()),
+            ));
         }
 
         self.buffer[self.bytes_written..self.bytes_written + len].copy_from_slice(buf);
@@ -1,21 +1,99 @@
+use std::ops::Range;
+use std::sync::Arc;
 use super::RawPacket;
+use crate::messages::Message;
+use crate::serialization::{Deserialize, Deserializer, SerializationError, Reader, LATEST_VERSION};
+
+enum Body {
+    Packet(RawPacket),
+    Owned(Vec<u8>),
+    /// A part of a payload shared with the other messages of an aggregate packet.
+    Slice(Arc<Payload>, Range<usize>),
+}
 
 pub struct Payload {
-    buffer: RawPacket,
+    body: Body,
 }
 
 impl Payload {
     pub fn from_raw_packet(buffer: RawPacket) -> Self {
         Self {
-            buffer,
+            body: Body::Packet(buffer),
         }
     }
 
+    /// Creates a payload owning its bytes, for messages that were reassembled from fragments.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self {
+            body: Body::Owned(bytes),
+        }
+    }
+
+    /// Splits the payload into one payload per range of its buffer without copying,
+    /// for the messages of an aggregate packet.
+    pub fn split(self, ranges: Vec<Range<usize>>) -> Vec<Payload> {
+        let shared = Arc::new(self);
+
+        ranges.into_iter()
+            .map(|range| Self {
+                body: Body::Slice(shared.clone(), range),
+            })
+            .collect()
+    }
+
     pub fn get_buffer(&self) -> &[u8] {
-        self.buffer.get_body()
+        match &self.body {
+            Body::Packet(packet) => packet.get_body(),
+            Body::Owned(bytes) => bytes,
+            Body::Slice(payload, range) => &payload.get_buffer()[range.clone()],
+        }
     }
 
+    /// Returns the buffer for changes, a payload sharing its buffer copies its part first.
     pub fn get_buffer_mut(&mut self) -> &mut [u8] {
-        self.buffer.get_body_mut()
+        if let Body::Slice(payload, range) = &self.body {
+            self.body = Body::Owned(payload.get_buffer()[range.clone()].to_vec());
+        }
+
+        match &mut self.body {
+            Body::Packet(packet) => packet.get_body_mut(),
+            Body::Owned(bytes) => bytes,
+            Body::Slice(..) => unreachable!("shared payloads were copied"),
+        }
+    }
+
+    /// Deserializes a message from the start of the payload.
+    /// Messages can borrow strings and byte slices from the payload instead of allocating.
+    pub fn deserialize<'a, T>(&'a self) -> Result<T, SerializationError> where T: Deserialize<'a, Item = T> {
+        self.deserialize_versioned(LATEST_VERSION)
+    }
+
+    /// Deserializes a message that was written by a peer using the given protocol version.
+    pub fn deserialize_versioned<'a, T>(&'a self, version: u32) -> Result<T, SerializationError> where T: Deserialize<'a, Item = T> {
+        let mut reader = Reader::new(self.get_buffer()).with_version(version);
+        T::deserialize(&mut reader)
+    }
+
+    /// Reads the id of the message in the payload, see `Message`.
+    pub fn get_message_id(&self) -> Result<u32, SerializationError> {
+        Reader::new(self.get_buffer()).deserialize_u32()
+    }
+
+    /// Deserializes a message written with `OutgoingPacket::serialize_message`.
+    /// Fails with `SerializationError::UnexpectedMessageId` if the payload contains another message type.
+    pub fn deserialize_message<'a, M>(&'a self) -> Result<M, SerializationError> where M: Message + Deserialize<'a, Item = M> {
+        self.deserialize_message_versioned(LATEST_VERSION)
+    }
+
+    /// Like `deserialize_message`, for a message written by a peer using the given protocol version.
+    pub fn deserialize_message_versioned<'a, M>(&'a self, version: u32) -> Result<M, SerializationError> where M: Message + Deserialize<'a, Item = M> {
+        let mut reader = Reader::new(self.get_buffer()).with_version(version);
+        let id = reader.deserialize_u32()?;
+
+        if id != M::ID {
+            return Err(SerializationError::UnexpectedMessageId { expected: M::ID, found: id });
+        }
+
+        M::deserialize(&mut reader)
     }
 }
@@ -0,0 +1,168 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use super::HEADER_SIZE;
+
+/// The size of packet buffers if not configured otherwise, the MTU of ethernet.
+pub const DEFAULT_MTU: usize = 1500;
+
+/// How many unused buffers a pool keeps around if not configured otherwise.
+pub const DEFAULT_POOL_CAPACITY: usize = 256;
+
+struct Buffers {
+    mtu: usize,
+    capacity: usize,
+    free: Mutex<Vec<Box<[u8]>>>,
+}
+
+/// Hands out packet buffers of `mtu` bytes and takes them back once they are dropped.
+///
+/// Buffers are heap allocated and only a pointer is moved from the transport into `RawPacket`,
+/// `IncomingPacket` and `Payload`, so receiving and sending packets doesn't copy them around.
+/// Cloning a pool is cheap, clones share their buffers.
+#[derive(Clone)]
+pub struct PacketPool {
+    buffers: Arc<Buffers>,
+}
+
+impl PacketPool {
+    pub fn new(mtu: usize) -> Self {
+        Self::with_capacity(mtu, DEFAULT_POOL_CAPACITY)
+    }
+
+    /// Creates a pool keeping at most `capacity` unused buffers, more are freed when they are dropped.
+    ///
+    /// # Panics
+    ///
+    /// If `mtu` is smaller than `HEADER_SIZE` or larger than a UDP datagram.
+    pub fn with_capacity(mtu: usize, capacity: usize) -> Self {
+        assert!(mtu >= HEADER_SIZE, "The MTU must be at least the {} bytes of the header", HEADER_SIZE);
+        assert!(mtu <= u16::MAX as usize, "The MTU must fit into a UDP datagram");
+
+        Self {
+            buffers: Arc::new(Buffers {
+                mtu,
+                capacity,
+                free: Mutex::new(Vec::with_capacity(capacity)),
+            }),
+        }
+    }
+
+    pub fn get_mtu(&self) -> usize {
+        self.buffers.mtu
+    }
+
+    /// Returns the number of unused buffers waiting to be handed out again.
+    pub fn get_free_count(&self) -> usize {
+        self.buffers.free.lock().expect("The pool is not poisoned").len()
+    }
+
+    /// Returns an unused buffer or allocates a new one.
+    /// Reused buffers are not cleared, packets only ever expose the bytes that were written to them.
+    pub fn acquire(&self) -> PacketBuffer {
+        let bytes = self.buffers.free.lock().expect("The pool is not poisoned").pop();
+
+        PacketBuffer {
+            bytes: bytes.unwrap_or_else(|| vec![0; self.buffers.mtu].into_boxed_slice()),
+            pool: Some(self.buffers.clone()),
+        }
+    }
+}
+
+impl Default for PacketPool {
+    fn default() -> Self {
+        Self::new(DEFAULT_MTU)
+    }
+}
+
+/// A buffer for a single packet, it goes back to its `PacketPool` when dropped.
+pub struct PacketBuffer {
+    bytes: Box<[u8]>,
+    pool: Option<Arc<Buffers>>,
+}
+
+impl PacketBuffer {
+    /// Allocates a buffer that doesn't belong to a pool.
+    pub fn unpooled(mtu: usize) -> Self {
+        assert!(mtu >= HEADER_SIZE, "The MTU must be at least the {} bytes of the header", HEADER_SIZE);
+
+        Self {
+            bytes: vec![0; mtu].into_boxed_slice(),
+            pool: None,
+        }
+    }
+}
+
+impl Deref for PacketBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl DerefMut for PacketBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+}
+
+impl Drop for PacketBuffer {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            let mut free = pool.free.lock().expect("The pool is not poisoned");
+
+            if free.len() < pool.capacity {
+                free.push(std::mem::take(&mut self.bytes));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reuses_dropped_buffers() {
+        let pool = PacketPool::new(DEFAULT_MTU);
+
+        let mut buffer = pool.acquire();
+        assert_eq!(buffer.len(), DEFAULT_MTU);
+        buffer[0] = 0x7;
+        let address = buffer.as_ptr();
+        drop(buffer);
+
+        assert_eq!(pool.get_free_count(), 1);
+
+        let buffer = pool.acquire();
+        assert_eq!(buffer.as_ptr(), address, "The buffer is reused");
+        assert_eq!(pool.get_free_count(), 0);
+    }
+
+    #[test]
+    fn it_keeps_at_most_capacity_buffers() {
+        let pool = PacketPool::with_capacity(512, 2);
+        let buffers: Vec<_> = (0..4).map(|_| pool.acquire()).collect();
+
+        assert!(buffers.iter().all(|buffer| buffer.len() == 512));
+        drop(buffers);
+
+        assert_eq!(pool.get_free_count(), 2);
+    }
+
+    #[test]
+    fn it_shares_buffers_between_clones() {
+        let pool = PacketPool::default();
+        let clone = pool.clone();
+
+        drop(clone.acquire());
+
+        assert_eq!(pool.get_free_count(), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn it_rejects_an_mtu_smaller_than_the_header() {
+        PacketPool::new(HEADER_SIZE - 1);
+    }
+}
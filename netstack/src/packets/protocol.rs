@@ -0,0 +1,24 @@
+/// The version of the packet layout, sent in the header of every packet.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Identifies what both ends of a connection speak.
+///
+/// The `id` is chosen by the application and mixed into the signature of every packet, so packets of
+/// another game or another build are rejected even if they use the same secret. It is only sent in plain
+/// text in connection packets and in the server's rejection, so a client of another protocol learns why
+/// it can't connect. The `version` is written into the header and changes with the packet layout of netstack.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct Protocol {
+    pub id: u64,
+    pub version: u8,
+}
+
+impl Protocol {
+    /// Creates the protocol with the given id in the `PROTOCOL_VERSION` of this crate.
+    pub fn new(id: u64) -> Self {
+        Self {
+            id,
+            version: PROTOCOL_VERSION,
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use super::{Header, HEADER_SIZE, IncomingPacket};
+use super::{Header, HEADER_SIZE, IncomingPacket, PacketBuffer, PacketError, Protocol};
 use crate::security::Secret;
 
 use sha2::Sha256;
@@ -6,32 +6,27 @@ use hmac::{Hmac, Mac};
 type HmacSha256 = Hmac<Sha256>;
 
 pub struct RawPacket {
-    buffer: [u8; 1500],
+    buffer: PacketBuffer,
     length: usize,
 }
 
 impl RawPacket {
-    pub fn new(buffer: [u8; 1500], length: usize) -> Self {
+    pub fn new(buffer: PacketBuffer, length: usize) -> Self {
+        assert!(length <= buffer.len(), "The packet cannot be longer than its buffer");
+
         Self {
             buffer,
             length,
         }
     }
 
-    pub fn get_header<'a>(&'a self) -> &'a Header {
-        use std::mem::transmute;
-
-        let ptr = &self.buffer as *const _;
-        let reference = unsafe { transmute(ptr) };
-        reference
+    pub fn get_header(&self) -> &Header {
+        // the header is packed, so it can be read from any address
+        unsafe { &*(self.buffer.as_ptr() as *const Header) }
     }
 
-    pub fn get_header_mut<'a>(&'a mut self) -> &'a mut Header {
-        use std::mem::transmute;
-
-        let ptr = &mut self.buffer as *mut _;
-        let reference = unsafe { transmute(ptr) };
-        reference
+    pub fn get_header_mut(&mut self) -> &mut Header {
+        unsafe { &mut *(self.buffer.as_mut_ptr() as *mut Header) }
     }
 
     pub fn get_buffer(&self) -> &[u8] {
@@ -50,21 +45,33 @@ impl RawPacket {
         &mut self.buffer[HEADER_SIZE..self.length]
     }
 
-    pub fn verify(self, secret: &Secret) -> Option<IncomingPacket> {
+    /// Checks that the packet was signed with the secret and belongs to the protocol.
+    /// The version is checked first, since packets of another version may well use a different layout.
+    pub fn verify(mut self, secret: &Secret, protocol: Protocol) -> Result<IncomingPacket, PacketError> {
         if self.length < HEADER_SIZE {
-            return None;
+            return Err(PacketError::TooShort { length: self.length });
+        }
+
+        let version = self.get_header().version;
+        if version != protocol.version {
+            return Err(PacketError::VersionMismatch { expected: protocol.version, found: version });
         }
 
-        let mut mac = HmacSha256::new_varkey(secret.get_bytes()).expect("HmacSha256 can take a key of any size");
         let body_length = self.get_header().body_length as usize;
+        if HEADER_SIZE + body_length > self.length {
+            return Err(PacketError::BodyLengthTooLarge { body_length, received: self.length - HEADER_SIZE });
+        }
 
+        let mut mac = HmacSha256::new_varkey(secret.get_bytes()).expect("HmacSha256 can take a key of any size");
+        mac.input(&protocol.id.to_le_bytes());
         mac.input(&self.buffer[32..HEADER_SIZE + body_length]);
-        let is_valid = mac.verify(&self.buffer[0..32]).is_ok();
 
-        if is_valid {
-            Some(IncomingPacket::from_raw_packet(self))
-        } else {
-            None
+        if mac.verify(&self.buffer[0..32]).is_err() {
+            return Err(PacketError::InvalidSignature);
         }
+
+        // trailing bytes aren't covered by the signature, so they are cut off
+        self.length = HEADER_SIZE + body_length;
+        Ok(IncomingPacket::from_raw_packet(self))
     }
 }
@@ -0,0 +1,122 @@
+use std::collections::{BTreeMap, HashMap};
+use super::{Registry, Schema, Type};
+
+macro_rules! impl_schema {
+    ($t:ty, $schema:ident) => {
+        impl Schema for $t {
+            fn get_type() -> Type {
+                Type::$schema
+            }
+        }
+    }
+}
+
+impl_schema!(bool, Bool);
+impl_schema!(u8, U8);
+impl_schema!(i8, I8);
+impl_schema!(u16, U16);
+impl_schema!(i16, I16);
+impl_schema!(u32, U32);
+impl_schema!(i32, I32);
+impl_schema!(u64, U64);
+impl_schema!(i64, I64);
+impl_schema!(f32, F32);
+impl_schema!(f64, F64);
+impl_schema!(String, String);
+impl_schema!(str, String);
+impl_schema!([u8], Bytes);
+
+impl<T> Schema for &T where T: Schema + ?Sized {
+    fn get_type() -> Type {
+        T::get_type()
+    }
+
+    fn register(registry: &mut Registry) {
+        T::register(registry)
+    }
+}
+
+impl<T> Schema for Box<T> where T: Schema {
+    fn get_type() -> Type {
+        T::get_type()
+    }
+
+    fn register(registry: &mut Registry) {
+        T::register(registry)
+    }
+}
+
+impl<T> Schema for Option<T> where T: Schema {
+    fn get_type() -> Type {
+        Type::Option(Box::new(T::get_type()))
+    }
+
+    fn register(registry: &mut Registry) {
+        T::register(registry)
+    }
+}
+
+impl<T> Schema for Vec<T> where T: Schema {
+    fn get_type() -> Type {
+        Type::Vec(Box::new(T::get_type()))
+    }
+
+    fn register(registry: &mut Registry) {
+        T::register(registry)
+    }
+}
+
+impl<T, const N: usize> Schema for [T; N] where T: Schema {
+    fn get_type() -> Type {
+        Type::Array(Box::new(T::get_type()), N)
+    }
+
+    fn register(registry: &mut Registry) {
+        T::register(registry)
+    }
+}
+
+impl<K, V, S> Schema for HashMap<K, V, S> where K: Schema, V: Schema {
+    fn get_type() -> Type {
+        Type::Map(Box::new(K::get_type()), Box::new(V::get_type()))
+    }
+
+    fn register(registry: &mut Registry) {
+        K::register(registry);
+        V::register(registry);
+    }
+}
+
+impl<K, V> Schema for BTreeMap<K, V> where K: Schema, V: Schema {
+    fn get_type() -> Type {
+        Type::Map(Box::new(K::get_type()), Box::new(V::get_type()))
+    }
+
+    fn register(registry: &mut Registry) {
+        K::register(registry);
+        V::register(registry);
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($t:ident),+) => {
+        impl<$($t),+> Schema for ($($t,)+) where $($t: Schema),+ {
+            fn get_type() -> Type {
+                Type::Tuple(vec![$($t::get_type()),+])
+            }
+
+            fn register(registry: &mut Registry) {
+                $($t::register(registry);)+
+            }
+        }
+    }
+}
+
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+impl_tuple!(A, B, C, D, E);
+impl_tuple!(A, B, C, D, E, F);
+impl_tuple!(A, B, C, D, E, F, G);
+impl_tuple!(A, B, C, D, E, F, G, H);
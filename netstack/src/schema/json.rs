@@ -0,0 +1,185 @@
+use std::fmt::{self, Write};
+use super::{Definition, DefinitionKind, Encoding, Field, Type, Variant};
+
+/// A minimal JSON document, enough to export schemas without pulling in a JSON library.
+pub(super) enum Json {
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn string(value: &str) -> Self {
+        Json::String(value.to_owned())
+    }
+
+    fn number(value: impl ToString) -> Self {
+        Json::Number(value.to_string())
+    }
+
+    fn object(entries: Vec<(&str, Json)>) -> Self {
+        Json::Object(entries.into_iter().map(|(key, value)| (key.to_owned(), value)).collect())
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, indentation: usize) -> fmt::Result {
+        match self {
+            Json::Number(number) => f.write_str(number),
+            Json::String(value) => write_string(f, value),
+            Json::Array(elements) if elements.is_empty() => f.write_str("[]"),
+            Json::Object(entries) if entries.is_empty() => f.write_str("{}"),
+            Json::Array(elements) => {
+                f.write_str("[\n")?;
+                for (index, element) in elements.iter().enumerate() {
+                    indent(f, indentation + 1)?;
+                    element.write(f, indentation + 1)?;
+                    f.write_str(if index + 1 < elements.len() { ",\n" } else { "\n" })?;
+                }
+                indent(f, indentation)?;
+                f.write_char(']')
+            },
+            Json::Object(entries) => {
+                f.write_str("{\n")?;
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    indent(f, indentation + 1)?;
+                    write_string(f, key)?;
+                    f.write_str(": ")?;
+                    value.write(f, indentation + 1)?;
+                    f.write_str(if index + 1 < entries.len() { ",\n" } else { "\n" })?;
+                }
+                indent(f, indentation)?;
+                f.write_char('}')
+            },
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+fn indent(f: &mut fmt::Formatter, indentation: usize) -> fmt::Result {
+    for _ in 0..indentation {
+        f.write_str("  ")?;
+    }
+
+    Ok(())
+}
+
+fn write_string(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    f.write_char('"')?;
+
+    for character in value.chars() {
+        match character {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            character if (character as u32) < 0x20 => write!(f, "\\u{:04x}", character as u32)?,
+            character => f.write_char(character)?,
+        }
+    }
+
+    f.write_char('"')
+}
+
+pub(super) fn definition(definition: &Definition) -> Json {
+    let mut entries = vec![("name", Json::string(&definition.name))];
+
+    match &definition.kind {
+        DefinitionKind::Struct { fields } => {
+            entries.push(("kind", Json::string("struct")));
+            if let Some(id) = definition.message_id {
+                entries.push(("message_id", Json::number(id)));
+            }
+            entries.push(("fields", Json::Array(fields.iter().map(field).collect())));
+        },
+        DefinitionKind::Enum { discriminant_bits, variants } => {
+            entries.push(("kind", Json::string("enum")));
+            if let Some(id) = definition.message_id {
+                entries.push(("message_id", Json::number(id)));
+            }
+            entries.push(("discriminant_bits", Json::number(discriminant_bits)));
+            entries.push(("variants", Json::Array(variants.iter().map(variant).collect())));
+        },
+    }
+
+    Json::object(entries)
+}
+
+fn variant(variant: &Variant) -> Json {
+    Json::object(vec![
+        ("name", Json::string(&variant.name)),
+        ("fields", Json::Array(variant.fields.iter().map(field).collect())),
+    ])
+}
+
+fn field(field: &Field) -> Json {
+    let mut entries = vec![
+        ("name", Json::string(&field.name)),
+        ("type", field_type(&field.field_type)),
+    ];
+
+    if let Some(encoding) = &field.encoding {
+        entries.push(("encoding", self::encoding(encoding)));
+    }
+
+    if let Some(since) = field.since {
+        entries.push(("since", Json::number(since)));
+    }
+
+    if let Some(until) = field.until {
+        entries.push(("until", Json::number(until)));
+    }
+
+    if let Some(max_len) = field.max_len {
+        entries.push(("max_len", Json::number(max_len)));
+    }
+
+    Json::object(entries)
+}
+
+/// Primitives are plain strings, composed types are objects with a single key.
+fn field_type(field_type: &Type) -> Json {
+    match field_type {
+        Type::Option(inner) => Json::object(vec![("option", self::field_type(inner))]),
+        Type::Vec(element) => Json::object(vec![("vec", self::field_type(element))]),
+        Type::Array(element, length) => Json::object(vec![
+            ("array", self::field_type(element)),
+            ("length", Json::number(length)),
+        ]),
+        Type::Map(key, value) => Json::object(vec![
+            ("map", Json::Array(vec![self::field_type(key), self::field_type(value)])),
+        ]),
+        Type::Tuple(elements) => Json::object(vec![("tuple", Json::Array(elements.iter().map(self::field_type).collect()))]),
+        Type::Named(name) => Json::object(vec![("ref", Json::string(name))]),
+        Type::Custom(module) => Json::object(vec![("custom", Json::string(module))]),
+        primitive => Json::String(primitive.to_string()),
+    }
+}
+
+fn encoding(encoding: &Encoding) -> Json {
+    match encoding {
+        Encoding::Bits { bits } => Json::object(vec![
+            ("bits", Json::object(vec![("bits", Json::number(bits))])),
+        ]),
+        Encoding::Range { min, max, bits } => Json::object(vec![
+            ("range", Json::object(vec![
+                ("min", Json::number(min)),
+                ("max", Json::number(max)),
+                ("bits", Json::number(bits)),
+            ])),
+        ]),
+        Encoding::Quantize { min, max, precision, bits } => Json::object(vec![
+            ("quantize", Json::object(vec![
+                ("min", Json::number(min)),
+                ("max", Json::number(max)),
+                ("precision", Json::number(precision)),
+                ("bits", Json::number(bits)),
+            ])),
+        ]),
+    }
+}
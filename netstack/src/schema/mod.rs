@@ -0,0 +1,326 @@
+//! Machine readable descriptions of message types, usually derived with `#[derive(Schema)]`.
+//!
+//! A `Registry` collects the definitions of messages and every type they contain and exports them
+//! as JSON for documentation, packet inspectors and code generators.
+
+mod impls;
+mod json;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use crate::messages::Message;
+use json::Json;
+
+/// How a value is written, either a primitive, a composition of other types or a named definition.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Bool,
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+    String,
+    Bytes,
+    Option(Box<Type>),
+    /// A length prefixed sequence.
+    Vec(Box<Type>),
+    /// A sequence of fixed length without a prefix.
+    Array(Box<Type>, usize),
+    Map(Box<Type>, Box<Type>),
+    Tuple(Vec<Type>),
+    /// A struct or enum with a `Definition` in the registry.
+    Named(String),
+    /// A field written by a custom module with `#[netstack(with = "...")]`.
+    Custom(String),
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Bool => write!(f, "bool"),
+            Type::U8 => write!(f, "u8"),
+            Type::I8 => write!(f, "i8"),
+            Type::U16 => write!(f, "u16"),
+            Type::I16 => write!(f, "i16"),
+            Type::U32 => write!(f, "u32"),
+            Type::I32 => write!(f, "i32"),
+            Type::U64 => write!(f, "u64"),
+            Type::I64 => write!(f, "i64"),
+            Type::F32 => write!(f, "f32"),
+            Type::F64 => write!(f, "f64"),
+            Type::String => write!(f, "String"),
+            Type::Bytes => write!(f, "Bytes"),
+            Type::Option(inner) => write!(f, "Option<{}>", inner),
+            Type::Vec(element) => write!(f, "Vec<{}>", element),
+            Type::Array(element, length) => write!(f, "[{}; {}]", element, length),
+            Type::Map(key, value) => write!(f, "Map<{}, {}>", key, value),
+            Type::Tuple(elements) => {
+                write!(f, "(")?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                write!(f, ")")
+            },
+            Type::Named(name) => write!(f, "{}", name),
+            Type::Custom(module) => write!(f, "with {}", module),
+        }
+    }
+}
+
+/// A compressed encoding set with a field attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Encoding {
+    Bits {
+        bits: u32,
+    },
+    Range {
+        min: i64,
+        max: i64,
+        bits: u32,
+    },
+    Quantize {
+        min: f32,
+        max: f32,
+        precision: f32,
+        bits: u32,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    /// The name of the field, or its position for tuple structs and variants.
+    pub name: String,
+    pub field_type: Type,
+    pub encoding: Option<Encoding>,
+    /// The first protocol version the field is sent in.
+    pub since: Option<u32>,
+    /// The protocol version the field was removed in.
+    pub until: Option<u32>,
+    pub max_len: Option<usize>,
+}
+
+impl Field {
+    pub fn new(name: impl Into<String>, field_type: Type) -> Self {
+        Self {
+            name: name.into(),
+            field_type,
+            encoding: None,
+            since: None,
+            until: None,
+            max_len: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub name: String,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DefinitionKind {
+    Struct {
+        fields: Vec<Field>,
+    },
+    /// Variants are written as their position with `discriminant_bits` bits, followed by their fields.
+    Enum {
+        discriminant_bits: u32,
+        variants: Vec<Variant>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    /// The id written in front of the type when it is sent as a `Message`.
+    pub message_id: Option<u32>,
+    pub kind: DefinitionKind,
+}
+
+/// Types that can describe how they are serialized.
+pub trait Schema {
+    /// Returns how the type appears in a field.
+    fn get_type() -> Type;
+
+    /// Adds the definitions of the type and every named type it contains to the registry.
+    fn register(_registry: &mut Registry) {}
+}
+
+/// Collects the definitions of message types and exports them as JSON.
+#[derive(Debug, Default)]
+pub struct Registry {
+    definitions: BTreeMap<String, Definition>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self {
+            definitions: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the definitions of `T` and every type it contains.
+    pub fn register<T>(&mut self) -> &mut Self where T: Schema + ?Sized {
+        T::register(self);
+        self
+    }
+
+    /// Adds the definitions of `M` and records the id it is sent with.
+    pub fn register_message<M>(&mut self) -> &mut Self where M: Message + Schema {
+        M::register(self);
+
+        if let Type::Named(name) = M::get_type() {
+            if let Some(definition) = self.definitions.get_mut(&name) {
+                definition.message_id = Some(M::ID);
+            }
+        }
+
+        self
+    }
+
+    /// Adds a definition, returns `false` if one with the same name was already added.
+    /// Derived implementations use this to register nested types only once.
+    pub fn add_definition(&mut self, definition: Definition) -> bool {
+        if self.definitions.contains_key(&definition.name) {
+            return false;
+        }
+
+        self.definitions.insert(definition.name.clone(), definition);
+        true
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.definitions.contains_key(name)
+    }
+
+    pub fn get_definition(&self, name: &str) -> Option<&Definition> {
+        self.definitions.get(name)
+    }
+
+    /// Returns all definitions ordered by name.
+    pub fn get_definitions(&self) -> impl Iterator<Item = &Definition> {
+        self.definitions.values()
+    }
+
+    /// Returns the definitions as a pretty printed JSON document.
+    pub fn to_json(&self) -> String {
+        let types = self.definitions.values().map(json::definition).collect();
+        Json::Object(vec![("types".to_owned(), Json::Array(types))]).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_names_types() {
+        let map = Type::Map(Box::new(Type::String), Box::new(Type::Vec(Box::new(Type::Option(Box::new(Type::U8))))));
+        assert_eq!(map.to_string(), "Map<String, Vec<Option<u8>>>");
+        assert_eq!(Type::Tuple(vec![Type::I8, Type::Array(Box::new(Type::F32), 3)]).to_string(), "(i8, [f32; 3])");
+        assert_eq!(<(u16, Option<&str>)>::get_type().to_string(), "(u16, Option<String>)");
+    }
+
+    #[test]
+    fn it_registers_definitions_once() {
+        let mut registry = Registry::new();
+        let definition = Definition {
+            name: "Ping".to_owned(),
+            message_id: None,
+            kind: DefinitionKind::Struct { fields: vec![Field::new("id", Type::U32)] },
+        };
+
+        assert!(registry.add_definition(definition.clone()));
+        assert!(!registry.add_definition(definition));
+        assert!(registry.contains("Ping"));
+        assert_eq!(registry.get_definitions().count(), 1);
+    }
+
+    #[test]
+    fn it_exports_json() {
+        let mut registry = Registry::new();
+        let mut value = Field::new("value", Type::Vec(Box::new(Type::Named("Item".to_owned()))));
+        value.since = Some(2);
+        value.max_len = Some(8);
+        let mut health = Field::new("health", Type::U8);
+        health.encoding = Some(Encoding::Range { min: 0, max: 100, bits: 7 });
+
+        registry.add_definition(Definition {
+            name: "Item".to_owned(),
+            message_id: None,
+            kind: DefinitionKind::Enum {
+                discriminant_bits: 1,
+                variants: vec![
+                    Variant { name: "Empty".to_owned(), fields: Vec::new() },
+                    Variant { name: "Health".to_owned(), fields: vec![health] },
+                ],
+            },
+        });
+        registry.add_definition(Definition {
+            name: "Bag \"1\"".to_owned(),
+            message_id: Some(7),
+            kind: DefinitionKind::Struct { fields: vec![value] },
+        });
+
+        let expected = r#"{
+  "types": [
+    {
+      "name": "Bag \"1\"",
+      "kind": "struct",
+      "message_id": 7,
+      "fields": [
+        {
+          "name": "value",
+          "type": {
+            "vec": {
+              "ref": "Item"
+            }
+          },
+          "since": 2,
+          "max_len": 8
+        }
+      ]
+    },
+    {
+      "name": "Item",
+      "kind": "enum",
+      "discriminant_bits": 1,
+      "variants": [
+        {
+          "name": "Empty",
+          "fields": []
+        },
+        {
+          "name": "Health",
+          "fields": [
+            {
+              "name": "health",
+              "type": "u8",
+              "encoding": {
+                "range": {
+                  "min": 0,
+                  "max": 100,
+                  "bits": 7
+                }
+              }
+            }
+          ]
+        }
+      ]
+    }
+  ]
+}"#;
+
+        assert_eq!(registry.to_json(), expected);
+    }
+}
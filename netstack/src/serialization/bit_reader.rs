@@ -0,0 +1,295 @@
+use std::convert::TryFrom;
+use super::{Deserializer, SerializationError, LATEST_VERSION, check_bits};
+use super::zig_zag::decode;
+
+const VARINT_MAX_ITERATIONS: usize = 10;
+
+/// A `Deserializer` for data written by a `BitWriter`.
+pub struct BitReader<'a> {
+    buffer: &'a [u8],
+    bit_position: usize,
+    version: u32,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(buffer: &'a [u8]) -> Self {
+        Self {
+            buffer,
+            bit_position: 0,
+            version: LATEST_VERSION,
+        }
+    }
+
+    /// Reads fields as they were in the given protocol version, see `Deserializer::get_version`.
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Returns the number of bits read so far.
+    pub fn get_bit_position(&self) -> usize {
+        self.bit_position
+    }
+
+    /// Returns the number of bits left to read.
+    pub fn remaining_bits(&self) -> usize {
+        self.buffer.len() * 8 - self.bit_position
+    }
+
+    fn read_bits(&mut self, bits: u32) -> Result<u64, SerializationError> {
+        if bits as usize > self.remaining_bits() {
+            return Err(SerializationError::UnexpectedEnd);
+        }
+
+        let mut value = 0;
+        let mut read = 0;
+
+        while read < bits {
+            let index = self.bit_position / 8;
+            let offset = (self.bit_position % 8) as u32;
+            let chunk = (8 - offset).min(bits - read);
+            let mask = ((1u16 << chunk) - 1) as u8;
+
+            value |= u64::from((self.buffer[index] >> offset) & mask) << read;
+
+            read += chunk;
+            self.bit_position += chunk as usize;
+        }
+
+        Ok(value)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, SerializationError> {
+        Ok(self.read_bits(8)? as u8)
+    }
+
+    fn read_var_uint(&mut self) -> Result<u64, SerializationError> {
+        let start = self.bit_position;
+
+        let mut decoded = 0;
+        let mut shift = 0;
+
+        for i in 0..VARINT_MAX_ITERATIONS {
+            let value = match self.read_u8() {
+                Ok(value) => value,
+                Err(error) => {
+                    self.bit_position = start;
+                    return Err(error);
+                },
+            };
+
+            // the last byte only has room for the single remaining bit of a u64
+            if i == VARINT_MAX_ITERATIONS - 1 && value > 0x1 {
+                self.bit_position = start;
+                return Err(SerializationError::VarUIntOverflow);
+            }
+
+            decoded |= ((value & 0x7f) as u64) << shift;
+
+            if (value & 0x80) != 0x80 {
+                return Ok(decoded);
+            }
+
+            shift += 7;
+        }
+
+        self.bit_position = start;
+        Err(SerializationError::VarUIntOverflow)
+    }
+
+    fn read_var_int(&mut self) -> Result<i64, SerializationError> {
+        let value = self.read_var_uint()?;
+        Ok(decode(value))
+    }
+}
+
+impl<'a> Deserializer<'a> for BitReader<'a> {
+    fn get_version(&self) -> u32 {
+        self.version
+    }
+
+    fn deserialize_string(&mut self) -> Result<String, SerializationError> {
+        Ok(self.deserialize_str()?.to_owned())
+    }
+
+    fn deserialize_str(&mut self) -> Result<&'a str, SerializationError> {
+        let start = self.bit_position;
+        let bytes = self.deserialize_bytes()?;
+
+        match std::str::from_utf8(bytes) {
+            Ok(value) => Ok(value),
+            Err(_) => {
+                self.bit_position = start;
+                Err(SerializationError::InvalidUtf8)
+            },
+        }
+    }
+
+    fn deserialize_bytes(&mut self) -> Result<&'a [u8], SerializationError> {
+        let start = self.bit_position;
+        let length = self.read_var_uint()?;
+
+        // byte strings start at the next full byte, see BitWriter
+        let aligned = self.bit_position.div_ceil(8);
+        let remaining = self.buffer.len().saturating_sub(aligned);
+
+        if length > remaining as u64 {
+            self.bit_position = start;
+            return Err(SerializationError::LengthTooLarge { length, remaining });
+        }
+
+        let end = aligned + length as usize;
+        self.bit_position = end * 8;
+
+        Ok(&self.buffer[aligned..end])
+    }
+
+    fn deserialize_bool(&mut self) -> Result<bool, SerializationError> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    fn deserialize_length_bounded(&mut self, min_size: usize, max: usize) -> Result<usize, SerializationError> {
+        let start = self.bit_position;
+        let length = self.read_var_uint()?;
+        let remaining = self.remaining_bits();
+
+        if length > max as u64 {
+            self.bit_position = start;
+            return Err(SerializationError::LengthExceedsMaximum { length: length as usize, max });
+        }
+
+        // elements that take up bytes in a `Reader` take up at least one bit here
+        let min_bits = min_size.min(1) as u64;
+        if length.saturating_mul(min_bits) > remaining as u64 {
+            self.bit_position = start;
+            return Err(SerializationError::LengthTooLarge { length, remaining });
+        }
+
+        Ok(length as usize)
+    }
+
+    fn deserialize_u8(&mut self) -> Result<u8, SerializationError> {
+        self.read_u8()
+    }
+
+    fn deserialize_i8(&mut self) -> Result<i8, SerializationError> {
+        Ok(self.read_u8()? as i8)
+    }
+
+    fn deserialize_u16(&mut self) -> Result<u16, SerializationError> {
+        u16::try_from(self.read_var_uint()?).map_err(|_| SerializationError::VarUIntOverflow)
+    }
+
+    fn deserialize_i16(&mut self) -> Result<i16, SerializationError> {
+        i16::try_from(self.read_var_int()?).map_err(|_| SerializationError::VarUIntOverflow)
+    }
+
+    fn deserialize_u32(&mut self) -> Result<u32, SerializationError> {
+        u32::try_from(self.read_var_uint()?).map_err(|_| SerializationError::VarUIntOverflow)
+    }
+
+    fn deserialize_i32(&mut self) -> Result<i32, SerializationError> {
+        i32::try_from(self.read_var_int()?).map_err(|_| SerializationError::VarUIntOverflow)
+    }
+
+    fn deserialize_u64(&mut self) -> Result<u64, SerializationError> {
+        self.read_var_uint()
+    }
+
+    fn deserialize_i64(&mut self) -> Result<i64, SerializationError> {
+        self.read_var_int()
+    }
+
+    fn deserialize_f32(&mut self) -> Result<f32, SerializationError> {
+        Ok(f32::from_bits(self.read_bits(32)? as u32))
+    }
+
+    fn deserialize_f64(&mut self) -> Result<f64, SerializationError> {
+        Ok(f64::from_bits(self.read_bits(64)?))
+    }
+
+    fn deserialize_bits(&mut self, bits: u32) -> Result<u64, SerializationError> {
+        check_bits(0, bits)?;
+        self.read_bits(bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_bits_lsb_first() {
+        let buffer = [0xfb, 0x3f];
+        let mut reader = BitReader::new(&buffer);
+
+        assert!(reader.deserialize_bool().unwrap());
+        assert_eq!(reader.deserialize_bits(3).unwrap(), 0b101);
+        assert_eq!(reader.deserialize_bits(10).unwrap(), 0x3ff);
+        assert_eq!(reader.remaining_bits(), 2);
+    }
+
+    #[test]
+    fn it_rejects_reads_past_the_end() {
+        let buffer = [0x00];
+        let mut reader = BitReader::new(&buffer);
+
+        reader.deserialize_bits(5).unwrap();
+
+        assert!(matches!(reader.deserialize_u8(), Err(SerializationError::UnexpectedEnd)));
+        assert!(matches!(reader.deserialize_f32(), Err(SerializationError::UnexpectedEnd)));
+        assert!(matches!(reader.deserialize_bits(4), Err(SerializationError::UnexpectedEnd)));
+        assert_eq!(reader.get_bit_position(), 5);
+    }
+
+    #[test]
+    fn it_rejects_a_truncated_var_uint() {
+        let buffer = [0xff, 0xff];
+        let mut reader = BitReader::new(&buffer);
+
+        assert!(matches!(reader.deserialize_u64(), Err(SerializationError::UnexpectedEnd)));
+        assert_eq!(reader.get_bit_position(), 0);
+    }
+
+    #[test]
+    fn it_rejects_lengths_longer_than_the_buffer() {
+        let buffer = [0x05, b'a'];
+        let mut reader = BitReader::new(&buffer);
+
+        assert!(matches!(reader.deserialize_string(), Err(SerializationError::LengthTooLarge { length: 5, remaining: 1 })));
+
+        let buffer = [0x09, 0xff];
+        let mut reader = BitReader::new(&buffer);
+
+        assert!(matches!(reader.deserialize_length(), Err(SerializationError::LengthTooLarge { length: 9, remaining: 8 })));
+    }
+
+    #[test]
+    fn it_rejects_invalid_utf8() {
+        let buffer = [0x02, 0xc3, 0x28];
+        let mut reader = BitReader::new(&buffer);
+
+        assert!(matches!(reader.deserialize_string(), Err(SerializationError::InvalidUtf8)));
+        assert_eq!(reader.get_bit_position(), 0);
+    }
+
+    #[test]
+    fn it_never_panics_on_arbitrary_input() {
+        let mut buffer = [0; 32];
+
+        for seed in 0..512u32 {
+            for (i, byte) in buffer.iter_mut().enumerate() {
+                *byte = (seed.wrapping_mul(2_654_435_761).wrapping_add(i as u32 * 40_503) >> 13) as u8;
+            }
+
+            for length in 0..buffer.len() {
+                let mut reader = BitReader::new(&buffer[0..length]);
+                let _ = reader.deserialize_bool();
+                while reader.deserialize_string().is_ok() {}
+                let _ = reader.deserialize_ranged(-7, 1000);
+                let _ = reader.deserialize_u64();
+                let _ = reader.deserialize_f64();
+            }
+        }
+    }
+}
@@ -0,0 +1,333 @@
+use super::{Serializer, SerializationError, LATEST_VERSION, check_bits, var_uint_size};
+use super::zig_zag::encode;
+
+const VARINT_MAX_BYTES: usize = 10;
+
+/// A `Serializer` that packs values at bit granularity.
+///
+/// `bool` takes up a single bit and `serialize_bits` / `serialize_ranged` write exactly as many
+/// bits as requested. All other values use the same encoding as `Writer`, without byte alignment,
+/// except for the contents of strings and byte slices which start at the next full byte.
+pub struct BitWriter<'a> {
+    buffer: &'a mut [u8],
+    bit_position: usize,
+    version: u32,
+}
+
+impl<'a> BitWriter<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self {
+            buffer,
+            bit_position: 0,
+            version: LATEST_VERSION,
+        }
+    }
+
+    /// Writes fields as they were in the given protocol version, see `Serializer::get_version`.
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Returns the number of bytes written so far, including a partially written last byte.
+    pub fn get_position(&self) -> usize {
+        self.bit_position.div_ceil(8)
+    }
+
+    /// Returns the number of bits written so far.
+    pub fn get_bit_position(&self) -> usize {
+        self.bit_position
+    }
+
+    fn write_bits(&mut self, value: u64, bits: u32) -> Result<(), SerializationError> {
+        if self.bit_position + bits as usize > self.buffer.len() * 8 {
+            return Err(SerializationError::BufferFull);
+        }
+
+        let mut value = value;
+        let mut remaining = bits;
+
+        while remaining > 0 {
+            let index = self.bit_position / 8;
+            let offset = (self.bit_position % 8) as u32;
+            let chunk = (8 - offset).min(remaining);
+            let mask = ((1u16 << chunk) - 1) as u8;
+
+            // clear stale data when starting a new byte, the buffer may be reused
+            if offset == 0 {
+                self.buffer[index] = 0;
+            }
+
+            self.buffer[index] |= (value as u8 & mask) << offset;
+
+            value >>= chunk;
+            remaining -= chunk;
+            self.bit_position += chunk as usize;
+        }
+
+        Ok(())
+    }
+
+    fn write_var_uint(&mut self, value: u64) -> Result<(), SerializationError> {
+        let mut encoded = [0; VARINT_MAX_BYTES];
+        let mut remaining = value;
+        let mut length = 0;
+
+        loop {
+            let mut byte = (remaining & 0x7f) as u8;
+            remaining >>= 7;
+
+            if remaining != 0 {
+                byte |= 0x80;
+            }
+
+            encoded[length] = byte;
+            length += 1;
+
+            if remaining == 0 {
+                break;
+            }
+        }
+
+        self.write_bytes(&encoded[0..length])
+    }
+
+    fn write_var_int(&mut self, value: i64) -> Result<(), SerializationError> {
+        self.write_var_uint(encode(value))
+    }
+
+    fn align(&mut self) -> Result<(), SerializationError> {
+        let padding = (8 - self.bit_position % 8) % 8;
+        self.write_bits(0, padding as u32)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), SerializationError> {
+        if self.bit_position + bytes.len() * 8 > self.buffer.len() * 8 {
+            return Err(SerializationError::BufferFull);
+        }
+
+        for byte in bytes {
+            self.write_bits(u64::from(*byte), 8)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Serializer for BitWriter<'a> {
+    fn get_version(&self) -> u32 {
+        self.version
+    }
+
+    fn serialize_string(&mut self, value: &str) -> Result<(), SerializationError> {
+        self.serialize_bytes(value.as_bytes())
+    }
+
+    fn serialize_bytes(&mut self, bytes: &[u8]) -> Result<(), SerializationError> {
+        // check the whole string up front, bits written into a partial byte can't be taken back
+        let prefixed = self.bit_position + var_uint_size(bytes.len() as u64) * 8;
+        let aligned = prefixed.div_ceil(8) * 8;
+        if aligned + bytes.len() * 8 > self.buffer.len() * 8 {
+            return Err(SerializationError::BufferFull);
+        }
+
+        self.write_var_uint(bytes.len() as u64)?;
+
+        // byte strings are aligned so readers can borrow them straight from the buffer
+        self.align()?;
+        self.write_bytes(bytes)
+    }
+
+    fn serialize_bool(&mut self, value: &bool) -> Result<(), SerializationError> {
+        self.write_bits(*value as u64, 1)
+    }
+
+    fn serialize_length(&mut self, value: &usize) -> Result<(), SerializationError> {
+        self.write_var_uint(*value as u64)
+    }
+
+    fn serialize_u8(&mut self, value: &u8) -> Result<(), SerializationError> {
+        self.write_bits(u64::from(*value), 8)
+    }
+
+    fn serialize_i8(&mut self, value: &i8) -> Result<(), SerializationError> {
+        self.write_bits(u64::from(*value as u8), 8)
+    }
+
+    fn serialize_u16(&mut self, value: &u16) -> Result<(), SerializationError> {
+        self.write_var_uint(u64::from(*value))
+    }
+
+    fn serialize_i16(&mut self, value: &i16) -> Result<(), SerializationError> {
+        self.write_var_int(i64::from(*value))
+    }
+
+    fn serialize_u32(&mut self, value: &u32) -> Result<(), SerializationError> {
+        self.write_var_uint(u64::from(*value))
+    }
+
+    fn serialize_i32(&mut self, value: &i32) -> Result<(), SerializationError> {
+        self.write_var_int(i64::from(*value))
+    }
+
+    fn serialize_u64(&mut self, value: &u64) -> Result<(), SerializationError> {
+        self.write_var_uint(*value)
+    }
+
+    fn serialize_i64(&mut self, value: &i64) -> Result<(), SerializationError> {
+        self.write_var_int(*value)
+    }
+
+    fn serialize_f32(&mut self, value: &f32) -> Result<(), SerializationError> {
+        self.write_bits(u64::from(value.to_bits()), 32)
+    }
+
+    fn serialize_f64(&mut self, value: &f64) -> Result<(), SerializationError> {
+        self.write_bits(value.to_bits(), 64)
+    }
+
+    fn serialize_bits(&mut self, value: &u64, bits: u32) -> Result<(), SerializationError> {
+        check_bits(*value, bits)?;
+        self.write_bits(*value, bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::{BitReader, DeserializeOwned, Deserializer, Serialize};
+
+    fn round_trip<T>(value: &T) -> T where T: Serialize + DeserializeOwned {
+        let mut buffer = [0; 128];
+        let length = {
+            let mut writer = BitWriter::new(&mut buffer);
+            value.serialize(&mut writer).expect("It serializes the value");
+            writer.get_position()
+        };
+
+        let mut reader = BitReader::new(&buffer[0..length]);
+        T::deserialize(&mut reader).expect("It deserializes the value")
+    }
+
+    #[test]
+    fn it_packs_bits_lsb_first() {
+        let mut buffer = [0; 4];
+        let mut writer = BitWriter::new(&mut buffer);
+
+        writer.serialize_bool(&true).unwrap();
+        writer.serialize_bits(&0b101, 3).unwrap();
+        writer.serialize_bits(&0x3ff, 10).unwrap();
+
+        assert_eq!(writer.get_bit_position(), 14);
+        assert_eq!(writer.get_position(), 2);
+        assert_eq!(buffer, [0xfb, 0x3f, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn it_packs_ranged_values() {
+        let mut buffer = [0; 4];
+        let mut writer = BitWriter::new(&mut buffer);
+
+        writer.serialize_ranged(&100, 0, 100).unwrap();
+        writer.serialize_ranged(&-3, -4, 3).unwrap();
+
+        assert_eq!(writer.get_bit_position(), 10);
+
+        let mut reader = BitReader::new(&buffer);
+        assert_eq!(reader.deserialize_ranged(0, 100).unwrap(), 100);
+        assert_eq!(reader.deserialize_ranged(-4, 3).unwrap(), -3);
+    }
+
+    #[test]
+    fn it_aligns_strings_to_full_bytes() {
+        let mut buffer = [0; 4];
+        let mut writer = BitWriter::new(&mut buffer);
+
+        writer.serialize_bool(&true).unwrap();
+        writer.serialize_string("a").unwrap();
+
+        assert_eq!(writer.get_bit_position(), 24);
+        assert_eq!(&buffer[0..3], &[0x03, 0x00, b'a']);
+
+        let mut reader = BitReader::new(&buffer[0..3]);
+        assert!(reader.deserialize_bool().unwrap());
+        assert_eq!(reader.deserialize_str().unwrap(), "a");
+    }
+
+    #[test]
+    fn it_overwrites_stale_buffer_contents() {
+        let mut buffer = [0xff; 2];
+        let mut writer = BitWriter::new(&mut buffer);
+
+        writer.serialize_bool(&false).unwrap();
+        writer.serialize_bool(&true).unwrap();
+
+        assert_eq!(buffer[0], 0x02);
+    }
+
+    #[test]
+    fn it_returns_buffer_full_instead_of_overflowing() {
+        let mut buffer = [0; 1];
+        let mut writer = BitWriter::new(&mut buffer);
+
+        writer.serialize_bits(&0, 5).unwrap();
+
+        assert!(matches!(writer.serialize_u8(&1), Err(SerializationError::BufferFull)));
+        assert!(matches!(writer.serialize_string("a"), Err(SerializationError::BufferFull)));
+        assert_eq!(writer.get_bit_position(), 5);
+
+        writer.serialize_bits(&0b111, 3).unwrap();
+        assert!(matches!(writer.serialize_bool(&true), Err(SerializationError::BufferFull)));
+    }
+
+    #[test]
+    fn it_leaves_no_bits_behind_after_a_failed_string() {
+        let mut buffer = [0; 2];
+        let mut writer = BitWriter::new(&mut buffer);
+
+        writer.serialize_bool(&true).unwrap();
+        assert!(matches!(writer.serialize_string("abc"), Err(SerializationError::BufferFull)));
+        writer.serialize_bool(&false).unwrap();
+        assert_eq!(writer.get_bit_position(), 2);
+
+        let mut reader = BitReader::new(&buffer);
+        assert!(reader.deserialize_bool().unwrap());
+        assert!(!reader.deserialize_bool().unwrap());
+    }
+
+    #[test]
+    fn it_rejects_values_that_do_not_fit() {
+        let mut buffer = [0; 16];
+        let mut writer = BitWriter::new(&mut buffer);
+
+        assert!(matches!(writer.serialize_bits(&8, 3), Err(SerializationError::BitsOverflow { .. })));
+        assert!(matches!(writer.serialize_bits(&0, 65), Err(SerializationError::TooManyBits { .. })));
+        assert!(matches!(writer.serialize_ranged(&-1, 0, 10), Err(SerializationError::ValueOutOfRange { .. })));
+        assert_eq!(writer.get_bit_position(), 0);
+    }
+
+    #[test]
+    fn it_round_trips_primitives_without_byte_alignment() {
+        assert!(round_trip(&(true, 0xabu8)).0);
+        assert_eq!(round_trip(&(true, i8::MIN, -5i16, u16::MAX)), (true, i8::MIN, -5, u16::MAX));
+        assert_eq!(round_trip(&(false, u32::MAX, i32::MIN, u64::MAX, i64::MIN)), (false, u32::MAX, i32::MIN, u64::MAX, i64::MIN));
+        assert_eq!(round_trip(&(true, 1.5f32, -2.25f64)), (true, 1.5, -2.25));
+        assert_eq!(round_trip(&(true, "ünïcödé".to_owned())), (true, "ünïcödé".to_owned()));
+    }
+
+    #[test]
+    fn it_round_trips_collections() {
+        let value = (Some(true), vec![true, false, true], [None, Some(3u8)]);
+        assert_eq!(round_trip(&value), value);
+    }
+
+    #[test]
+    fn it_packs_bools_into_single_bits() {
+        let mut buffer = [0; 16];
+        let mut writer = BitWriter::new(&mut buffer);
+
+        [true; 8].serialize(&mut writer).unwrap();
+
+        assert_eq!(writer.get_position(), 1);
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::hash::{BuildHasher, Hash};
+use super::{Deserialize, Deserializer, Serialize, Serializer, SerializationError, deserialize_collection_length};
+
+// the reader already limits lengths to the remaining bytes, see impls.rs
+const MAX_PREALLOCATION: usize = 1024;
+
+// masks are written in chunks, the largest chunk a single `serialize_bits` call can hold
+const MASK_CHUNK_BITS: usize = 64;
+
+/// Types that can be encoded relative to a baseline value the receiver already knows, for example
+/// the state sent in the last packet the remote acknowledged.
+///
+/// Composite types write a mask of the parts that changed and only encode those, primitives have
+/// no smaller representation and are written in full.
+pub trait DeltaSerialize: Serialize {
+    fn serialize_delta(&self, baseline: &Self, serializer: &mut impl Serializer) -> Result<(), SerializationError>;
+
+    /// Writes a delta if there is a baseline and the full value otherwise, for example before the
+    /// remote acknowledged any state. A single flag tells `deserialize_with_baseline` which one follows.
+    fn serialize_with_baseline(&self, baseline: Option<&Self>, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        match baseline {
+            Some(baseline) => {
+                serializer.serialize_bool(&true)?;
+                self.serialize_delta(baseline, serializer)
+            },
+            None => {
+                serializer.serialize_bool(&false)?;
+                self.serialize(serializer)
+            },
+        }
+    }
+}
+
+/// The counterpart of `DeltaSerialize`, reconstructs a value from a delta and the same baseline
+/// that was used to encode it.
+pub trait DeltaDeserialize<'de>: Deserialize<'de, Item = Self> + Sized {
+    fn deserialize_delta(baseline: &Self, deserializer: &mut impl Deserializer<'de>) -> Result<Self, SerializationError>;
+
+    /// Reads a value written by `DeltaSerialize::serialize_with_baseline`.
+    fn deserialize_with_baseline(baseline: Option<&Self>, deserializer: &mut impl Deserializer<'de>) -> Result<Self, SerializationError> {
+        if !deserializer.deserialize_bool()? {
+            return Self::deserialize(deserializer);
+        }
+
+        match baseline {
+            Some(baseline) => Self::deserialize_delta(baseline, deserializer),
+            None => Err(SerializationError::MissingBaseline),
+        }
+    }
+}
+
+/// Writes one bit per entry of `changed`, packed into as few bytes as the serializer allows.
+pub fn serialize_changed_mask(serializer: &mut impl Serializer, changed: &[bool]) -> Result<(), SerializationError> {
+    for chunk in changed.chunks(MASK_CHUNK_BITS) {
+        let mask = chunk.iter()
+            .enumerate()
+            .fold(0u64, |mask, (i, changed)| mask | ((*changed as u64) << i));
+
+        serializer.serialize_bits(&mask, chunk.len() as u32)?;
+    }
+
+    Ok(())
+}
+
+/// Reads a mask of `count` entries written by `serialize_changed_mask`.
+pub fn deserialize_changed_mask<'de>(deserializer: &mut impl Deserializer<'de>, count: usize) -> Result<Vec<bool>, SerializationError> {
+    let mut changed = Vec::with_capacity(count.min(MAX_PREALLOCATION));
+    let mut remaining = count;
+
+    while remaining > 0 {
+        let bits = remaining.min(MASK_CHUNK_BITS);
+        let mask = deserializer.deserialize_bits(bits as u32)?;
+
+        changed.extend((0..bits).map(|i| mask & (1 << i) != 0));
+        remaining -= bits;
+    }
+
+    Ok(changed)
+}
+
+macro_rules! impl_delta {
+    ($t:ident) => {
+        impl DeltaSerialize for $t {
+            fn serialize_delta(&self, _baseline: &Self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+                self.serialize(serializer)
+            }
+        }
+
+        impl<'de> DeltaDeserialize<'de> for $t {
+            fn deserialize_delta(_baseline: &Self, deserializer: &mut impl Deserializer<'de>) -> Result<Self, SerializationError> {
+                Self::deserialize(deserializer)
+            }
+        }
+    }
+}
+
+impl_delta!(bool);
+impl_delta!(u8);
+impl_delta!(i8);
+impl_delta!(u16);
+impl_delta!(i16);
+impl_delta!(u32);
+impl_delta!(i32);
+impl_delta!(u64);
+impl_delta!(i64);
+impl_delta!(f32);
+impl_delta!(f64);
+impl_delta!(String);
+
+impl<T> DeltaSerialize for Option<T> where T: DeltaSerialize {
+    fn serialize_delta(&self, baseline: &Self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        match (self, baseline) {
+            (Some(value), Some(baseline)) => {
+                serializer.serialize_bool(&true)?;
+                value.serialize_delta(baseline, serializer)
+            },
+            (value, _) => value.serialize(serializer),
+        }
+    }
+}
+
+impl<'de, T> DeltaDeserialize<'de> for Option<T> where T: DeltaDeserialize<'de> {
+    fn deserialize_delta(baseline: &Self, deserializer: &mut impl Deserializer<'de>) -> Result<Self, SerializationError> {
+        if !deserializer.deserialize_bool()? {
+            return Ok(None);
+        }
+
+        match baseline {
+            Some(baseline) => Ok(Some(T::deserialize_delta(baseline, deserializer)?)),
+            None => Ok(Some(T::deserialize(deserializer)?)),
+        }
+    }
+}
+
+impl<T> DeltaSerialize for Box<T> where T: DeltaSerialize {
+    fn serialize_delta(&self, baseline: &Self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        self.as_ref().serialize_delta(baseline, serializer)
+    }
+}
+
+impl<'de, T> DeltaDeserialize<'de> for Box<T> where T: DeltaDeserialize<'de> {
+    fn deserialize_delta(baseline: &Self, deserializer: &mut impl Deserializer<'de>) -> Result<Self, SerializationError> {
+        Ok(Box::new(T::deserialize_delta(baseline, deserializer)?))
+    }
+}
+
+/// Elements that also exist in the baseline are encoded as deltas behind a changed mask,
+/// appended elements are written in full.
+///
+/// The number of elements kept from the baseline and the number of appended elements are written
+/// separately, unchanged elements take up no space so only the latter is limited by the input size.
+impl<T> DeltaSerialize for Vec<T> where T: DeltaSerialize + PartialEq {
+    fn serialize_delta(&self, baseline: &Self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        let shared = self.len().min(baseline.len());
+        serializer.serialize_u64(&(shared as u64))?;
+        serializer.serialize_length(&(self.len() - shared))?;
+
+        serialize_shared(&self[0..shared], &baseline[0..shared], serializer)?;
+
+        for item in &self[shared..] {
+            item.serialize(serializer)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'de, T> DeltaDeserialize<'de> for Vec<T> where T: DeltaDeserialize<'de> + Clone {
+    fn deserialize_delta(baseline: &Self, deserializer: &mut impl Deserializer<'de>) -> Result<Self, SerializationError> {
+        let shared = deserializer.deserialize_u64()?;
+        if shared > baseline.len() as u64 {
+            return Err(SerializationError::BaselineMismatch { length: shared, baseline: baseline.len() });
+        }
+
+        let shared = shared as usize;
+        let appended = deserialize_collection_length(deserializer, T::MIN_ENCODED_SIZE)?;
+        let mut items = Vec::with_capacity((shared + appended).min(MAX_PREALLOCATION));
+        deserialize_shared(&baseline[0..shared], &mut items, deserializer)?;
+
+        for _ in 0..appended {
+            items.push(T::deserialize(deserializer)?);
+        }
+
+        Ok(items)
+    }
+}
+
+impl<T, const N: usize> DeltaSerialize for [T; N] where T: DeltaSerialize + PartialEq {
+    fn serialize_delta(&self, baseline: &Self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        serialize_shared(self, baseline, serializer)
+    }
+}
+
+impl<'de, T, const N: usize> DeltaDeserialize<'de> for [T; N] where T: DeltaDeserialize<'de> + Clone {
+    fn deserialize_delta(baseline: &Self, deserializer: &mut impl Deserializer<'de>) -> Result<Self, SerializationError> {
+        let mut items = Vec::with_capacity(N);
+        deserialize_shared(baseline, &mut items, deserializer)?;
+
+        match items.try_into() {
+            Ok(array) => Ok(array),
+            Err(_) => unreachable!("exactly N items were deserialized"),
+        }
+    }
+}
+
+fn serialize_shared<T>(items: &[T], baseline: &[T], serializer: &mut impl Serializer) -> Result<(), SerializationError>
+    where T: DeltaSerialize + PartialEq
+{
+    let changed: Vec<bool> = items.iter()
+        .zip(baseline)
+        .map(|(item, baseline)| item != baseline)
+        .collect();
+
+    serialize_changed_mask(serializer, &changed)?;
+
+    for ((item, baseline), _) in items.iter().zip(baseline).zip(&changed).filter(|(_, changed)| **changed) {
+        item.serialize_delta(baseline, serializer)?;
+    }
+
+    Ok(())
+}
+
+fn deserialize_shared<'de, T>(baseline: &[T], items: &mut Vec<T>, deserializer: &mut impl Deserializer<'de>) -> Result<(), SerializationError>
+    where T: DeltaDeserialize<'de> + Clone
+{
+    let changed = deserialize_changed_mask(deserializer, baseline.len())?;
+
+    for (baseline, changed) in baseline.iter().zip(changed) {
+        if changed {
+            items.push(T::deserialize_delta(baseline, deserializer)?);
+        } else {
+            items.push(baseline.clone());
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the keys removed since the baseline, followed by the entries that were added or changed.
+impl<K, V, S> DeltaSerialize for HashMap<K, V, S>
+    where K: Serialize + Eq + Hash, V: DeltaSerialize + PartialEq, S: BuildHasher
+{
+    fn serialize_delta(&self, baseline: &Self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        let removed: Vec<&K> = baseline.keys().filter(|key| !self.contains_key(key)).collect();
+        let changed: Vec<(&K, &V)> = self.iter().filter(|(key, value)| baseline.get(key) != Some(value)).collect();
+
+        serialize_map_delta(&removed, &changed, |key| baseline.get(key), serializer)
+    }
+}
+
+impl<'de, K, V, S> DeltaDeserialize<'de> for HashMap<K, V, S>
+    where K: Deserialize<'de, Item = K> + Eq + Hash + Clone, V: DeltaDeserialize<'de> + Clone, S: BuildHasher + Default + Clone
+{
+    fn deserialize_delta(baseline: &Self, deserializer: &mut impl Deserializer<'de>) -> Result<Self, SerializationError> {
+        let mut items = baseline.clone();
+
+        for _ in 0..deserialize_collection_length(deserializer, K::MIN_ENCODED_SIZE)? {
+            items.remove(&K::deserialize(deserializer)?);
+        }
+
+        for _ in 0..deserialize_collection_length(deserializer, K::MIN_ENCODED_SIZE)? {
+            let key = K::deserialize(deserializer)?;
+            let value = match items.get(&key) {
+                Some(baseline) => V::deserialize_delta(baseline, deserializer)?,
+                None => V::deserialize(deserializer)?,
+            };
+            items.insert(key, value);
+        }
+
+        Ok(items)
+    }
+}
+
+impl<K, V> DeltaSerialize for BTreeMap<K, V> where K: Serialize + Ord, V: DeltaSerialize + PartialEq {
+    fn serialize_delta(&self, baseline: &Self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        let removed: Vec<&K> = baseline.keys().filter(|key| !self.contains_key(key)).collect();
+        let changed: Vec<(&K, &V)> = self.iter().filter(|(key, value)| baseline.get(key) != Some(value)).collect();
+
+        serialize_map_delta(&removed, &changed, |key| baseline.get(key), serializer)
+    }
+}
+
+impl<'de, K, V> DeltaDeserialize<'de> for BTreeMap<K, V>
+    where K: Deserialize<'de, Item = K> + Ord + Clone, V: DeltaDeserialize<'de> + Clone
+{
+    fn deserialize_delta(baseline: &Self, deserializer: &mut impl Deserializer<'de>) -> Result<Self, SerializationError> {
+        let mut items = baseline.clone();
+
+        for _ in 0..deserialize_collection_length(deserializer, K::MIN_ENCODED_SIZE)? {
+            items.remove(&K::deserialize(deserializer)?);
+        }
+
+        for _ in 0..deserialize_collection_length(deserializer, K::MIN_ENCODED_SIZE)? {
+            let key = K::deserialize(deserializer)?;
+            let value = match items.get(&key) {
+                Some(baseline) => V::deserialize_delta(baseline, deserializer)?,
+                None => V::deserialize(deserializer)?,
+            };
+            items.insert(key, value);
+        }
+
+        Ok(items)
+    }
+}
+
+fn serialize_map_delta<'a, K, V>(
+    removed: &[&K],
+    changed: &[(&K, &'a V)],
+    get_baseline: impl Fn(&K) -> Option<&'a V>,
+    serializer: &mut impl Serializer,
+) -> Result<(), SerializationError> where K: Serialize, V: DeltaSerialize {
+    serializer.serialize_length(&removed.len())?;
+
+    for key in removed {
+        key.serialize(serializer)?;
+    }
+
+    serializer.serialize_length(&changed.len())?;
+
+    for (key, value) in changed {
+        key.serialize(serializer)?;
+
+        match get_baseline(key) {
+            Some(baseline) => value.serialize_delta(baseline, serializer)?,
+            None => value.serialize(serializer)?,
+        }
+    }
+
+    Ok(())
+}
+
+macro_rules! impl_delta_tuple {
+    ($count:expr; $($t:ident $index:tt),+) => {
+        impl<$($t),+> DeltaSerialize for ($($t,)+) where $($t: DeltaSerialize + PartialEq),+ {
+            fn serialize_delta(&self, baseline: &Self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+                let changed = [$(self.$index != baseline.$index),+];
+                serialize_changed_mask(serializer, &changed)?;
+
+                $(
+                    if changed[$index] {
+                        self.$index.serialize_delta(&baseline.$index, serializer)?;
+                    }
+                )+
+
+                Ok(())
+            }
+        }
+
+        impl<'de, $($t),+> DeltaDeserialize<'de> for ($($t,)+) where $($t: DeltaDeserialize<'de> + Clone),+ {
+            fn deserialize_delta(baseline: &Self, deserializer: &mut impl Deserializer<'de>) -> Result<Self, SerializationError> {
+                let changed = deserialize_changed_mask(deserializer, $count)?;
+
+                Ok(($(
+                    if changed[$index] {
+                        $t::deserialize_delta(&baseline.$index, deserializer)?
+                    } else {
+                        baseline.$index.clone()
+                    },
+                )+))
+            }
+        }
+    }
+}
+
+impl_delta_tuple!(1; A 0);
+impl_delta_tuple!(2; A 0, B 1);
+impl_delta_tuple!(3; A 0, B 1, C 2);
+impl_delta_tuple!(4; A 0, B 1, C 2, D 3);
+impl_delta_tuple!(5; A 0, B 1, C 2, D 3, E 4);
+impl_delta_tuple!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+impl_delta_tuple!(7; A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_delta_tuple!(8; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::{BitReader, BitWriter, Reader, Writer};
+
+    fn delta_round_trip<T>(value: &T, baseline: &T) -> usize
+        where T: DeltaSerialize + for<'de> DeltaDeserialize<'de> + PartialEq + std::fmt::Debug
+    {
+        let mut buffer = [0; 512];
+        let length = {
+            let mut writer = Writer::new(&mut buffer);
+            value.serialize_delta(baseline, &mut writer).expect("It serializes the delta");
+            writer.get_position()
+        };
+
+        let mut reader = Reader::new(&buffer[0..length]);
+        assert_eq!(&T::deserialize_delta(baseline, &mut reader).expect("It deserializes the delta"), value);
+        assert_eq!(reader.remaining(), 0, "It consumes all bytes");
+
+        let mut buffer = [0; 512];
+        let bit_length = {
+            let mut writer = BitWriter::new(&mut buffer);
+            value.serialize_delta(baseline, &mut writer).expect("It serializes the delta");
+            writer.get_position()
+        };
+
+        let mut reader = BitReader::new(&buffer[0..bit_length]);
+        assert_eq!(&T::deserialize_delta(baseline, &mut reader).expect("It deserializes the delta"), value);
+
+        length
+    }
+
+    #[test]
+    fn it_writes_primitives_in_full() {
+        assert_eq!(delta_round_trip(&300u32, &300), 2);
+        assert_eq!(delta_round_trip(&"new".to_owned(), &"old".to_owned()), 4);
+        delta_round_trip(&true, &false);
+    }
+
+    #[test]
+    fn it_only_writes_changed_elements() {
+        let baseline: Vec<u64> = (0..100).map(|i| i * 1000).collect();
+        let mut value = baseline.clone();
+        value[42] = 7;
+
+        // kept and appended lengths, 13 bytes of mask and the changed element
+        assert_eq!(delta_round_trip(&value, &baseline), 2 + 13 + 1);
+        assert_eq!(delta_round_trip(&baseline, &baseline), 2 + 13);
+    }
+
+    #[test]
+    fn it_handles_added_and_removed_elements() {
+        delta_round_trip(&vec![1u8, 2, 3, 4], &vec![1, 5]);
+        delta_round_trip(&vec![1u8], &vec![1, 5, 6]);
+        delta_round_trip(&vec![], &vec![1u8]);
+        delta_round_trip(&vec![vec![1u16, 2], vec![3]], &vec![vec![1, 3]]);
+    }
+
+    #[test]
+    fn it_round_trips_arrays_and_tuples() {
+        assert_eq!(delta_round_trip(&[1u8, 2, 3], &[1, 2, 3]), 1);
+        assert_eq!(delta_round_trip(&(1u8, 500u16, -1i8), &(1, 600, -1)), 3);
+        delta_round_trip(&(1u8, 2u8, 3u8, 4u8, 5u8, 6u8, 7u8, 8u8), &(0, 2, 0, 4, 0, 6, 0, 8));
+    }
+
+    #[test]
+    fn it_round_trips_options_and_boxes() {
+        delta_round_trip(&Some((1u8, 2u8)), &Some((1, 3)));
+        delta_round_trip(&Some(1u8), &None);
+        delta_round_trip(&None, &Some(1u8));
+        delta_round_trip(&Box::new([1u16, 2]), &Box::new([1, 3]));
+    }
+
+    #[test]
+    fn it_round_trips_maps() {
+        let mut baseline = HashMap::new();
+        baseline.insert(1u32, vec![1u8, 2]);
+        baseline.insert(2u32, vec![3]);
+        baseline.insert(3u32, vec![4]);
+
+        let mut value = baseline.clone();
+        value.remove(&2);
+        value.insert(3, vec![5]);
+        value.insert(4, vec![6]);
+
+        delta_round_trip(&value, &baseline);
+        assert_eq!(delta_round_trip(&baseline, &baseline), 2);
+
+        let baseline: BTreeMap<String, u8> = vec![("a".to_owned(), 1), ("b".to_owned(), 2)].into_iter().collect();
+        let value: BTreeMap<String, u8> = vec![("b".to_owned(), 3), ("c".to_owned(), 4)].into_iter().collect();
+        delta_round_trip(&value, &baseline);
+    }
+
+    #[test]
+    fn it_rejects_a_delta_against_a_shorter_baseline() {
+        let mut buffer = [0; 16];
+        let length = {
+            let mut writer = Writer::new(&mut buffer);
+            vec![1u8, 2, 3].serialize_delta(&vec![1, 2, 4], &mut writer).unwrap();
+            writer.get_position()
+        };
+
+        let mut reader = Reader::new(&buffer[0..length]);
+        assert!(matches!(Vec::<u8>::deserialize_delta(&vec![1], &mut reader), Err(SerializationError::BaselineMismatch { length: 3, baseline: 1 })));
+    }
+
+    #[test]
+    fn it_falls_back_to_the_full_value_without_a_baseline() {
+        let mut buffer = [0; 16];
+        let length = {
+            let mut writer = Writer::new(&mut buffer);
+            vec![1u8, 2].serialize_with_baseline(None, &mut writer).unwrap();
+            vec![1u8, 3].serialize_with_baseline(Some(&vec![1, 2]), &mut writer).unwrap();
+            writer.get_position()
+        };
+
+        let mut reader = Reader::new(&buffer[0..length]);
+        let first = Vec::<u8>::deserialize_with_baseline(None, &mut reader).unwrap();
+        assert_eq!(first, vec![1, 2]);
+        assert_eq!(Vec::<u8>::deserialize_with_baseline(Some(&first), &mut reader).unwrap(), vec![1, 3]);
+
+        let mut reader = Reader::new(&buffer[4..length]);
+        assert!(matches!(Vec::<u8>::deserialize_with_baseline(None, &mut reader), Err(SerializationError::MissingBaseline)));
+    }
+
+    #[test]
+    fn it_packs_masks_into_64_bit_chunks() {
+        let changed: Vec<bool> = (0..130).map(|i| i % 3 == 0).collect();
+
+        let mut buffer = [0; 32];
+        let mut writer = BitWriter::new(&mut buffer);
+        serialize_changed_mask(&mut writer, &changed).unwrap();
+        assert_eq!(writer.get_bit_position(), 130);
+
+        let mut reader = BitReader::new(&buffer);
+        assert_eq!(deserialize_changed_mask(&mut reader, 130).unwrap(), changed);
+    }
+}
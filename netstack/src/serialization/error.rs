@@ -2,4 +2,89 @@
 pub enum SerializationError {
     #[fail(display = "Parsing VarUInt further would overflow the maximum number of iterations")]
     VarUIntOverflow,
+
+    #[fail(display = "Writing further would exceed the buffer")]
+    BufferFull,
+
+    #[fail(display = "Reached the end of the buffer before the value was complete")]
+    UnexpectedEnd,
+
+    #[fail(display = "String is not valid UTF-8")]
+    InvalidUtf8,
+
+    #[fail(display = "Length of {} exceeds the {} remaining bytes", length, remaining)]
+    LengthTooLarge {
+        length: u64,
+        remaining: usize,
+    },
+
+    #[fail(display = "Length of {} exceeds the maximum length of {}", length, max)]
+    LengthExceedsMaximum {
+        length: usize,
+        max: usize,
+    },
+
+    #[fail(display = "Invalid boolean value {}", value)]
+    InvalidBool {
+        value: u8,
+    },
+
+    #[fail(display = "Cannot serialize {} bits at once, the maximum is 64", bits)]
+    TooManyBits {
+        bits: u32,
+    },
+
+    #[fail(display = "Value {} does not fit into {} bits", value, bits)]
+    BitsOverflow {
+        value: u64,
+        bits: u32,
+    },
+
+    #[fail(display = "Value {} is outside of the range {}..={}", value, min, max)]
+    ValueOutOfRange {
+        value: i64,
+        min: i64,
+        max: i64,
+    },
+
+    #[fail(display = "Unknown enum discriminant {}", value)]
+    InvalidDiscriminant {
+        value: u64,
+    },
+
+    #[fail(display = "Delta refers to {} elements but the baseline only has {}", length, baseline)]
+    BaselineMismatch {
+        length: u64,
+        baseline: usize,
+    },
+
+    #[fail(display = "Received a delta but there is no baseline to apply it to")]
+    MissingBaseline,
+
+    #[fail(display = "{}", message)]
+    Custom {
+        message: String,
+    },
+
+    #[fail(display = "Invalid quantization parameters")]
+    InvalidQuantization,
+
+    #[fail(display = "Value cannot be quantized, it is either not finite or has zero length")]
+    NotQuantizable,
+
+    #[fail(display = "No message type is registered for id {}", id)]
+    UnknownMessageId {
+        id: u32,
+    },
+
+    #[fail(display = "Expected a message with id {} but found id {}", expected, found)]
+    UnexpectedMessageId {
+        expected: u32,
+        found: u32,
+    },
+
+    #[fail(display = "Another message type is already registered for id {}", id)]
+    DuplicateMessageId {
+        id: u32,
+    },
 }
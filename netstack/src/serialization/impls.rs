@@ -0,0 +1,332 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::hash::{BuildHasher, Hash};
+use super::{Deserialize, DeserializeBounded, Deserializer, Serialize, Serializer, SerializationError, add_sizes, repeat_size, default_max_length};
+
+// the reader already limits lengths to the remaining bytes, this additionally caps
+// allocations for deserializers that can't know how many bytes are left
+const MAX_PREALLOCATION: usize = 1024;
+
+impl<T> Serialize for Option<T> where T: Serialize {
+    const MAX_ENCODED_SIZE: Option<usize> = add_sizes(Some(1), T::MAX_ENCODED_SIZE);
+
+    fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        match self {
+            Some(value) => {
+                serializer.serialize_bool(&true)?;
+                value.serialize(serializer)
+            },
+            None => serializer.serialize_bool(&false),
+        }
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Option<T> where T: Deserialize<'de, Item = T> {
+    type Item = Option<T>;
+
+    fn deserialize(deserializer: &mut impl Deserializer<'de>) -> Result<Self::Item, SerializationError> {
+        if deserializer.deserialize_bool()? {
+            Ok(Some(T::deserialize(deserializer)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<T> Serialize for Box<T> where T: Serialize {
+    const MAX_ENCODED_SIZE: Option<usize> = T::MAX_ENCODED_SIZE;
+
+    fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        self.as_ref().serialize(serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Box<T> where T: Deserialize<'de, Item = T> {
+    type Item = Box<T>;
+
+    const MIN_ENCODED_SIZE: usize = T::MIN_ENCODED_SIZE;
+
+    fn deserialize(deserializer: &mut impl Deserializer<'de>) -> Result<Self::Item, SerializationError> {
+        Ok(Box::new(T::deserialize(deserializer)?))
+    }
+}
+
+impl<T> Serialize for Vec<T> where T: Serialize {
+    fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        serializer.serialize_length(&self.len())?;
+
+        for item in self {
+            item.serialize(serializer)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Vec<T> where T: Deserialize<'de, Item = T> {
+    type Item = Vec<T>;
+
+    fn deserialize(deserializer: &mut impl Deserializer<'de>) -> Result<Self::Item, SerializationError> {
+        Self::deserialize_bounded(deserializer, default_max_length(T::MIN_ENCODED_SIZE))
+    }
+}
+
+impl<'de, T> DeserializeBounded<'de> for Vec<T> where T: Deserialize<'de, Item = T> {
+    fn deserialize_bounded(deserializer: &mut impl Deserializer<'de>, max_len: usize) -> Result<Self::Item, SerializationError> {
+        let length = deserializer.deserialize_length_bounded(T::MIN_ENCODED_SIZE, max_len)?;
+        let mut items = Vec::with_capacity(length.min(MAX_PREALLOCATION));
+
+        for _ in 0..length {
+            items.push(T::deserialize(deserializer)?);
+        }
+
+        Ok(items)
+    }
+}
+
+/// Arrays have a fixed length, so unlike `Vec` they are written without a length prefix.
+impl<T, const N: usize> Serialize for [T; N] where T: Serialize {
+    const MAX_ENCODED_SIZE: Option<usize> = repeat_size(T::MAX_ENCODED_SIZE, N);
+
+    fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        for item in self {
+            item.serialize(serializer)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'de, T, const N: usize> Deserialize<'de> for [T; N] where T: Deserialize<'de, Item = T> {
+    type Item = [T; N];
+
+    const MIN_ENCODED_SIZE: usize = T::MIN_ENCODED_SIZE.saturating_mul(N);
+
+    fn deserialize(deserializer: &mut impl Deserializer<'de>) -> Result<Self::Item, SerializationError> {
+        let mut items = Vec::with_capacity(N);
+
+        for _ in 0..N {
+            items.push(T::deserialize(deserializer)?);
+        }
+
+        match items.try_into() {
+            Ok(array) => Ok(array),
+            Err(_) => unreachable!("exactly N items were deserialized"),
+        }
+    }
+}
+
+impl<K, V, S> Serialize for HashMap<K, V, S> where K: Serialize, V: Serialize {
+    fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        serializer.serialize_length(&self.len())?;
+
+        for (key, value) in self {
+            key.serialize(serializer)?;
+            value.serialize(serializer)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'de, K, V, S> Deserialize<'de> for HashMap<K, V, S>
+    where K: Deserialize<'de, Item = K> + Eq + Hash, V: Deserialize<'de, Item = V>, S: BuildHasher + Default
+{
+    type Item = HashMap<K, V, S>;
+
+    fn deserialize(deserializer: &mut impl Deserializer<'de>) -> Result<Self::Item, SerializationError> {
+        Self::deserialize_bounded(deserializer, default_max_length(K::MIN_ENCODED_SIZE.saturating_add(V::MIN_ENCODED_SIZE)))
+    }
+}
+
+impl<'de, K, V, S> DeserializeBounded<'de> for HashMap<K, V, S>
+    where K: Deserialize<'de, Item = K> + Eq + Hash, V: Deserialize<'de, Item = V>, S: BuildHasher + Default
+{
+    fn deserialize_bounded(deserializer: &mut impl Deserializer<'de>, max_len: usize) -> Result<Self::Item, SerializationError> {
+        let length = deserializer.deserialize_length_bounded(K::MIN_ENCODED_SIZE.saturating_add(V::MIN_ENCODED_SIZE), max_len)?;
+        let mut items = HashMap::with_capacity_and_hasher(length.min(MAX_PREALLOCATION), S::default());
+
+        for _ in 0..length {
+            let key = K::deserialize(deserializer)?;
+            let value = V::deserialize(deserializer)?;
+            items.insert(key, value);
+        }
+
+        Ok(items)
+    }
+}
+
+impl<K, V> Serialize for BTreeMap<K, V> where K: Serialize, V: Serialize {
+    fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        serializer.serialize_length(&self.len())?;
+
+        for (key, value) in self {
+            key.serialize(serializer)?;
+            value.serialize(serializer)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for BTreeMap<K, V> where K: Deserialize<'de, Item = K> + Ord, V: Deserialize<'de, Item = V> {
+    type Item = BTreeMap<K, V>;
+
+    fn deserialize(deserializer: &mut impl Deserializer<'de>) -> Result<Self::Item, SerializationError> {
+        Self::deserialize_bounded(deserializer, default_max_length(K::MIN_ENCODED_SIZE.saturating_add(V::MIN_ENCODED_SIZE)))
+    }
+}
+
+impl<'de, K, V> DeserializeBounded<'de> for BTreeMap<K, V> where K: Deserialize<'de, Item = K> + Ord, V: Deserialize<'de, Item = V> {
+    fn deserialize_bounded(deserializer: &mut impl Deserializer<'de>, max_len: usize) -> Result<Self::Item, SerializationError> {
+        let length = deserializer.deserialize_length_bounded(K::MIN_ENCODED_SIZE.saturating_add(V::MIN_ENCODED_SIZE), max_len)?;
+        let mut items = BTreeMap::new();
+
+        for _ in 0..length {
+            let key = K::deserialize(deserializer)?;
+            let value = V::deserialize(deserializer)?;
+            items.insert(key, value);
+        }
+
+        Ok(items)
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($t:ident),+) => {
+        impl<$($t),+> Serialize for ($($t,)+) where $($t: Serialize),+ {
+            const MAX_ENCODED_SIZE: Option<usize> = {
+                let size = Some(0);
+                $(let size = add_sizes(size, $t::MAX_ENCODED_SIZE);)+
+                size
+            };
+
+            #[allow(non_snake_case)]
+            fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+                let ($($t,)+) = self;
+                $($t.serialize(serializer)?;)+
+                Ok(())
+            }
+        }
+
+        impl<'de, $($t),+> Deserialize<'de> for ($($t,)+) where $($t: Deserialize<'de, Item = $t>),+ {
+            type Item = ($($t,)+);
+
+            const MIN_ENCODED_SIZE: usize = {
+                let size: usize = 0;
+                $(let size = size.saturating_add($t::MIN_ENCODED_SIZE);)+
+                size
+            };
+
+            fn deserialize(deserializer: &mut impl Deserializer<'de>) -> Result<Self::Item, SerializationError> {
+                Ok(($($t::deserialize(deserializer)?,)+))
+            }
+        }
+    }
+}
+
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+impl_tuple!(A, B, C, D, E);
+impl_tuple!(A, B, C, D, E, F);
+impl_tuple!(A, B, C, D, E, F, G);
+impl_tuple!(A, B, C, D, E, F, G, H);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::{DeserializeOwned, Reader, Writer};
+
+    fn round_trip<T>(value: &T) -> T where T: Serialize + DeserializeOwned {
+        let mut buffer = [0; 256];
+        let length = {
+            let mut writer = Writer::new(&mut buffer);
+            value.serialize(&mut writer).expect("It serializes the value");
+            writer.get_position()
+        };
+
+        let mut reader = Reader::new(&buffer[0..length]);
+        let result = T::deserialize(&mut reader).expect("It deserializes the value");
+        assert_eq!(reader.remaining(), 0, "It consumes all bytes");
+
+        result
+    }
+
+    fn serialize<T>(value: &T) -> Vec<u8> where T: Serialize {
+        let mut buffer = [0; 256];
+        let mut writer = Writer::new(&mut buffer);
+        value.serialize(&mut writer).expect("It serializes the value");
+        let length = writer.get_position();
+
+        buffer[0..length].to_vec()
+    }
+
+    #[test]
+    fn it_round_trips_options() {
+        assert_eq!(round_trip(&Some(15u32)), Some(15));
+        assert_eq!(round_trip(&Option::<u32>::None), None);
+        assert_eq!(round_trip(&Some(Some("nested".to_owned()))), Some(Some("nested".to_owned())));
+    }
+
+    #[test]
+    fn it_writes_a_presence_byte_for_options() {
+        assert_eq!(serialize(&Some(7u8)), vec![0x01, 0x07]);
+        assert_eq!(serialize(&Option::<u8>::None), vec![0x00]);
+    }
+
+    #[test]
+    fn it_round_trips_vecs() {
+        assert_eq!(round_trip(&Vec::<u64>::new()), vec![]);
+        assert_eq!(round_trip(&vec![1u16, 2, 300]), vec![1, 2, 300]);
+        assert_eq!(round_trip(&vec![vec![true], vec![]]), vec![vec![true], vec![]]);
+    }
+
+    #[test]
+    fn it_writes_a_length_prefix_for_vecs() {
+        assert_eq!(serialize(&vec![0xau8, 0xb]), vec![0x02, 0x0a, 0x0b]);
+    }
+
+    #[test]
+    fn it_round_trips_arrays_without_a_length_prefix() {
+        assert_eq!(round_trip(&[1.5f32, -2.0, 3.25]), [1.5, -2.0, 3.25]);
+        assert_eq!(serialize(&[0x1u8, 0x2]), vec![0x1, 0x2]);
+    }
+
+    #[test]
+    fn it_round_trips_tuples() {
+        assert_eq!(round_trip(&(1u8,)), (1,));
+        assert_eq!(round_trip(&(1u8, -2i32, "three".to_owned())), (1, -2, "three".to_owned()));
+        assert_eq!(
+            round_trip(&(1u8, 2u16, 3u32, 4u64, 5i8, 6i16, 7i32, 8i64)),
+            (1, 2, 3, 4, 5, 6, 7, 8));
+    }
+
+    #[test]
+    fn it_round_trips_boxes() {
+        assert_eq!(round_trip(&Box::new(42i64)), Box::new(42));
+    }
+
+    #[test]
+    fn it_round_trips_maps() {
+        let mut hash_map = HashMap::new();
+        hash_map.insert(1u32, "one".to_owned());
+        hash_map.insert(2u32, "two".to_owned());
+
+        let mut btree_map = BTreeMap::new();
+        btree_map.insert("a".to_owned(), vec![1u8]);
+        btree_map.insert("b".to_owned(), vec![]);
+
+        assert_eq!(round_trip(&hash_map), hash_map);
+        assert_eq!(round_trip(&btree_map), btree_map);
+    }
+
+    #[test]
+    fn it_rejects_a_vec_longer_than_the_buffer() {
+        let buffer = [0xff, 0xff, 0xff, 0xff, 0x0f, 0x01];
+        let mut reader = Reader::new(&buffer);
+
+        assert!(matches!(Vec::<u8>::deserialize(&mut reader), Err(SerializationError::LengthTooLarge { .. })));
+    }
+}
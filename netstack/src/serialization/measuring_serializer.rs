@@ -0,0 +1,224 @@
+use super::{Serializer, SerializationError, LATEST_VERSION, check_bits};
+use super::zig_zag::encode;
+
+/// A `Serializer` that doesn't write anything and only counts how large the encoded value would be.
+///
+/// Created with `new` it measures the size as written by `Writer`, created with `bit_packed` it
+/// measures the size as written by `BitWriter`.
+pub struct MeasuringSerializer {
+    bits: usize,
+    bit_packed: bool,
+    version: u32,
+}
+
+impl MeasuringSerializer {
+    /// Measures the byte aligned encoding of `Writer`.
+    pub fn new() -> Self {
+        Self {
+            bits: 0,
+            bit_packed: false,
+            version: LATEST_VERSION,
+        }
+    }
+
+    /// Measures the bit packed encoding of `BitWriter`.
+    pub fn bit_packed() -> Self {
+        Self {
+            bits: 0,
+            bit_packed: true,
+            version: LATEST_VERSION,
+        }
+    }
+
+    /// Measures fields as they were in the given protocol version, see `Serializer::get_version`.
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Returns the number of bytes measured so far, including a partially used last byte.
+    pub fn get_size(&self) -> usize {
+        self.bits.div_ceil(8)
+    }
+
+    /// Returns the number of bits measured so far.
+    pub fn get_bits(&self) -> usize {
+        self.bits
+    }
+
+    fn add_bytes(&mut self, bytes: usize) -> Result<(), SerializationError> {
+        self.bits += bytes * 8;
+        Ok(())
+    }
+
+    fn add_var_uint(&mut self, value: u64) -> Result<(), SerializationError> {
+        let significant_bits = 64 - value.leading_zeros() as usize;
+        self.add_bytes(significant_bits.div_ceil(7).max(1))
+    }
+
+    fn add_var_int(&mut self, value: i64) -> Result<(), SerializationError> {
+        self.add_var_uint(encode(value))
+    }
+}
+
+impl Default for MeasuringSerializer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Serializer for MeasuringSerializer {
+    fn get_version(&self) -> u32 {
+        self.version
+    }
+
+    fn serialize_string(&mut self, value: &str) -> Result<(), SerializationError> {
+        self.serialize_bytes(value.as_bytes())
+    }
+
+    fn serialize_bytes(&mut self, value: &[u8]) -> Result<(), SerializationError> {
+        self.add_var_uint(value.len() as u64)?;
+
+        // BitWriter aligns the contents to the next full byte
+        self.bits = self.bits.div_ceil(8) * 8;
+        self.add_bytes(value.len())
+    }
+
+    fn serialize_bool(&mut self, _value: &bool) -> Result<(), SerializationError> {
+        if self.bit_packed {
+            self.bits += 1;
+            Ok(())
+        } else {
+            self.add_bytes(1)
+        }
+    }
+
+    fn serialize_length(&mut self, value: &usize) -> Result<(), SerializationError> {
+        self.add_var_uint(*value as u64)
+    }
+
+    fn serialize_u8(&mut self, _value: &u8) -> Result<(), SerializationError> {
+        self.add_bytes(1)
+    }
+
+    fn serialize_i8(&mut self, _value: &i8) -> Result<(), SerializationError> {
+        self.add_bytes(1)
+    }
+
+    fn serialize_u16(&mut self, value: &u16) -> Result<(), SerializationError> {
+        self.add_var_uint(u64::from(*value))
+    }
+
+    fn serialize_i16(&mut self, value: &i16) -> Result<(), SerializationError> {
+        self.add_var_int(i64::from(*value))
+    }
+
+    fn serialize_u32(&mut self, value: &u32) -> Result<(), SerializationError> {
+        self.add_var_uint(u64::from(*value))
+    }
+
+    fn serialize_i32(&mut self, value: &i32) -> Result<(), SerializationError> {
+        self.add_var_int(i64::from(*value))
+    }
+
+    fn serialize_u64(&mut self, value: &u64) -> Result<(), SerializationError> {
+        self.add_var_uint(*value)
+    }
+
+    fn serialize_i64(&mut self, value: &i64) -> Result<(), SerializationError> {
+        self.add_var_int(*value)
+    }
+
+    fn serialize_f32(&mut self, _value: &f32) -> Result<(), SerializationError> {
+        self.add_bytes(4)
+    }
+
+    fn serialize_f64(&mut self, _value: &f64) -> Result<(), SerializationError> {
+        self.add_bytes(8)
+    }
+
+    fn serialize_bits(&mut self, value: &u64, bits: u32) -> Result<(), SerializationError> {
+        check_bits(*value, bits)?;
+
+        if self.bit_packed {
+            self.bits += bits as usize;
+            Ok(())
+        } else {
+            self.add_bytes(bits.div_ceil(8) as usize)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::{BitWriter, Serialize, Writer};
+
+    fn assert_sizes_match<T>(value: &T) where T: Serialize {
+        let mut buffer = [0; 512];
+        let mut writer = Writer::new(&mut buffer);
+        value.serialize(&mut writer).unwrap();
+        assert_eq!(value.encoded_size().unwrap(), writer.get_position());
+
+        let mut buffer = [0; 512];
+        let mut writer = BitWriter::new(&mut buffer);
+        value.serialize(&mut writer).unwrap();
+        assert_eq!(value.encoded_bits().unwrap(), writer.get_bit_position());
+    }
+
+    #[test]
+    fn it_measures_var_uints() {
+        for value in &[0u64, 1, 127, 128, 16_383, 16_384, 1 << 35, u64::MAX] {
+            assert_sizes_match(value);
+        }
+
+        assert_eq!(0u64.encoded_size().unwrap(), 1);
+        assert_eq!(128u64.encoded_size().unwrap(), 2);
+        assert_eq!(u64::MAX.encoded_size().unwrap(), 10);
+    }
+
+    #[test]
+    fn it_measures_zig_zag_encoded_var_ints() {
+        for value in &[0i64, 1, -1, 63, -64, 64, -65, i64::MIN, i64::MAX] {
+            assert_sizes_match(value);
+        }
+
+        for value in &[i32::MIN, -300, 300, i32::MAX] {
+            assert_sizes_match(value);
+        }
+    }
+
+    #[test]
+    fn it_measures_primitives() {
+        assert_sizes_match(&(true, 1u8, -1i8, 1u16, -1i16, 1.5f32, -1.5f64));
+        assert_sizes_match(&"Hello World!".to_owned());
+        assert_sizes_match(&"x".repeat(200));
+        assert_sizes_match(&(true, "unaligned", &[0x1u8, 0x2][..]));
+    }
+
+    #[test]
+    fn it_measures_collections() {
+        assert_sizes_match(&vec![true; 300]);
+        assert_sizes_match(&(Some(5u32), Option::<u32>::None, [1u16, 2, 3]));
+    }
+
+    #[test]
+    fn it_measures_bits_and_ranges() {
+        let mut measuring = MeasuringSerializer::new();
+        measuring.serialize_bits(&3, 5).unwrap();
+        measuring.serialize_ranged(&50, 0, 1000).unwrap();
+        assert_eq!(measuring.get_size(), 3);
+
+        let mut measuring = MeasuringSerializer::bit_packed();
+        measuring.serialize_bits(&3, 5).unwrap();
+        measuring.serialize_ranged(&50, 0, 1000).unwrap();
+        assert_eq!(measuring.get_bits(), 15);
+        assert_eq!(measuring.get_size(), 2);
+    }
+
+    #[test]
+    fn it_reports_the_same_errors_as_the_writers() {
+        assert!(matches!(MeasuringSerializer::new().serialize_bits(&4, 2), Err(SerializationError::BitsOverflow { .. })));
+        assert!(matches!(MeasuringSerializer::new().serialize_ranged(&-1, 0, 1), Err(SerializationError::ValueOutOfRange { .. })));
+    }
+}
@@ -1,17 +1,34 @@
 mod reader;
 mod writer;
+mod bit_reader;
+mod bit_writer;
 mod zig_zag;
 mod error;
+mod impls;
+mod quantization;
+mod measuring_serializer;
+mod delta;
+mod size;
+#[cfg(feature = "serde")]
+mod serde_compat;
 pub use reader::Reader;
 pub use writer::Writer;
+pub use bit_reader::BitReader;
+pub use bit_writer::BitWriter;
+pub use measuring_serializer::MeasuringSerializer;
+pub use delta::{DeltaSerialize, DeltaDeserialize, serialize_changed_mask, deserialize_changed_mask};
+pub use size::{LengthPrefixed, DeserializeBounded, var_uint_size, add_sizes, max_sizes, repeat_size, length_prefixed_size, assert_max_encoded_size, check_max_len};
+pub use quantization::{FloatQuantization, UnitVectorQuantization, QuaternionQuantization};
 pub use error::SerializationError;
+#[cfg(feature = "serde")]
+pub use serde_compat::{Serde, SerdeSerializer, SerdeDeserializer, SerdeError, serialize_serde, deserialize_serde};
 
 macro_rules! impl_deserialize {
     ($t:ident, $d:tt) => {
-        impl Deserialize for $t {
+        impl<'de> Deserialize<'de> for $t {
             type Item = $t;
 
-            fn deserialize(deserializer: &mut impl Deserializer) -> Result<Self::Item, SerializationError> {
+            fn deserialize(deserializer: &mut impl Deserializer<'de>) -> Result<Self::Item, SerializationError> {
                 deserializer.$d()
             }
         }
@@ -21,8 +38,17 @@ macro_rules! impl_deserialize {
 macro_rules! impl_serialize {
     ($t:ident, $d:tt) => {
         impl Serialize for $t {
-            fn serialize(&self, serializer: &mut impl Serializer) {
-                serializer.$d(&self);
+            fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+                serializer.$d(self)
+            }
+        }
+    };
+    ($t:ident, $d:tt, $size:expr) => {
+        impl Serialize for $t {
+            const MAX_ENCODED_SIZE: Option<usize> = Some($size);
+
+            fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+                serializer.$d(self)
             }
         }
     }
@@ -31,44 +57,171 @@ macro_rules! impl_serialize {
 impl_deserialize!(String, deserialize_string);
 impl_serialize!(String, serialize_string);
 
+impl Serialize for str {
+    fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        serializer.serialize_string(self)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for &'a str {
+    type Item = &'a str;
+
+    fn deserialize(deserializer: &mut impl Deserializer<'de>) -> Result<Self::Item, SerializationError> {
+        deserializer.deserialize_str()
+    }
+}
+
+impl Serialize for [u8] {
+    fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        serializer.serialize_bytes(self)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for &'a [u8] {
+    type Item = &'a [u8];
+
+    fn deserialize(deserializer: &mut impl Deserializer<'de>) -> Result<Self::Item, SerializationError> {
+        deserializer.deserialize_bytes()
+    }
+}
+
+impl<T> Serialize for &T where T: Serialize + ?Sized {
+    const MAX_ENCODED_SIZE: Option<usize> = T::MAX_ENCODED_SIZE;
+
+    fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        (**self).serialize(serializer)
+    }
+}
+
+impl_deserialize!(bool, deserialize_bool);
+impl_serialize!(bool, serialize_bool, 1);
+
 impl_deserialize!(u8, deserialize_u8);
-impl_serialize!(u8, serialize_u8);
+impl_serialize!(u8, serialize_u8, 1);
 impl_deserialize!(i8, deserialize_i8);
-impl_serialize!(i8, serialize_i8);
+impl_serialize!(i8, serialize_i8, 1);
 
 impl_deserialize!(u16, deserialize_u16);
-impl_serialize!(u16, serialize_u16);
+impl_serialize!(u16, serialize_u16, 3);
 impl_deserialize!(i16, deserialize_i16);
-impl_serialize!(i16, serialize_i16);
+impl_serialize!(i16, serialize_i16, 3);
 
 impl_deserialize!(u32, deserialize_u32);
-impl_serialize!(u32, serialize_u32);
+impl_serialize!(u32, serialize_u32, 5);
 impl_deserialize!(i32, deserialize_i32);
-impl_serialize!(i32, serialize_i32);
+impl_serialize!(i32, serialize_i32, 5);
 
 impl_deserialize!(u64, deserialize_u64);
-impl_serialize!(u64, serialize_u64);
+impl_serialize!(u64, serialize_u64, 10);
 impl_deserialize!(i64, deserialize_i64);
-impl_serialize!(i64, serialize_i64);
+impl_serialize!(i64, serialize_i64, 10);
 
 impl_deserialize!(f32, deserialize_f32);
-impl_serialize!(f32, serialize_f32);
+impl_serialize!(f32, serialize_f32, 4);
 
 impl_deserialize!(f64, deserialize_f64);
-impl_serialize!(f64, serialize_f64);
+impl_serialize!(f64, serialize_f64, 8);
+
+const MAX_BITS: u32 = 64;
+
+/// The protocol version serializers use unless one is set with `with_version`.
+///
+/// Fields marked with `#[netstack(since = N)]` are written in every version from `N` on, fields
+/// marked with `#[netstack(until = N)]` were removed in version `N` and are not written anymore.
+pub const LATEST_VERSION: u32 = u32::MAX;
+
+/// Collections of elements that take up no space on the wire can't be checked against the remaining
+/// input, so they are limited to this many elements instead.
+pub const MAX_ZERO_SIZED_LENGTH: usize = 1024;
+
+/// Returns the maximum length of a collection without `#[netstack(max_len = N)]`.
+pub(crate) const fn default_max_length(min_size: usize) -> usize {
+    if min_size == 0 { MAX_ZERO_SIZED_LENGTH } else { usize::MAX }
+}
+
+/// Reads the length prefix of a collection whose elements take up at least `min_size` bytes.
+pub(crate) fn deserialize_collection_length<'de>(deserializer: &mut impl Deserializer<'de>, min_size: usize) -> Result<usize, SerializationError> {
+    deserializer.deserialize_length_bounded(min_size, default_max_length(min_size))
+}
+
+/// Returns the number of bits needed to represent every value in `min..=max`.
+pub(crate) fn bits_required(min: i64, max: i64) -> u32 {
+    let range = max.wrapping_sub(min) as u64;
+    MAX_BITS - range.leading_zeros()
+}
+
+/// Ensures `value` can be represented with `bits` bits.
+pub(crate) fn check_bits(value: u64, bits: u32) -> Result<(), SerializationError> {
+    if bits > MAX_BITS {
+        return Err(SerializationError::TooManyBits { bits });
+    }
 
-pub trait Deserialize {
+    if bits < MAX_BITS && value >> bits != 0 {
+        return Err(SerializationError::BitsOverflow { value, bits });
+    }
+
+    Ok(())
+}
+
+/// Types that can be deserialized from data with the lifetime `'de`.
+///
+/// Types like `&'de str` or `&'de [u8]` borrow from the deserializer's buffer instead of allocating.
+pub trait Deserialize<'de> {
     type Item;
 
-    fn deserialize(deserializer: &mut impl Deserializer) -> Result<Self::Item, SerializationError>;
+    /// The smallest number of bytes a `Reader` reads for any value of the type, `BitReader` reads at least
+    /// one bit unless this is zero. Collections reject lengths whose elements can't fit into the remaining input.
+    const MIN_ENCODED_SIZE: usize = 1;
+
+    fn deserialize(deserializer: &mut impl Deserializer<'de>) -> Result<Self::Item, SerializationError>;
 }
 
+/// Types that can be deserialized without borrowing from the deserializer's buffer.
+pub trait DeserializeOwned: for<'de> Deserialize<'de, Item = Self> {}
+
+impl<T> DeserializeOwned for T where T: for<'de> Deserialize<'de, Item = T> {}
+
 pub trait Serialize {
-    fn serialize(&self, serializer: &mut impl Serializer);
+    /// The largest number of bytes a `Writer` needs for any value of the type, `BitWriter` never needs more.
+    /// `None` if the size is unbounded, like for `String` or `Vec`.
+    const MAX_ENCODED_SIZE: Option<usize> = None;
+
+    fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError>;
+
+    /// Returns the number of bytes the value takes up when written by a `Writer`.
+    fn encoded_size(&self) -> Result<usize, SerializationError> {
+        let mut measuring = MeasuringSerializer::new();
+        self.serialize(&mut measuring)?;
+        Ok(measuring.get_size())
+    }
+
+    /// Returns the number of bits the value takes up when written by a `BitWriter`.
+    fn encoded_bits(&self) -> Result<usize, SerializationError> {
+        let mut measuring = MeasuringSerializer::bit_packed();
+        self.serialize(&mut measuring)?;
+        Ok(measuring.get_bits())
+    }
 }
 
-pub trait Deserializer {
+pub trait Deserializer<'de> {
+    /// Returns the protocol version the data was written with, `LATEST_VERSION` by default.
+    fn get_version(&self) -> u32 {
+        LATEST_VERSION
+    }
+
     fn deserialize_string(&mut self) -> Result<String, SerializationError>;
+    /// Reads a string without copying it out of the buffer.
+    fn deserialize_str(&mut self) -> Result<&'de str, SerializationError>;
+    /// Reads a length prefixed byte slice without copying it out of the buffer.
+    fn deserialize_bytes(&mut self) -> Result<&'de [u8], SerializationError>;
+    fn deserialize_bool(&mut self) -> Result<bool, SerializationError>;
+    /// Reads the length prefix of a collection whose elements take up at least one byte.
+    fn deserialize_length(&mut self) -> Result<usize, SerializationError> {
+        self.deserialize_length_bounded(1, usize::MAX)
+    }
+    /// Reads the length prefix of a collection of at most `max` elements that take up at least `min_size`
+    /// bytes each, so a length that can't be valid fails before any element is read.
+    fn deserialize_length_bounded(&mut self, min_size: usize, max: usize) -> Result<usize, SerializationError>;
     fn deserialize_u8(&mut self) -> Result<u8, SerializationError>;
     fn deserialize_i8(&mut self) -> Result<i8, SerializationError>;
     fn deserialize_u16(&mut self) -> Result<u16, SerializationError>;
@@ -79,18 +232,83 @@ pub trait Deserializer {
     fn deserialize_i64(&mut self) -> Result<i64, SerializationError>;
     fn deserialize_f32(&mut self) -> Result<f32, SerializationError>;
     fn deserialize_f64(&mut self) -> Result<f64, SerializationError>;
+
+    /// Reads a value that was written with `bits` bits, `bits` can be at most 64.
+    fn deserialize_bits(&mut self, bits: u32) -> Result<u64, SerializationError>;
+
+    /// Reads a value in the inclusive range `min..=max` that was written with `serialize_ranged`.
+    fn deserialize_ranged(&mut self, min: i64, max: i64) -> Result<i64, SerializationError> {
+        let offset = self.deserialize_bits(bits_required(min, max))?;
+        let value = min.wrapping_add(offset as i64);
+
+        if value < min || value > max {
+            return Err(SerializationError::ValueOutOfRange { value, min, max });
+        }
+
+        Ok(value)
+    }
 }
 
 pub trait Serializer {
-    fn serialize_string(&mut self, value: &str);
-    fn serialize_u8(&mut self, value: &u8);
-    fn serialize_i8(&mut self, value: &i8);
-    fn serialize_u16(&mut self, value: &u16);
-    fn serialize_i16(&mut self, value: &i16);
-    fn serialize_u32(&mut self, value: &u32);
-    fn serialize_i32(&mut self, value: &i32);
-    fn serialize_u64(&mut self, value: &u64);
-    fn serialize_i64(&mut self, value: &i64);
-    fn serialize_f32(&mut self, value: &f32);
-    fn serialize_f64(&mut self, value: &f64);
+    /// Returns the protocol version of the receiving side, `LATEST_VERSION` by default.
+    ///
+    /// Derived implementations leave out fields that don't exist in this version, so newer peers
+    /// can keep talking to older ones.
+    fn get_version(&self) -> u32 {
+        LATEST_VERSION
+    }
+
+    fn serialize_string(&mut self, value: &str) -> Result<(), SerializationError>;
+    /// Writes a length prefixed byte slice, the counterpart of `Deserializer::deserialize_bytes`.
+    fn serialize_bytes(&mut self, value: &[u8]) -> Result<(), SerializationError>;
+    fn serialize_bool(&mut self, value: &bool) -> Result<(), SerializationError>;
+    /// Writes the length prefix of a collection.
+    fn serialize_length(&mut self, value: &usize) -> Result<(), SerializationError>;
+    fn serialize_u8(&mut self, value: &u8) -> Result<(), SerializationError>;
+    fn serialize_i8(&mut self, value: &i8) -> Result<(), SerializationError>;
+    fn serialize_u16(&mut self, value: &u16) -> Result<(), SerializationError>;
+    fn serialize_i16(&mut self, value: &i16) -> Result<(), SerializationError>;
+    fn serialize_u32(&mut self, value: &u32) -> Result<(), SerializationError>;
+    fn serialize_i32(&mut self, value: &i32) -> Result<(), SerializationError>;
+    fn serialize_u64(&mut self, value: &u64) -> Result<(), SerializationError>;
+    fn serialize_i64(&mut self, value: &i64) -> Result<(), SerializationError>;
+    fn serialize_f32(&mut self, value: &f32) -> Result<(), SerializationError>;
+    fn serialize_f64(&mut self, value: &f64) -> Result<(), SerializationError>;
+
+    /// Writes the lowest `bits` bits of `value`, `bits` can be at most 64.
+    /// Byte aligned serializers round up to whole bytes.
+    fn serialize_bits(&mut self, value: &u64, bits: u32) -> Result<(), SerializationError>;
+
+    /// Writes a value in the inclusive range `min..=max` using only as many bits as the range needs.
+    fn serialize_ranged(&mut self, value: &i64, min: i64, max: i64) -> Result<(), SerializationError> {
+        if *value < min || *value > max {
+            return Err(SerializationError::ValueOutOfRange { value: *value, min, max });
+        }
+
+        let offset = value.wrapping_sub(min) as u64;
+        self.serialize_bits(&offset, bits_required(min, max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_calculates_the_bits_required_for_a_range() {
+        assert_eq!(bits_required(0, 0), 0);
+        assert_eq!(bits_required(0, 1), 1);
+        assert_eq!(bits_required(0, 100), 7);
+        assert_eq!(bits_required(-128, 127), 8);
+        assert_eq!(bits_required(0, 128), 8);
+        assert_eq!(bits_required(i64::MIN, i64::MAX), 64);
+    }
+
+    #[test]
+    fn it_checks_that_values_fit_into_bits() {
+        assert!(check_bits(31, 5).is_ok());
+        assert!(check_bits(u64::MAX, 64).is_ok());
+        assert!(matches!(check_bits(32, 5), Err(SerializationError::BitsOverflow { value: 32, bits: 5 })));
+        assert!(matches!(check_bits(0, 65), Err(SerializationError::TooManyBits { bits: 65 })));
+    }
 }
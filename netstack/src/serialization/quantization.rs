@@ -0,0 +1,407 @@
+use super::{Deserializer, Serializer, SerializationError, bits_required};
+
+const QUATERNION_INDEX_BITS: u32 = 2;
+const MAX_COMPONENT_BITS: u32 = 24;
+
+/// Quantizes `f32` values within `min..=max` to a fixed point representation with the given precision.
+///
+/// Values are written with the smallest number of bits that can represent every step between `min`
+/// and `max`. A decoded value is at most `precision / 2` away from the original value, plus the
+/// rounding error of `f32` itself. Finite values outside of the range are clamped to `min` or `max`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FloatQuantization {
+    min: f64,
+    max: f64,
+    step_size: f64,
+    steps: u64,
+    bits: u32,
+}
+
+impl FloatQuantization {
+    pub fn new(min: f32, max: f32, precision: f32) -> Result<Self, SerializationError> {
+        if !min.is_finite() || !max.is_finite() || !precision.is_finite() || min >= max || precision <= 0.0 {
+            return Err(SerializationError::InvalidQuantization);
+        }
+
+        let min = f64::from(min);
+        let max = f64::from(max);
+        let precision = f64::from(precision);
+
+        let steps = ((max - min) / precision).ceil();
+        if steps >= (1u64 << 53) as f64 {
+            return Err(SerializationError::InvalidQuantization);
+        }
+
+        // spread the steps evenly so both min and max can be represented exactly
+        let steps = (steps as u64).max(1);
+        let step_size = (max - min) / steps as f64;
+
+        Ok(Self {
+            min,
+            max,
+            step_size,
+            steps,
+            bits: bits_required(0, steps as i64),
+        })
+    }
+
+    /// Returns the number of bits a single value takes up in a bit packed serializer.
+    pub fn get_bits(&self) -> u32 {
+        self.bits
+    }
+
+    pub fn serialize(&self, value: &f32, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        if !value.is_finite() {
+            return Err(SerializationError::NotQuantizable);
+        }
+
+        let value = f64::from(*value).clamp(self.min, self.max);
+        let step = ((value - self.min) / self.step_size).round() as u64;
+
+        serializer.serialize_bits(&step.min(self.steps), self.bits)
+    }
+
+    pub fn deserialize<'de>(&self, deserializer: &mut impl Deserializer<'de>) -> Result<f32, SerializationError> {
+        let step = deserializer.deserialize_bits(self.bits)?;
+
+        if step > self.steps {
+            return Err(SerializationError::ValueOutOfRange { value: step as i64, min: 0, max: self.steps as i64 });
+        }
+
+        let value = self.min + step as f64 * self.step_size;
+        Ok(value.min(self.max) as f32)
+    }
+}
+
+/// Quantizes unit vectors using an octahedral mapping with `bits` bits per component.
+///
+/// The direction is projected onto an octahedron which is unfolded onto a square, so only two
+/// components are written. Vectors are normalized before encoding, decoded vectors always have unit
+/// length. The angle between the original and the decoded direction is below `2^(3 - bits)` radians,
+/// e.g. about 1.8° with 8 bits and 0.007° with 16 bits per component.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct UnitVectorQuantization {
+    bits: u32,
+}
+
+impl UnitVectorQuantization {
+    pub fn new(bits: u32) -> Result<Self, SerializationError> {
+        if !(2..=MAX_COMPONENT_BITS).contains(&bits) {
+            return Err(SerializationError::InvalidQuantization);
+        }
+
+        Ok(Self {
+            bits,
+        })
+    }
+
+    /// Returns the number of bits a single vector takes up in a bit packed serializer.
+    pub fn get_bits(&self) -> u32 {
+        self.bits * 2
+    }
+
+    pub fn serialize(&self, value: &[f32; 3], serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        let [x, y, z] = [f64::from(value[0]), f64::from(value[1]), f64::from(value[2])];
+        let length = x.abs() + y.abs() + z.abs();
+
+        if !length.is_finite() || length == 0.0 {
+            return Err(SerializationError::NotQuantizable);
+        }
+
+        let (mut u, mut v) = (x / length, y / length);
+
+        // fold the lower half of the octahedron over the upper half
+        if z < 0.0 {
+            let folded_u = (1.0 - v.abs()) * sign(u);
+            let folded_v = (1.0 - u.abs()) * sign(v);
+            u = folded_u;
+            v = folded_v;
+        }
+
+        serializer.serialize_bits(&quantize_unit(u, self.bits), self.bits)?;
+        serializer.serialize_bits(&quantize_unit(v, self.bits), self.bits)
+    }
+
+    pub fn deserialize<'de>(&self, deserializer: &mut impl Deserializer<'de>) -> Result<[f32; 3], SerializationError> {
+        let mut u = dequantize_unit(deserializer.deserialize_bits(self.bits)?, self.bits);
+        let mut v = dequantize_unit(deserializer.deserialize_bits(self.bits)?, self.bits);
+        let z = 1.0 - u.abs() - v.abs();
+
+        if z < 0.0 {
+            let unfolded_u = (1.0 - v.abs()) * sign(u);
+            let unfolded_v = (1.0 - u.abs()) * sign(v);
+            u = unfolded_u;
+            v = unfolded_v;
+        }
+
+        let length = (u * u + v * v + z * z).sqrt();
+        Ok([(u / length) as f32, (v / length) as f32, (z / length) as f32])
+    }
+}
+
+/// Quantizes rotations stored as `[x, y, z, w]` quaternions with the smallest three method.
+///
+/// The largest component is dropped and reconstructed from the other three, which all lie within
+/// `±1/sqrt(2)` and are written with `bits` bits each, plus 2 bits for the index of the dropped
+/// component. Quaternions are normalized before encoding and `q` and `-q` describe the same rotation,
+/// so the decoded quaternion may have all signs flipped. Every component of the decoded quaternion is
+/// within `3 / (sqrt(2) * (2^bits - 2))` of the original, e.g. about 0.0042 with 9 bits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct QuaternionQuantization {
+    bits: u32,
+}
+
+impl QuaternionQuantization {
+    pub fn new(bits: u32) -> Result<Self, SerializationError> {
+        if !(2..=MAX_COMPONENT_BITS).contains(&bits) {
+            return Err(SerializationError::InvalidQuantization);
+        }
+
+        Ok(Self {
+            bits,
+        })
+    }
+
+    /// Returns the number of bits a single quaternion takes up in a bit packed serializer.
+    pub fn get_bits(&self) -> u32 {
+        QUATERNION_INDEX_BITS + self.bits * 3
+    }
+
+    pub fn serialize(&self, value: &[f32; 4], serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        let components = [f64::from(value[0]), f64::from(value[1]), f64::from(value[2]), f64::from(value[3])];
+        let length = components.iter().map(|c| c * c).sum::<f64>().sqrt();
+
+        if !length.is_finite() || length == 0.0 {
+            return Err(SerializationError::NotQuantizable);
+        }
+
+        let mut largest = 0;
+        for i in 1..4 {
+            if components[i].abs() > components[largest].abs() {
+                largest = i;
+            }
+        }
+
+        // q and -q are the same rotation, flip the signs so the dropped component is positive
+        let scale = if components[largest] < 0.0 { -1.0 / length } else { 1.0 / length };
+
+        serializer.serialize_bits(&(largest as u64), QUATERNION_INDEX_BITS)?;
+
+        for (i, component) in components.iter().enumerate() {
+            if i != largest {
+                let normalized = component * scale * std::f64::consts::SQRT_2;
+                serializer.serialize_bits(&quantize_unit(normalized, self.bits), self.bits)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn deserialize<'de>(&self, deserializer: &mut impl Deserializer<'de>) -> Result<[f32; 4], SerializationError> {
+        let largest = deserializer.deserialize_bits(QUATERNION_INDEX_BITS)? as usize;
+        let mut components = [0.0f64; 4];
+        let mut sum = 0.0;
+
+        for (i, component) in components.iter_mut().enumerate() {
+            if i != largest {
+                let value = dequantize_unit(deserializer.deserialize_bits(self.bits)?, self.bits) / std::f64::consts::SQRT_2;
+                *component = value;
+                sum += value * value;
+            }
+        }
+
+        components[largest] = (1.0 - sum).max(0.0).sqrt();
+
+        Ok([components[0] as f32, components[1] as f32, components[2] as f32, components[3] as f32])
+    }
+}
+
+fn sign(value: f64) -> f64 {
+    if value >= 0.0 { 1.0 } else { -1.0 }
+}
+
+/// Maps a value in `-1..=1` onto `0..=2^bits - 2`, using an odd number of steps so zero is exact.
+fn quantize_unit(value: f64, bits: u32) -> u64 {
+    let max = ((1u64 << bits) - 2) as f64;
+    let value = value.clamp(-1.0, 1.0);
+
+    ((value + 1.0) * 0.5 * max).round() as u64
+}
+
+fn dequantize_unit(value: u64, bits: u32) -> f64 {
+    let max = ((1u64 << bits) - 2) as f64;
+
+    (value as f64 / max * 2.0 - 1.0).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::{BitReader, BitWriter, Reader, Writer};
+
+    // deterministic pseudo random numbers in -1..1, good enough to cover the unit sphere
+    fn random_values(count: usize) -> Vec<f32> {
+        let mut state: u64 = 0x853c_49e6_748f_ea9b;
+
+        (0..count).map(|_| {
+            state = state.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1_442_695_040_888_963_407);
+            ((state >> 40) as f32 / (1u64 << 24) as f32) * 2.0 - 1.0
+        }).collect()
+    }
+
+    fn angle_between(a: [f32; 3], b: [f32; 3]) -> f64 {
+        let length_a = a.iter().map(|c| f64::from(*c).powi(2)).sum::<f64>().sqrt();
+        let length_b = b.iter().map(|c| f64::from(*c).powi(2)).sum::<f64>().sqrt();
+        let dot = a.iter().zip(b.iter()).map(|(a, b)| f64::from(*a) * f64::from(*b)).sum::<f64>();
+
+        (dot / (length_a * length_b)).clamp(-1.0, 1.0).acos()
+    }
+
+    #[test]
+    fn it_rejects_invalid_parameters() {
+        assert!(matches!(FloatQuantization::new(1.0, 0.0, 0.1), Err(SerializationError::InvalidQuantization)));
+        assert!(matches!(FloatQuantization::new(0.0, 1.0, 0.0), Err(SerializationError::InvalidQuantization)));
+        assert!(matches!(FloatQuantization::new(0.0, f32::INFINITY, 0.1), Err(SerializationError::InvalidQuantization)));
+        assert!(matches!(FloatQuantization::new(-1e30, 1e30, 1e-30), Err(SerializationError::InvalidQuantization)));
+        assert!(matches!(UnitVectorQuantization::new(1), Err(SerializationError::InvalidQuantization)));
+        assert!(matches!(QuaternionQuantization::new(25), Err(SerializationError::InvalidQuantization)));
+    }
+
+    #[test]
+    fn it_calculates_the_bits_of_a_float_quantization() {
+        assert_eq!(FloatQuantization::new(0.0, 100.0, 1.0).unwrap().get_bits(), 7);
+        assert_eq!(FloatQuantization::new(-512.0, 512.0, 0.01).unwrap().get_bits(), 17);
+    }
+
+    #[test]
+    fn it_round_trips_quantized_floats_within_half_the_precision() {
+        let quantization = FloatQuantization::new(-512.0, 512.0, 0.01).unwrap();
+        let values: Vec<f32> = random_values(2000).iter().map(|v| v * 512.0).chain(vec![-512.0, 512.0, 0.0]).collect();
+
+        let mut buffer = [0; 8192];
+        let mut writer = BitWriter::new(&mut buffer);
+        for value in &values {
+            quantization.serialize(value, &mut writer).unwrap();
+        }
+        assert_eq!(writer.get_bit_position(), values.len() * 17);
+
+        let mut reader = BitReader::new(&buffer);
+        for value in &values {
+            let decoded = quantization.deserialize(&mut reader).unwrap();
+            assert!((decoded - value).abs() <= 0.005 + 512.0 * f32::EPSILON, "{} decoded as {}", value, decoded);
+        }
+    }
+
+    #[test]
+    fn it_clamps_floats_outside_of_the_range() {
+        let quantization = FloatQuantization::new(0.0, 1.0, 0.3).unwrap();
+        let mut buffer = [0; 8];
+        let mut writer = Writer::new(&mut buffer);
+
+        quantization.serialize(&-5.0, &mut writer).unwrap();
+        quantization.serialize(&5.0, &mut writer).unwrap();
+        assert!(matches!(quantization.serialize(&f32::NAN, &mut writer), Err(SerializationError::NotQuantizable)));
+
+        let mut reader = Reader::new(&buffer);
+        assert_eq!(quantization.deserialize(&mut reader).unwrap(), 0.0);
+        assert_eq!(quantization.deserialize(&mut reader).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn it_rejects_quantized_floats_beyond_the_last_step() {
+        let quantization = FloatQuantization::new(0.0, 100.0, 1.0).unwrap();
+        let buffer = [0x7f];
+        let mut reader = BitReader::new(&buffer);
+
+        assert!(matches!(quantization.deserialize(&mut reader), Err(SerializationError::ValueOutOfRange { .. })));
+    }
+
+    #[test]
+    fn it_round_trips_unit_vectors_within_the_documented_angle() {
+        let values = random_values(3 * 2000);
+        let mut vectors: Vec<[f32; 3]> = values.chunks(3).map(|c| [c[0], c[1], c[2]]).collect();
+        vectors.extend_from_slice(&[
+            [1.0, 0.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0],
+            [0.0, -1.0, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0, -1.0],
+        ]);
+
+        for bits in &[4, 8, 10, 12, 16] {
+            let quantization = UnitVectorQuantization::new(*bits).unwrap();
+            let bound = 2f64.powi(3 - *bits as i32);
+
+            for vector in &vectors {
+                let mut buffer = [0; 8];
+                let mut writer = BitWriter::new(&mut buffer);
+                quantization.serialize(vector, &mut writer).unwrap();
+                assert_eq!(writer.get_bit_position(), quantization.get_bits() as usize);
+
+                let mut reader = BitReader::new(&buffer);
+                let decoded = quantization.deserialize(&mut reader).unwrap();
+                let length = decoded.iter().map(|c| f64::from(*c).powi(2)).sum::<f64>().sqrt();
+
+                assert!((length - 1.0).abs() < 1e-6);
+                assert!(angle_between(*vector, decoded) < bound, "{:?} decoded as {:?} with {} bits", vector, decoded, bits);
+            }
+        }
+    }
+
+    #[test]
+    fn it_rejects_zero_length_vectors() {
+        let quantization = UnitVectorQuantization::new(8).unwrap();
+        let mut buffer = [0; 8];
+        let mut writer = BitWriter::new(&mut buffer);
+
+        assert!(matches!(quantization.serialize(&[0.0; 3], &mut writer), Err(SerializationError::NotQuantizable)));
+        assert!(matches!(quantization.serialize(&[f32::NAN, 0.0, 1.0], &mut writer), Err(SerializationError::NotQuantizable)));
+    }
+
+    #[test]
+    fn it_round_trips_quaternions_within_the_documented_error() {
+        let values = random_values(4 * 2000);
+        let mut quaternions: Vec<[f32; 4]> = values.chunks(4).map(|c| [c[0], c[1], c[2], c[3]]).collect();
+        quaternions.extend_from_slice(&[[0.0, 0.0, 0.0, 1.0], [0.0, 0.0, 0.0, -1.0], [0.5, -0.5, 0.5, -0.5]]);
+
+        for bits in &[6, 9, 12, 16] {
+            let quantization = QuaternionQuantization::new(*bits).unwrap();
+            let bound = 3.0 / (std::f64::consts::SQRT_2 * ((1u64 << bits) - 2) as f64);
+
+            for quaternion in &quaternions {
+                let mut buffer = [0; 16];
+                let mut writer = BitWriter::new(&mut buffer);
+                quantization.serialize(quaternion, &mut writer).unwrap();
+                assert_eq!(writer.get_bit_position(), quantization.get_bits() as usize);
+
+                let mut reader = BitReader::new(&buffer);
+                let decoded = quantization.deserialize(&mut reader).unwrap();
+
+                let length = quaternion.iter().map(|c| f64::from(*c).powi(2)).sum::<f64>().sqrt();
+                let dot: f64 = quaternion.iter().zip(decoded.iter()).map(|(a, b)| f64::from(*a) * f64::from(*b)).sum();
+                let flip = if dot < 0.0 { -1.0 } else { 1.0 };
+
+                for i in 0..4 {
+                    let expected = f64::from(quaternion[i]) / length;
+                    let error = (f64::from(decoded[i]) * flip - expected).abs();
+                    assert!(error <= bound + 1e-6, "{:?} decoded as {:?} with {} bits", quaternion, decoded, bits);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn it_works_with_byte_aligned_serializers() {
+        let float = FloatQuantization::new(-1.0, 1.0, 0.001).unwrap();
+        let vector = UnitVectorQuantization::new(12).unwrap();
+        let quaternion = QuaternionQuantization::new(10).unwrap();
+
+        let mut buffer = [0; 32];
+        let mut writer = Writer::new(&mut buffer);
+        float.serialize(&0.5, &mut writer).unwrap();
+        vector.serialize(&[0.0, 1.0, 0.0], &mut writer).unwrap();
+        quaternion.serialize(&[0.0, 0.0, 0.0, 1.0], &mut writer).unwrap();
+
+        let mut reader = Reader::new(&buffer);
+        assert!((float.deserialize(&mut reader).unwrap() - 0.5).abs() <= 0.0005);
+        assert!(angle_between(vector.deserialize(&mut reader).unwrap(), [0.0, 1.0, 0.0]) < 1e-3);
+        assert_eq!(quaternion.deserialize(&mut reader).unwrap(), [0.0, 0.0, 0.0, 1.0]);
+    }
+}
@@ -1,4 +1,5 @@
-use super::{Deserializer, SerializationError};
+use std::convert::TryFrom;
+use super::{Deserializer, SerializationError, LATEST_VERSION, check_bits};
 use super::zig_zag::decode;
 
 const VARINT_MAX_ITERATIONS: usize = 10;
@@ -6,6 +7,7 @@ const VARINT_MAX_ITERATIONS: usize = 10;
 pub struct Reader<'a> {
     buffer: &'a [u8],
     position: usize,
+    version: u32,
 }
 
 impl<'a> Reader<'a> {
@@ -13,9 +15,26 @@ impl<'a> Reader<'a> {
         Self {
             buffer,
             position: 0,
+            version: LATEST_VERSION,
         }
     }
 
+    /// Reads fields as they were in the given protocol version, see `Deserializer::get_version`.
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Returns the number of bytes read so far.
+    pub fn get_position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns the number of bytes left to read.
+    pub fn remaining(&self) -> usize {
+        self.buffer.len() - self.position
+    }
+
     fn read_var_uint(&mut self) -> Result<u64, SerializationError> {
         let start = self.position;
         let mut bytes_read = 0;
@@ -24,9 +43,14 @@ impl<'a> Reader<'a> {
         let mut shift = 0;
 
         for i in 0..VARINT_MAX_ITERATIONS {
-            let value = self.buffer[start + i];
+            let value = *self.buffer.get(start + i).ok_or(SerializationError::UnexpectedEnd)?;
             bytes_read += 1;
 
+            // the last byte only has room for the single remaining bit of a u64
+            if i == VARINT_MAX_ITERATIONS - 1 && value > 0x1 {
+                return Err(SerializationError::VarUIntOverflow);
+            }
+
             decoded |= ((value & 0x7f) as u64) << shift;
 
             if (value & 0x80) != 0x80 {
@@ -40,42 +64,326 @@ impl<'a> Reader<'a> {
         Err(SerializationError::VarUIntOverflow)
     }
 
+    fn read_bytes(&mut self, length: usize) -> Result<&'a [u8], SerializationError> {
+        if length > self.remaining() {
+            return Err(SerializationError::UnexpectedEnd);
+        }
+
+        let start = self.position;
+        self.position += length;
+
+        Ok(&self.buffer[start..start + length])
+    }
+
     fn read_var_int(&mut self) -> Result<i64, SerializationError> {
         let value = self.read_var_uint()?;
         Ok(decode(value))
     }
 }
 
-impl<'a> Deserializer for Reader<'a> {
+impl<'a> Deserializer<'a> for Reader<'a> {
+    fn get_version(&self) -> u32 {
+        self.version
+    }
+
     fn deserialize_string(&mut self) -> Result<String, SerializationError> {
-        let length = self.read_var_uint()? as usize;
+        Ok(self.deserialize_str()?.to_owned())
+    }
+
+    fn deserialize_str(&mut self) -> Result<&'a str, SerializationError> {
         let start = self.position;
-        let end = self.position + length;
+        let bytes = self.deserialize_bytes()?;
 
-        self.position += length;
+        match std::str::from_utf8(bytes) {
+            Ok(value) => Ok(value),
+            Err(_) => {
+                self.position = start;
+                Err(SerializationError::InvalidUtf8)
+            },
+        }
+    }
+
+    fn deserialize_bytes(&mut self) -> Result<&'a [u8], SerializationError> {
+        let start = self.position;
+        let length = self.read_var_uint()?;
+        let remaining = self.remaining();
+
+        // compare as u64 so a huge length can't wrap around on 32 bit targets
+        if length > remaining as u64 {
+            self.position = start;
+            return Err(SerializationError::LengthTooLarge { length, remaining });
+        }
+
+        self.read_bytes(length as usize)
+    }
+
+    fn deserialize_bool(&mut self) -> Result<bool, SerializationError> {
+        match self.read_bytes(1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => {
+                self.position -= 1;
+                Err(SerializationError::InvalidBool { value })
+            },
+        }
+    }
+
+    fn deserialize_length_bounded(&mut self, min_size: usize, max: usize) -> Result<usize, SerializationError> {
+        let start = self.position;
+        let length = self.read_var_uint()?;
+        let remaining = self.remaining();
+
+        if length > max as u64 {
+            self.position = start;
+            return Err(SerializationError::LengthExceedsMaximum { length: length as usize, max });
+        }
+
+        // compare as u64 so a huge length can't wrap around on 32 bit targets
+        if length.saturating_mul(min_size as u64) > remaining as u64 {
+            self.position = start;
+            return Err(SerializationError::LengthTooLarge { length, remaining });
+        }
 
-        Ok(std::str::from_utf8(&self.buffer[start..end]).unwrap().to_owned())
+        Ok(length as usize)
     }
 
     fn deserialize_u8(&mut self) -> Result<u8, SerializationError> {
-        let position = self.position;
-        self.position += 1;
-        Ok(self.buffer[position])
+        Ok(self.read_bytes(1)?[0])
     }
 
     fn deserialize_i8(&mut self) -> Result<i8, SerializationError> {
-        let position = self.position;
-        self.position += 1;
-
-        Ok(self.buffer[position] as i8)
-    }
-    
-    fn deserialize_u16(&mut self) -> Result<u16, SerializationError> { unimplemented!() }
-    fn deserialize_i16(&mut self) -> Result<i16, SerializationError> { unimplemented!() }
-    fn deserialize_u32(&mut self) -> Result<u32, SerializationError> { unimplemented!() }
-    fn deserialize_i32(&mut self) -> Result<i32, SerializationError> { unimplemented!() }
-    fn deserialize_u64(&mut self) -> Result<u64, SerializationError> { unimplemented!() }
-    fn deserialize_i64(&mut self) -> Result<i64, SerializationError> { unimplemented!() }
-    fn deserialize_f32(&mut self) -> Result<f32, SerializationError> { unimplemented!() }
-    fn deserialize_f64(&mut self) -> Result<f64, SerializationError> { unimplemented!() }
+        Ok(self.read_bytes(1)?[0] as i8)
+    }
+
+    fn deserialize_u16(&mut self) -> Result<u16, SerializationError> {
+        u16::try_from(self.read_var_uint()?).map_err(|_| SerializationError::VarUIntOverflow)
+    }
+
+    fn deserialize_i16(&mut self) -> Result<i16, SerializationError> {
+        i16::try_from(self.read_var_int()?).map_err(|_| SerializationError::VarUIntOverflow)
+    }
+
+    fn deserialize_u32(&mut self) -> Result<u32, SerializationError> {
+        u32::try_from(self.read_var_uint()?).map_err(|_| SerializationError::VarUIntOverflow)
+    }
+
+    fn deserialize_i32(&mut self) -> Result<i32, SerializationError> {
+        i32::try_from(self.read_var_int()?).map_err(|_| SerializationError::VarUIntOverflow)
+    }
+
+    fn deserialize_u64(&mut self) -> Result<u64, SerializationError> {
+        self.read_var_uint()
+    }
+
+    fn deserialize_i64(&mut self) -> Result<i64, SerializationError> {
+        self.read_var_int()
+    }
+
+    fn deserialize_f32(&mut self) -> Result<f32, SerializationError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.read_bytes(4)?);
+        Ok(f32::from_le_bytes(bytes))
+    }
+
+    fn deserialize_f64(&mut self) -> Result<f64, SerializationError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.read_bytes(8)?);
+        Ok(f64::from_le_bytes(bytes))
+    }
+
+    fn deserialize_bits(&mut self, bits: u32) -> Result<u64, SerializationError> {
+        check_bits(0, bits)?;
+
+        let start = self.position;
+        let length = bits.div_ceil(8) as usize;
+        let mut bytes = [0; 8];
+        bytes[0..length].copy_from_slice(self.read_bytes(length)?);
+        let value = u64::from_le_bytes(bytes);
+
+        if let Err(error) = check_bits(value, bits) {
+            self.position = start;
+            return Err(error);
+        }
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_reads_var_uints() {
+        let buffer = [0x01, 0xac, 0x02];
+        let mut reader = Reader::new(&buffer);
+
+        assert_eq!(reader.deserialize_u32().unwrap(), 1);
+        assert_eq!(reader.deserialize_u32().unwrap(), 300);
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn it_rejects_reads_from_an_empty_buffer() {
+        let mut reader = Reader::new(&[]);
+
+        assert!(matches!(reader.deserialize_u8(), Err(SerializationError::UnexpectedEnd)));
+        assert!(matches!(reader.deserialize_i8(), Err(SerializationError::UnexpectedEnd)));
+        assert!(matches!(reader.deserialize_u16(), Err(SerializationError::UnexpectedEnd)));
+        assert!(matches!(reader.deserialize_i64(), Err(SerializationError::UnexpectedEnd)));
+        assert!(matches!(reader.deserialize_f32(), Err(SerializationError::UnexpectedEnd)));
+        assert!(matches!(reader.deserialize_f64(), Err(SerializationError::UnexpectedEnd)));
+        assert!(matches!(reader.deserialize_string(), Err(SerializationError::UnexpectedEnd)));
+    }
+
+    #[test]
+    fn it_rejects_a_truncated_var_uint() {
+        let buffer = [0xff, 0xff];
+        let mut reader = Reader::new(&buffer);
+
+        assert!(matches!(reader.deserialize_u64(), Err(SerializationError::UnexpectedEnd)));
+        assert_eq!(reader.get_position(), 0);
+    }
+
+    #[test]
+    fn it_rejects_a_truncated_float() {
+        let buffer = [0x0, 0x0, 0x0, 0x0, 0x0];
+        let mut reader = Reader::new(&buffer);
+
+        assert!(matches!(reader.deserialize_f64(), Err(SerializationError::UnexpectedEnd)));
+        assert_eq!(reader.get_position(), 0);
+    }
+
+    #[test]
+    fn it_rejects_var_uints_that_overflow_a_u64() {
+        let buffer = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02];
+        let mut reader = Reader::new(&buffer);
+
+        assert!(matches!(reader.deserialize_u64(), Err(SerializationError::VarUIntOverflow)));
+
+        let buffer = [0xff; 16];
+        let mut reader = Reader::new(&buffer);
+
+        assert!(matches!(reader.deserialize_u64(), Err(SerializationError::VarUIntOverflow)));
+    }
+
+    #[test]
+    fn it_rejects_var_uints_that_overflow_the_target_type() {
+        let buffer = [0x80, 0x80, 0x04];
+        let mut reader = Reader::new(&buffer);
+
+        assert!(matches!(reader.deserialize_u16(), Err(SerializationError::VarUIntOverflow)));
+    }
+
+    #[test]
+    fn it_rejects_a_string_longer_than_the_buffer() {
+        let buffer = [0x05, b'a', b'b'];
+        let mut reader = Reader::new(&buffer);
+
+        match reader.deserialize_string() {
+            Err(SerializationError::LengthTooLarge { length, remaining }) => {
+                assert_eq!(length, 5);
+                assert_eq!(remaining, 2);
+            },
+            _ => panic!("expected LengthTooLarge"),
+        }
+    }
+
+    #[test]
+    fn it_rejects_a_string_with_a_huge_length() {
+        let buffer = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        let mut reader = Reader::new(&buffer);
+
+        assert!(matches!(reader.deserialize_string(), Err(SerializationError::LengthTooLarge { .. })));
+    }
+
+    #[test]
+    fn it_rejects_invalid_utf8() {
+        let buffer = [0x02, 0xc3, 0x28];
+        let mut reader = Reader::new(&buffer);
+
+        assert!(matches!(reader.deserialize_string(), Err(SerializationError::InvalidUtf8)));
+        assert_eq!(reader.get_position(), 0);
+    }
+
+    #[test]
+    fn it_borrows_strings_and_bytes_from_the_buffer() {
+        let buffer = [0x02, b'a', b'b', 0x03, 0x1, 0x2, 0x3];
+        let mut reader = Reader::new(&buffer);
+
+        let string = reader.deserialize_str().unwrap();
+        let bytes = reader.deserialize_bytes().unwrap();
+
+        assert_eq!(string, "ab");
+        assert_eq!(bytes, &[0x1, 0x2, 0x3]);
+        assert_eq!(string.as_ptr(), buffer[1..].as_ptr());
+        assert_eq!(bytes.as_ptr(), buffer[4..].as_ptr());
+    }
+
+    #[test]
+    fn it_rejects_invalid_bools() {
+        let buffer = [0x01, 0x00, 0x02];
+        let mut reader = Reader::new(&buffer);
+
+        assert_eq!(reader.deserialize_bool().unwrap(), true);
+        assert_eq!(reader.deserialize_bool().unwrap(), false);
+        assert!(matches!(reader.deserialize_bool(), Err(SerializationError::InvalidBool { value: 2 })));
+    }
+
+    #[test]
+    fn it_rejects_a_collection_length_longer_than_the_buffer() {
+        let buffer = [0x03, 0x01, 0x02];
+        let mut reader = Reader::new(&buffer);
+
+        assert!(matches!(reader.deserialize_length(), Err(SerializationError::LengthTooLarge { length: 3, remaining: 2 })));
+        assert_eq!(reader.get_position(), 0);
+    }
+
+    #[test]
+    fn it_bounds_collection_lengths_by_the_element_size_and_maximum() {
+        let buffer = [0x03, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06];
+        let mut reader = Reader::new(&buffer);
+
+        assert!(matches!(reader.deserialize_length_bounded(3, usize::MAX), Err(SerializationError::LengthTooLarge { length: 3, remaining: 6 })));
+        assert!(matches!(reader.deserialize_length_bounded(0, 2), Err(SerializationError::LengthExceedsMaximum { length: 3, max: 2 })));
+        assert_eq!(reader.get_position(), 0);
+        assert_eq!(reader.deserialize_length_bounded(2, 3).unwrap(), 3);
+    }
+
+    #[test]
+    fn it_rejects_bits_that_exceed_the_bit_count() {
+        let buffer = [0x20];
+        let mut reader = Reader::new(&buffer);
+
+        assert!(matches!(reader.deserialize_bits(5), Err(SerializationError::BitsOverflow { value: 32, bits: 5 })));
+        assert!(matches!(reader.deserialize_bits(65), Err(SerializationError::TooManyBits { bits: 65 })));
+        assert_eq!(reader.get_position(), 0);
+    }
+
+    #[test]
+    fn it_rejects_ranged_values_outside_of_the_range() {
+        let buffer = [0x7f];
+        let mut reader = Reader::new(&buffer);
+
+        assert!(matches!(reader.deserialize_ranged(0, 100), Err(SerializationError::ValueOutOfRange { value: 127, .. })));
+    }
+
+    #[test]
+    fn it_never_panics_on_arbitrary_input() {
+        let mut buffer = [0; 32];
+
+        for seed in 0..512u32 {
+            for (i, byte) in buffer.iter_mut().enumerate() {
+                *byte = (seed.wrapping_mul(2_654_435_761).wrapping_add(i as u32 * 40_503) >> 13) as u8;
+            }
+
+            for length in 0..buffer.len() {
+                let mut reader = Reader::new(&buffer[0..length]);
+                while reader.deserialize_string().is_ok() {}
+                let _ = reader.deserialize_u64();
+                let _ = reader.deserialize_i32();
+                let _ = reader.deserialize_f64();
+            }
+        }
+    }
 }
@@ -0,0 +1,660 @@
+use std::fmt;
+use ::serde::{de, ser};
+use ::serde::de::IntoDeserializer;
+use super::{Deserialize, Deserializer, Serialize, Serializer, SerializationError};
+
+/// Wraps a type implementing serde's `Serialize` and `Deserialize` so it can be used wherever
+/// netstack expects its own traits, for example with `OutgoingPacket::serialize` and
+/// `Payload::deserialize`.
+///
+/// Values use the same compact encoding as netstack's own implementations. The format is not
+/// self-describing, so `deserialize_any` and skipping unknown fields are not supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Serde<T>(pub T);
+
+impl<T> Serialize for Serde<T> where T: ser::Serialize {
+    fn serialize(&self, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        serialize_serde(&self.0, serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for Serde<T> where T: de::Deserialize<'de> {
+    type Item = Serde<T>;
+
+    fn deserialize(deserializer: &mut impl Deserializer<'de>) -> Result<Self::Item, SerializationError> {
+        Ok(Serde(deserialize_serde(deserializer)?))
+    }
+}
+
+/// Serializes a serde type with a netstack `Serializer`.
+pub fn serialize_serde<T>(value: &T, serializer: &mut impl Serializer) -> Result<(), SerializationError>
+    where T: ser::Serialize + ?Sized
+{
+    value.serialize(&mut SerdeSerializer::new(serializer)).map_err(|error| error.0)
+}
+
+/// Deserializes a serde type with a netstack `Deserializer`.
+pub fn deserialize_serde<'de, T>(deserializer: &mut impl Deserializer<'de>) -> Result<T, SerializationError>
+    where T: de::Deserialize<'de>
+{
+    T::deserialize(&mut SerdeDeserializer::new(deserializer)).map_err(|error| error.0)
+}
+
+/// The error type of `SerdeSerializer` and `SerdeDeserializer`, serde requires it to implement
+/// `std::error::Error`.
+#[derive(Debug)]
+pub struct SerdeError(pub SerializationError);
+
+impl fmt::Display for SerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for SerdeError {}
+
+impl ser::Error for SerdeError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        SerdeError(SerializationError::Custom { message: message.to_string() })
+    }
+}
+
+impl de::Error for SerdeError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        SerdeError(SerializationError::Custom { message: message.to_string() })
+    }
+}
+
+impl From<SerializationError> for SerdeError {
+    fn from(error: SerializationError) -> Self {
+        SerdeError(error)
+    }
+}
+
+fn unsupported(what: &str) -> SerdeError {
+    SerdeError(SerializationError::Custom { message: format!("{} is not supported by the netstack encoding", what) })
+}
+
+/// A serde `Serializer` writing netstack's wire format through any netstack `Serializer`.
+pub struct SerdeSerializer<'a, S> {
+    serializer: &'a mut S,
+}
+
+impl<'a, S> SerdeSerializer<'a, S> where S: Serializer {
+    pub fn new(serializer: &'a mut S) -> Self {
+        Self {
+            serializer,
+        }
+    }
+}
+
+impl<'a, 'b, S> ser::Serializer for &'b mut SerdeSerializer<'a, S> where S: Serializer {
+    type Ok = ();
+    type Error = SerdeError;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, value: bool) -> Result<(), SerdeError> {
+        Ok(self.serializer.serialize_bool(&value)?)
+    }
+
+    fn serialize_i8(self, value: i8) -> Result<(), SerdeError> {
+        Ok(self.serializer.serialize_i8(&value)?)
+    }
+
+    fn serialize_i16(self, value: i16) -> Result<(), SerdeError> {
+        Ok(self.serializer.serialize_i16(&value)?)
+    }
+
+    fn serialize_i32(self, value: i32) -> Result<(), SerdeError> {
+        Ok(self.serializer.serialize_i32(&value)?)
+    }
+
+    fn serialize_i64(self, value: i64) -> Result<(), SerdeError> {
+        Ok(self.serializer.serialize_i64(&value)?)
+    }
+
+    fn serialize_u8(self, value: u8) -> Result<(), SerdeError> {
+        Ok(self.serializer.serialize_u8(&value)?)
+    }
+
+    fn serialize_u16(self, value: u16) -> Result<(), SerdeError> {
+        Ok(self.serializer.serialize_u16(&value)?)
+    }
+
+    fn serialize_u32(self, value: u32) -> Result<(), SerdeError> {
+        Ok(self.serializer.serialize_u32(&value)?)
+    }
+
+    fn serialize_u64(self, value: u64) -> Result<(), SerdeError> {
+        Ok(self.serializer.serialize_u64(&value)?)
+    }
+
+    fn serialize_f32(self, value: f32) -> Result<(), SerdeError> {
+        Ok(self.serializer.serialize_f32(&value)?)
+    }
+
+    fn serialize_f64(self, value: f64) -> Result<(), SerdeError> {
+        Ok(self.serializer.serialize_f64(&value)?)
+    }
+
+    fn serialize_char(self, value: char) -> Result<(), SerdeError> {
+        Ok(self.serializer.serialize_u32(&(value as u32))?)
+    }
+
+    fn serialize_str(self, value: &str) -> Result<(), SerdeError> {
+        Ok(self.serializer.serialize_string(value)?)
+    }
+
+    fn serialize_bytes(self, value: &[u8]) -> Result<(), SerdeError> {
+        Ok(self.serializer.serialize_bytes(value)?)
+    }
+
+    fn serialize_none(self) -> Result<(), SerdeError> {
+        Ok(self.serializer.serialize_bool(&false)?)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<(), SerdeError> where T: ser::Serialize + ?Sized {
+        self.serializer.serialize_bool(&true)?;
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), SerdeError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, variant_index: u32, _variant: &'static str) -> Result<(), SerdeError> {
+        Ok(self.serializer.serialize_u32(&variant_index)?)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<(), SerdeError> where T: ser::Serialize + ?Sized {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(self, _name: &'static str, variant_index: u32, _variant: &'static str, value: &T) -> Result<(), SerdeError>
+        where T: ser::Serialize + ?Sized
+    {
+        self.serializer.serialize_u32(&variant_index)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, length: Option<usize>) -> Result<Self, SerdeError> {
+        match length {
+            Some(length) => {
+                self.serializer.serialize_length(&length)?;
+                Ok(self)
+            },
+            None => Err(unsupported("A sequence of unknown length")),
+        }
+    }
+
+    fn serialize_tuple(self, _length: usize) -> Result<Self, SerdeError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _length: usize) -> Result<Self, SerdeError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, variant_index: u32, _variant: &'static str, _length: usize) -> Result<Self, SerdeError> {
+        self.serializer.serialize_u32(&variant_index)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, length: Option<usize>) -> Result<Self, SerdeError> {
+        match length {
+            Some(length) => {
+                self.serializer.serialize_length(&length)?;
+                Ok(self)
+            },
+            None => Err(unsupported("A map of unknown length")),
+        }
+    }
+
+    fn serialize_struct(self, _name: &'static str, _length: usize) -> Result<Self, SerdeError> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, variant_index: u32, _variant: &'static str, _length: usize) -> Result<Self, SerdeError> {
+        self.serializer.serialize_u32(&variant_index)?;
+        Ok(self)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+impl<'a, 'b, S> ser::SerializeSeq for &'b mut SerdeSerializer<'a, S> where S: Serializer {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), SerdeError> where T: ser::Serialize + ?Sized {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b, S> ser::SerializeTuple for &'b mut SerdeSerializer<'a, S> where S: Serializer {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), SerdeError> where T: ser::Serialize + ?Sized {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b, S> ser::SerializeTupleStruct for &'b mut SerdeSerializer<'a, S> where S: Serializer {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), SerdeError> where T: ser::Serialize + ?Sized {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b, S> ser::SerializeTupleVariant for &'b mut SerdeSerializer<'a, S> where S: Serializer {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), SerdeError> where T: ser::Serialize + ?Sized {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b, S> ser::SerializeMap for &'b mut SerdeSerializer<'a, S> where S: Serializer {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), SerdeError> where T: ser::Serialize + ?Sized {
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), SerdeError> where T: ser::Serialize + ?Sized {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b, S> ser::SerializeStruct for &'b mut SerdeSerializer<'a, S> where S: Serializer {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<(), SerdeError> where T: ser::Serialize + ?Sized {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+}
+
+impl<'a, 'b, S> ser::SerializeStructVariant for &'b mut SerdeSerializer<'a, S> where S: Serializer {
+    type Ok = ();
+    type Error = SerdeError;
+
+    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<(), SerdeError> where T: ser::Serialize + ?Sized {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+}
+
+/// A serde `Deserializer` reading netstack's wire format through any netstack `Deserializer`.
+pub struct SerdeDeserializer<'a, D> {
+    deserializer: &'a mut D,
+}
+
+impl<'a, D> SerdeDeserializer<'a, D> {
+    pub fn new(deserializer: &'a mut D) -> Self {
+        Self {
+            deserializer,
+        }
+    }
+}
+
+impl<'de, 'a, 'b, D> de::Deserializer<'de> for &'b mut SerdeDeserializer<'a, D> where D: Deserializer<'de> {
+    type Error = SerdeError;
+
+    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        Err(unsupported("Deserializing without knowing the type"))
+    }
+
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_bool(self.deserializer.deserialize_bool()?)
+    }
+
+    fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_i8(self.deserializer.deserialize_i8()?)
+    }
+
+    fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_i16(self.deserializer.deserialize_i16()?)
+    }
+
+    fn deserialize_i32<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_i32(self.deserializer.deserialize_i32()?)
+    }
+
+    fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_i64(self.deserializer.deserialize_i64()?)
+    }
+
+    fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_u8(self.deserializer.deserialize_u8()?)
+    }
+
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_u16(self.deserializer.deserialize_u16()?)
+    }
+
+    fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_u32(self.deserializer.deserialize_u32()?)
+    }
+
+    fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_u64(self.deserializer.deserialize_u64()?)
+    }
+
+    fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_f32(self.deserializer.deserialize_f32()?)
+    }
+
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_f64(self.deserializer.deserialize_f64()?)
+    }
+
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        let value = self.deserializer.deserialize_u32()?;
+
+        match std::char::from_u32(value) {
+            Some(value) => visitor.visit_char(value),
+            None => Err(de::Error::invalid_value(de::Unexpected::Unsigned(u64::from(value)), &"a unicode scalar value")),
+        }
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_borrowed_str(self.deserializer.deserialize_str()?)
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_borrowed_bytes(self.deserializer.deserialize_bytes()?)
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        if self.deserializer.deserialize_bool()? {
+            visitor.visit_some(self)
+        } else {
+            visitor.visit_none()
+        }
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        let remaining = self.deserializer.deserialize_length()?;
+        visitor.visit_seq(Access { deserializer: self, remaining })
+    }
+
+    fn deserialize_tuple<V>(self, length: usize, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_seq(Access { deserializer: self, remaining: length })
+    }
+
+    fn deserialize_tuple_struct<V>(self, _name: &'static str, length: usize, visitor: V) -> Result<V::Value, SerdeError>
+        where V: de::Visitor<'de>
+    {
+        visitor.visit_seq(Access { deserializer: self, remaining: length })
+    }
+
+    fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        let remaining = self.deserializer.deserialize_length()?;
+        visitor.visit_map(Access { deserializer: self, remaining })
+    }
+
+    fn deserialize_struct<V>(self, _name: &'static str, fields: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError>
+        where V: de::Visitor<'de>
+    {
+        visitor.visit_seq(Access { deserializer: self, remaining: fields.len() })
+    }
+
+    fn deserialize_enum<V>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError>
+        where V: de::Visitor<'de>
+    {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_u32(self.deserializer.deserialize_u32()?)
+    }
+
+    fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        Err(unsupported("Skipping values"))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+struct Access<'a, 'b, D> {
+    deserializer: &'b mut SerdeDeserializer<'a, D>,
+    remaining: usize,
+}
+
+impl<'de, 'a, 'b, D> de::SeqAccess<'de> for Access<'a, 'b, D> where D: Deserializer<'de> {
+    type Error = SerdeError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, SerdeError> where T: de::DeserializeSeed<'de> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.deserializer).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'de, 'a, 'b, D> de::MapAccess<'de> for Access<'a, 'b, D> where D: Deserializer<'de> {
+    type Error = SerdeError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, SerdeError> where K: de::DeserializeSeed<'de> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.deserializer).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, SerdeError> where V: de::DeserializeSeed<'de> {
+        seed.deserialize(&mut *self.deserializer)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+impl<'de, 'a, 'b, D> de::EnumAccess<'de> for &'b mut SerdeDeserializer<'a, D> where D: Deserializer<'de> {
+    type Error = SerdeError;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self), SerdeError> where V: de::DeserializeSeed<'de> {
+        let variant_index = self.deserializer.deserialize_u32()?;
+        let value = seed.deserialize(IntoDeserializer::<SerdeError>::into_deserializer(variant_index))?;
+        Ok((value, self))
+    }
+}
+
+impl<'de, 'a, 'b, D> de::VariantAccess<'de> for &'b mut SerdeDeserializer<'a, D> where D: Deserializer<'de> {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<(), SerdeError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, SerdeError> where T: de::DeserializeSeed<'de> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V>(self, length: usize, visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_seq(Access { deserializer: self, remaining: length })
+    }
+
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value, SerdeError> where V: de::Visitor<'de> {
+        visitor.visit_seq(Access { deserializer: self, remaining: fields.len() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use ::serde::{Deserialize as SerdeDeserialize, Serialize as SerdeSerialize};
+    use super::*;
+    use crate::serialization::{BitReader, BitWriter, Reader, Writer};
+
+    #[derive(Debug, PartialEq, SerdeSerialize, SerdeDeserialize)]
+    enum Action {
+        Idle,
+        Move(f32, f32),
+        Say { text: String },
+        Emote(char),
+    }
+
+    #[derive(Debug, PartialEq, SerdeSerialize, SerdeDeserialize)]
+    struct Player {
+        id: u32,
+        name: String,
+        health: Option<u8>,
+        actions: Vec<Action>,
+        scores: BTreeMap<String, i64>,
+        position: (i16, i16),
+    }
+
+    fn player() -> Player {
+        let mut scores = BTreeMap::new();
+        scores.insert("kills".to_owned(), 12);
+        scores.insert("deaths".to_owned(), -3);
+
+        Player {
+            id: 300,
+            name: "Alice".to_owned(),
+            health: Some(80),
+            actions: vec![Action::Idle, Action::Move(1.0, -2.5), Action::Say { text: "gg".to_owned() }, Action::Emote('ü')],
+            scores,
+            position: (-5, 7),
+        }
+    }
+
+    #[test]
+    fn it_round_trips_serde_types() {
+        let mut buffer = [0; 128];
+        let length = {
+            let mut writer = Writer::new(&mut buffer);
+            Serde(player()).serialize(&mut writer).unwrap();
+            writer.get_position()
+        };
+
+        let mut reader = Reader::new(&buffer[0..length]);
+        assert_eq!(Serde::<Player>::deserialize(&mut reader).unwrap().0, player());
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn it_round_trips_serde_types_bit_packed() {
+        let mut buffer = [0; 128];
+        let length = {
+            let mut writer = BitWriter::new(&mut buffer);
+            serialize_serde(&player(), &mut writer).unwrap();
+            writer.get_position()
+        };
+
+        let mut reader = BitReader::new(&buffer[0..length]);
+        assert_eq!(deserialize_serde::<Player>(&mut reader).unwrap(), player());
+    }
+
+    #[test]
+    fn it_uses_the_netstack_encoding() {
+        let mut buffer = [0; 16];
+        let mut writer = Writer::new(&mut buffer);
+        serialize_serde(&(300u32, Some(-1i32), "ab"), &mut writer).unwrap();
+        let length = writer.get_position();
+
+        let mut expected_buffer = [0; 16];
+        let mut expected = Writer::new(&mut expected_buffer);
+        Serialize::serialize(&(300u32, Some(-1i32), "ab".to_owned()), &mut expected).unwrap();
+        let expected_length = expected.get_position();
+
+        assert_eq!(&buffer[0..length], &expected_buffer[0..expected_length]);
+    }
+
+    #[test]
+    fn it_borrows_strings_from_the_buffer() {
+        let buffer = [0x03, b'a', b'b', b'c'];
+        let mut reader = Reader::new(&buffer);
+
+        let value: &str = deserialize_serde(&mut reader).unwrap();
+        assert_eq!(value, "abc");
+        assert_eq!(value.as_ptr(), buffer[1..].as_ptr());
+    }
+
+    #[test]
+    fn it_reports_netstack_errors() {
+        let buffer = [0x05, b'a'];
+        let mut reader = Reader::new(&buffer);
+
+        assert!(matches!(deserialize_serde::<String>(&mut reader), Err(SerializationError::LengthTooLarge { .. })));
+        assert!(matches!(deserialize_serde::<Action>(&mut Reader::new(&[0x09])), Err(SerializationError::Custom { .. })));
+    }
+}
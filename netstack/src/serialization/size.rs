@@ -0,0 +1,219 @@
+use std::collections::{BTreeMap, HashMap};
+use super::{Deserialize, Deserializer, Serialize, SerializationError};
+
+/// Collections written with a length prefix, their size can be bounded with `#[netstack(max_len = N)]`.
+pub trait LengthPrefixed {
+    /// The largest size of a single element, for maps the size of a key and a value.
+    const MAX_ELEMENT_SIZE: Option<usize>;
+
+    /// Returns the length written as the prefix, the number of bytes for strings.
+    fn get_length(&self) -> usize;
+}
+
+/// Length prefixed types that can be read with the limit of `#[netstack(max_len = N)]`.
+pub trait DeserializeBounded<'de>: Deserialize<'de> {
+    /// Reads a value of at most `max_len` elements, collections fail on the length prefix before any
+    /// element is read.
+    fn deserialize_bounded(deserializer: &mut impl Deserializer<'de>, max_len: usize) -> Result<Self::Item, SerializationError>;
+}
+
+/// Returns the number of bytes a var uint takes up.
+pub const fn var_uint_size(value: u64) -> usize {
+    let significant_bits = 64 - value.leading_zeros() as usize;
+
+    if significant_bits == 0 {
+        1
+    } else {
+        significant_bits.div_ceil(7)
+    }
+}
+
+/// Adds two sizes, the result is unbounded if either of them is.
+pub const fn add_sizes(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (Some(a), Some(b)) => a.checked_add(b),
+        _ => None,
+    }
+}
+
+/// Returns the larger of two sizes, the result is unbounded if either of them is.
+pub const fn max_sizes(a: Option<usize>, b: Option<usize>) -> Option<usize> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if a > b { a } else { b }),
+        _ => None,
+    }
+}
+
+/// Returns the size of `count` values of the given size.
+pub const fn repeat_size(size: Option<usize>, count: usize) -> Option<usize> {
+    match size {
+        Some(size) => size.checked_mul(count),
+        None => None,
+    }
+}
+
+/// Returns the size of a collection with at most `max_len` elements, including its length prefix.
+pub const fn length_prefixed_size(max_len: usize, element_size: Option<usize>) -> Option<usize> {
+    add_sizes(Some(var_uint_size(max_len as u64)), repeat_size(element_size, max_len))
+}
+
+/// Fails the build if values of `T` can be larger than `limit` bytes or have no upper bound.
+///
+/// ```ignore
+/// const _: () = assert_max_encoded_size::<Greeting>(MAX_PAYLOAD_SIZE);
+/// ```
+pub const fn assert_max_encoded_size<T>(limit: usize) where T: Serialize + ?Sized {
+    match T::MAX_ENCODED_SIZE {
+        Some(size) if size <= limit => {},
+        Some(_) => panic!("the maximum encoded size of the type exceeds the limit"),
+        None => panic!("the type has no maximum encoded size, use `#[netstack(max_len = N)]` on unbounded fields"),
+    }
+}
+
+/// Ensures a collection isn't longer than allowed by `#[netstack(max_len = N)]`.
+pub fn check_max_len(length: usize, max: usize) -> Result<(), SerializationError> {
+    if length > max {
+        return Err(SerializationError::LengthExceedsMaximum { length, max });
+    }
+
+    Ok(())
+}
+
+impl LengthPrefixed for String {
+    const MAX_ELEMENT_SIZE: Option<usize> = Some(1);
+
+    fn get_length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl LengthPrefixed for str {
+    const MAX_ELEMENT_SIZE: Option<usize> = Some(1);
+
+    fn get_length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl LengthPrefixed for [u8] {
+    const MAX_ELEMENT_SIZE: Option<usize> = Some(1);
+
+    fn get_length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> LengthPrefixed for Vec<T> where T: Serialize {
+    const MAX_ELEMENT_SIZE: Option<usize> = T::MAX_ENCODED_SIZE;
+
+    fn get_length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<K, V, S> LengthPrefixed for HashMap<K, V, S> where K: Serialize, V: Serialize {
+    const MAX_ELEMENT_SIZE: Option<usize> = add_sizes(K::MAX_ENCODED_SIZE, V::MAX_ENCODED_SIZE);
+
+    fn get_length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<K, V> LengthPrefixed for BTreeMap<K, V> where K: Serialize, V: Serialize {
+    const MAX_ELEMENT_SIZE: Option<usize> = add_sizes(K::MAX_ENCODED_SIZE, V::MAX_ENCODED_SIZE);
+
+    fn get_length(&self) -> usize {
+        self.len()
+    }
+}
+
+// strings and bytes are taken from the buffer in one piece, so they are checked once they were read
+
+impl<'de> DeserializeBounded<'de> for String {
+    fn deserialize_bounded(deserializer: &mut impl Deserializer<'de>, max_len: usize) -> Result<Self::Item, SerializationError> {
+        let value = deserializer.deserialize_string()?;
+        check_max_len(value.len(), max_len)?;
+        Ok(value)
+    }
+}
+
+impl<'de: 'a, 'a> DeserializeBounded<'de> for &'a str {
+    fn deserialize_bounded(deserializer: &mut impl Deserializer<'de>, max_len: usize) -> Result<Self::Item, SerializationError> {
+        let value = deserializer.deserialize_str()?;
+        check_max_len(value.len(), max_len)?;
+        Ok(value)
+    }
+}
+
+impl<'de: 'a, 'a> DeserializeBounded<'de> for &'a [u8] {
+    fn deserialize_bounded(deserializer: &mut impl Deserializer<'de>, max_len: usize) -> Result<Self::Item, SerializationError> {
+        let value = deserializer.deserialize_bytes()?;
+        check_max_len(value.len(), max_len)?;
+        Ok(value)
+    }
+}
+
+impl<T> LengthPrefixed for &T where T: LengthPrefixed + ?Sized {
+    const MAX_ELEMENT_SIZE: Option<usize> = T::MAX_ELEMENT_SIZE;
+
+    fn get_length(&self) -> usize {
+        (**self).get_length()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::{Reader, Writer};
+
+    fn written_size(value: &impl Serialize) -> usize {
+        let mut buffer = [0; 256];
+        let mut writer = Writer::new(&mut buffer);
+        value.serialize(&mut writer).expect("It serializes the value");
+        writer.get_position()
+    }
+
+    #[test]
+    fn it_calculates_var_uint_sizes() {
+        assert_eq!(var_uint_size(0), 1);
+        assert_eq!(var_uint_size(127), 1);
+        assert_eq!(var_uint_size(128), 2);
+        assert_eq!(var_uint_size(u64::MAX), 10);
+    }
+
+    #[test]
+    fn it_bounds_the_size_of_primitives() {
+        assert_eq!(written_size(&u16::MAX), u16::MAX_ENCODED_SIZE.unwrap());
+        assert_eq!(written_size(&i32::MIN), i32::MAX_ENCODED_SIZE.unwrap());
+        assert_eq!(written_size(&u64::MAX), u64::MAX_ENCODED_SIZE.unwrap());
+        assert_eq!(written_size(&i64::MIN), i64::MAX_ENCODED_SIZE.unwrap());
+        assert_eq!(written_size(&f64::MAX), f64::MAX_ENCODED_SIZE.unwrap());
+    }
+
+    #[test]
+    fn it_combines_sizes() {
+        assert_eq!(<(u8, Option<u32>, [bool; 4])>::MAX_ENCODED_SIZE, Some(1 + 6 + 4));
+        assert_eq!(<Option<String>>::MAX_ENCODED_SIZE, None);
+        assert_eq!(<&Box<u16>>::MAX_ENCODED_SIZE, Some(3));
+        assert_eq!(length_prefixed_size(200, Some(3)), Some(2 + 600));
+        assert_eq!(repeat_size(Some(usize::MAX), 2), None);
+        assert_eq!(max_sizes(Some(1), Some(2)), Some(2));
+    }
+
+    #[test]
+    fn it_rejects_a_length_prefix_beyond_the_maximum_before_reading_elements() {
+        let buffer = [0x03, 0x01, 0x02, 0x03];
+        let mut reader = Reader::new(&buffer);
+
+        assert!(matches!(Vec::<u8>::deserialize_bounded(&mut reader, 2), Err(SerializationError::LengthExceedsMaximum { length: 3, max: 2 })));
+        assert_eq!(reader.get_position(), 0);
+        assert!(matches!(String::deserialize_bounded(&mut reader, 2), Err(SerializationError::LengthExceedsMaximum { length: 3, max: 2 })));
+        assert_eq!(Vec::<u8>::deserialize_bounded(&mut Reader::new(&buffer), 3).unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn it_checks_the_maximum_length() {
+        assert!(check_max_len(3, 3).is_ok());
+        assert!(matches!(check_max_len(4, 3), Err(SerializationError::LengthExceedsMaximum { length: 4, max: 3 })));
+    }
+}
@@ -1,8 +1,12 @@
-use super::{Serializer, SerializationError};
+use super::{Serializer, SerializationError, LATEST_VERSION, check_bits};
+use super::zig_zag::encode;
+
+const VARINT_MAX_BYTES: usize = 10;
 
 pub struct Writer<'a> {
     buffer: &'a mut [u8],
     position: usize,
+    version: u32,
 }
 
 impl<'a> Writer<'a> {
@@ -10,20 +14,318 @@ impl<'a> Writer<'a> {
         Self {
             buffer,
             position: 0,
+            version: LATEST_VERSION,
+        }
+    }
+
+    /// Writes fields as they were in the given protocol version, see `Serializer::get_version`.
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Returns the number of bytes written so far.
+    pub fn get_position(&self) -> usize {
+        self.position
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), SerializationError> {
+        let start = self.position;
+        let end = start + bytes.len();
+
+        if end > self.buffer.len() {
+            return Err(SerializationError::BufferFull);
+        }
+
+        self.buffer[start..end].copy_from_slice(bytes);
+        self.position = end;
+
+        Ok(())
+    }
+
+    fn write_var_uint(&mut self, value: u64) -> Result<(), SerializationError> {
+        // encode into a scratch buffer first so a full buffer never leaves a partial varint behind
+        let mut encoded = [0; VARINT_MAX_BYTES];
+        let mut remaining = value;
+        let mut length = 0;
+
+        loop {
+            let mut byte = (remaining & 0x7f) as u8;
+            remaining >>= 7;
+
+            if remaining != 0 {
+                byte |= 0x80;
+            }
+
+            encoded[length] = byte;
+            length += 1;
+
+            if remaining == 0 {
+                break;
+            }
         }
+
+        self.write_bytes(&encoded[0..length])
+    }
+
+    fn write_var_int(&mut self, value: i64) -> Result<(), SerializationError> {
+        self.write_var_uint(encode(value))
     }
 }
 
 impl<'a> Serializer for Writer<'a> {
-    fn serialize_string(&mut self, value: &str) { unimplemented!() }
-    fn serialize_u8(&mut self, value: &u8) { unimplemented!() }
-    fn serialize_i8(&mut self, value: &i8) { unimplemented!() }
-    fn serialize_u16(&mut self, value: &u16) { unimplemented!() }
-    fn serialize_i16(&mut self, value: &i16) { unimplemented!() }
-    fn serialize_u32(&mut self, value: &u32) { unimplemented!() }
-    fn serialize_i32(&mut self, value: &i32) { unimplemented!() }
-    fn serialize_u64(&mut self, value: &u64) { unimplemented!() }
-    fn serialize_i64(&mut self, value: &i64) { unimplemented!() }
-    fn serialize_f32(&mut self, value: &f32) { unimplemented!() }
-    fn serialize_f64(&mut self, value: &f64) { unimplemented!() }
+    fn get_version(&self) -> u32 {
+        self.version
+    }
+
+    fn serialize_string(&mut self, value: &str) -> Result<(), SerializationError> {
+        self.serialize_bytes(value.as_bytes())
+    }
+
+    fn serialize_bytes(&mut self, bytes: &[u8]) -> Result<(), SerializationError> {
+        let start = self.position;
+        self.write_var_uint(bytes.len() as u64)?;
+
+        if let Err(error) = self.write_bytes(bytes) {
+            self.position = start;
+            return Err(error);
+        }
+
+        Ok(())
+    }
+
+    fn serialize_bool(&mut self, value: &bool) -> Result<(), SerializationError> {
+        self.write_bytes(&[*value as u8])
+    }
+
+    fn serialize_length(&mut self, value: &usize) -> Result<(), SerializationError> {
+        self.write_var_uint(*value as u64)
+    }
+
+    fn serialize_u8(&mut self, value: &u8) -> Result<(), SerializationError> {
+        self.write_bytes(&[*value])
+    }
+
+    fn serialize_i8(&mut self, value: &i8) -> Result<(), SerializationError> {
+        self.write_bytes(&[*value as u8])
+    }
+
+    fn serialize_u16(&mut self, value: &u16) -> Result<(), SerializationError> {
+        self.write_var_uint(u64::from(*value))
+    }
+
+    fn serialize_i16(&mut self, value: &i16) -> Result<(), SerializationError> {
+        self.write_var_int(i64::from(*value))
+    }
+
+    fn serialize_u32(&mut self, value: &u32) -> Result<(), SerializationError> {
+        self.write_var_uint(u64::from(*value))
+    }
+
+    fn serialize_i32(&mut self, value: &i32) -> Result<(), SerializationError> {
+        self.write_var_int(i64::from(*value))
+    }
+
+    fn serialize_u64(&mut self, value: &u64) -> Result<(), SerializationError> {
+        self.write_var_uint(*value)
+    }
+
+    fn serialize_i64(&mut self, value: &i64) -> Result<(), SerializationError> {
+        self.write_var_int(*value)
+    }
+
+    fn serialize_f32(&mut self, value: &f32) -> Result<(), SerializationError> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    fn serialize_f64(&mut self, value: &f64) -> Result<(), SerializationError> {
+        self.write_bytes(&value.to_le_bytes())
+    }
+
+    fn serialize_bits(&mut self, value: &u64, bits: u32) -> Result<(), SerializationError> {
+        check_bits(*value, bits)?;
+
+        let length = bits.div_ceil(8) as usize;
+        self.write_bytes(&value.to_le_bytes()[0..length])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::{Reader, Serialize, DeserializeOwned, Deserializer};
+
+    fn round_trip<T>(value: T) -> T where T: Serialize + DeserializeOwned {
+        let mut buffer = [0; 64];
+        let length = {
+            let mut writer = Writer::new(&mut buffer);
+            value.serialize(&mut writer).expect("It serializes the value");
+            writer.get_position()
+        };
+
+        let mut reader = Reader::new(&buffer[0..length]);
+        T::deserialize(&mut reader).expect("It deserializes the value")
+    }
+
+    #[test]
+    fn it_writes_var_uints() {
+        let mut buffer = [0; 16];
+        let mut writer = Writer::new(&mut buffer);
+
+        writer.serialize_u32(&1).unwrap();
+        writer.serialize_u32(&300).unwrap();
+        let length = writer.get_position();
+
+        assert_eq!(&buffer[0..length], &[0x01, 0xac, 0x02]);
+    }
+
+    #[test]
+    fn it_writes_zig_zag_encoded_var_ints() {
+        let mut buffer = [0; 16];
+        let mut writer = Writer::new(&mut buffer);
+
+        writer.serialize_i32(&-1).unwrap();
+        writer.serialize_i32(&1).unwrap();
+        writer.serialize_i32(&-65).unwrap();
+        let length = writer.get_position();
+
+        assert_eq!(&buffer[0..length], &[0x01, 0x02, 0x81, 0x01]);
+    }
+
+    #[test]
+    fn it_writes_length_prefixed_strings() {
+        let mut buffer = [0; 16];
+        let mut writer = Writer::new(&mut buffer);
+
+        writer.serialize_string("abc").unwrap();
+        let length = writer.get_position();
+
+        assert_eq!(&buffer[0..length], &[0x03, b'a', b'b', b'c']);
+    }
+
+    #[test]
+    fn it_returns_buffer_full_instead_of_overflowing() {
+        let mut buffer = [0; 4];
+        let mut writer = Writer::new(&mut buffer);
+
+        writer.serialize_f32(&1.0).unwrap();
+
+        assert!(matches!(writer.serialize_u8(&1), Err(SerializationError::BufferFull)));
+        assert_eq!(writer.get_position(), 4);
+    }
+
+    #[test]
+    fn it_does_not_write_partial_values() {
+        let mut buffer = [0; 4];
+        let mut writer = Writer::new(&mut buffer);
+
+        writer.serialize_u8(&1).unwrap();
+
+        assert!(matches!(writer.serialize_u64(&u64::MAX), Err(SerializationError::BufferFull)));
+        assert!(matches!(writer.serialize_string("abcd"), Err(SerializationError::BufferFull)));
+        assert_eq!(writer.get_position(), 1);
+    }
+
+    #[test]
+    fn it_writes_bits_as_whole_bytes() {
+        let mut buffer = [0; 16];
+        let mut writer = Writer::new(&mut buffer);
+
+        writer.serialize_bits(&0x1f, 5).unwrap();
+        writer.serialize_bits(&0x1234, 13).unwrap();
+        writer.serialize_ranged(&-1, -1, 1).unwrap();
+        let length = writer.get_position();
+
+        assert_eq!(&buffer[0..length], &[0x1f, 0x34, 0x12, 0x00]);
+    }
+
+    #[test]
+    fn it_round_trips_bits_and_ranges() {
+        let mut buffer = [0; 32];
+        let mut writer = Writer::new(&mut buffer);
+
+        writer.serialize_bits(&0x3ff, 10).unwrap();
+        writer.serialize_bits(&u64::MAX, 64).unwrap();
+        writer.serialize_ranged(&42, 0, 100).unwrap();
+        writer.serialize_ranged(&i64::MIN, i64::MIN, i64::MAX).unwrap();
+        assert!(matches!(writer.serialize_ranged(&101, 0, 100), Err(SerializationError::ValueOutOfRange { .. })));
+        let length = writer.get_position();
+
+        let mut reader = Reader::new(&buffer[0..length]);
+        assert_eq!(reader.deserialize_bits(10).unwrap(), 0x3ff);
+        assert_eq!(reader.deserialize_bits(64).unwrap(), u64::MAX);
+        assert_eq!(reader.deserialize_ranged(0, 100).unwrap(), 42);
+        assert_eq!(reader.deserialize_ranged(i64::MIN, i64::MAX).unwrap(), i64::MIN);
+    }
+
+    #[test]
+    fn it_round_trips_strings() {
+        assert_eq!(round_trip(String::new()), "");
+        assert_eq!(round_trip("Hello World!".to_owned()), "Hello World!");
+        assert_eq!(round_trip("ünïcödé ✓".to_owned()), "ünïcödé ✓");
+    }
+
+    #[test]
+    fn it_round_trips_bools() {
+        assert!(round_trip(true));
+        assert!(!round_trip(false));
+    }
+
+    #[test]
+    fn it_round_trips_u8_and_i8() {
+        for value in &[0, 1, 127, 128, u8::MAX] {
+            assert_eq!(round_trip(*value), *value);
+        }
+
+        for value in &[0, 1, -1, i8::MIN, i8::MAX] {
+            assert_eq!(round_trip(*value), *value);
+        }
+    }
+
+    #[test]
+    fn it_round_trips_u16_and_i16() {
+        for value in &[0, 1, 127, 128, 16384, u16::MAX] {
+            assert_eq!(round_trip(*value), *value);
+        }
+
+        for value in &[0, 1, -1, -64, 64, i16::MIN, i16::MAX] {
+            assert_eq!(round_trip(*value), *value);
+        }
+    }
+
+    #[test]
+    fn it_round_trips_u32_and_i32() {
+        for value in &[0, 1, 127, 128, 2_097_152, u32::MAX] {
+            assert_eq!(round_trip(*value), *value);
+        }
+
+        for value in &[0, 1, -1, -132_456_789, 132_456_789, i32::MIN, i32::MAX] {
+            assert_eq!(round_trip(*value), *value);
+        }
+    }
+
+    #[test]
+    fn it_round_trips_u64_and_i64() {
+        for value in &[0, 1, 127, 128, 1 << 56, u64::MAX] {
+            assert_eq!(round_trip(*value), *value);
+        }
+
+        for value in &[0, 1, -1, -(1 << 40), 1 << 40, i64::MIN, i64::MAX] {
+            assert_eq!(round_trip(*value), *value);
+        }
+    }
+
+    #[test]
+    fn it_round_trips_floats() {
+        for value in &[0.0, -0.0, 1.5, -3.25, f32::MIN, f32::MAX, f32::EPSILON, f32::INFINITY] {
+            assert_eq!(round_trip(*value).to_bits(), value.to_bits());
+        }
+
+        for value in &[0.0, -0.0, 1.5, -3.25, f64::MIN, f64::MAX, f64::EPSILON, f64::NEG_INFINITY] {
+            assert_eq!(round_trip(*value).to_bits(), value.to_bits());
+        }
+
+        assert!(round_trip(f32::NAN).is_nan());
+        assert!(round_trip(f64::NAN).is_nan());
+    }
 }
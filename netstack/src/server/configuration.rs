@@ -1,6 +1,17 @@
 pub struct Configuration {
+    /// Identifies the application, packets signed with another protocol id are rejected.
+    pub protocol_id: u64,
     pub max_connections: usize,
     pub timeout: usize,
     pub reserved_timeout: usize,
     pub heartbeat: usize,
+    /// The size of packet buffers, packets received from the network are cut off after this many bytes.
+    /// Must be at least `fragmentation::MIN_MTU`, fragments carry their own size so the peer may use another MTU.
+    pub mtu: usize,
+    /// The largest message `send_fragmented` sends and the largest fragmented message that is reassembled.
+    pub max_message_size: usize,
+    /// How many fragmented messages of a single connection are reassembled at the same time.
+    pub max_fragment_groups: usize,
+    /// After how many updates a fragmented message that is still incomplete is dropped.
+    pub fragment_timeout: usize,
 }
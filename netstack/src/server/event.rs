@@ -1,3 +1,4 @@
+use std::ops::Range;
 use crate::{
     connection::Connection,
     packets::Payload,
@@ -10,10 +11,19 @@ pub enum Event {
     Disconnected {
         connection: Connection,
     },
+    /// A message arrived in the packet with the given sequence number, fragmented messages report the
+    /// fragment that completed them and every message of an aggregate packet reports the same one.
     Message {
         connection: Connection,
+        sequence_number: u64,
         payload: Payload,
     },
+    /// The queued messages numbered `messages` were sent in the packet with the given sequence number.
+    MessagesSent {
+        connection: Connection,
+        sequence_number: u64,
+        messages: Range<u64>,
+    },
     MessageAcknowledged {
         connection: Connection,
         sequence_number: u64,
@@ -1,11 +1,15 @@
 use failure::Error;
+use std::convert::TryInto;
 use crate::connection::*;
 use super::transport::Transport;
 use std::collections::HashMap;
 use std::net::SocketAddr;
-use crate::packets::{RawPacket, OutgoingPacket, PacketType};
+use crate::packets::{RawPacket, OutgoingPacket, PacketPool, PacketType, Payload, Protocol, HEADER_SIZE};
+use crate::fragmentation::{self, Reassembly};
+use crate::aggregation::{self, SendQueue};
 use crate::security::{Secret, ConnectionToken, ReplayBuffer};
 use crate::monitoring::ServerMonitor;
+use crate::serialization::Serialize;
 
 mod configuration;
 pub use configuration::Configuration;
@@ -37,13 +41,25 @@ pub struct Server {
     ack_buffers: ConnectionDataList<ReplayBuffer>,
     connection_token_to_connection: HashMap<ConnectionToken, Connection>,
     address_to_connection: HashMap<SocketAddr, Connection>,
+    reassemblies: ConnectionDataList<Reassembly>,
+    fragment_ids: ConnectionDataList<u16>,
+    send_queues: ConnectionDataList<SendQueue>,
+    pool: PacketPool,
+    protocol: Protocol,
 
     monitor: Box<dyn ServerMonitor>,
 }
 
 impl Server {
+    /// # Panics
+    ///
+    /// If `configuration.mtu` is smaller than `fragmentation::MIN_MTU`.
     pub fn new(configuration: Configuration, transport: Box<dyn Transport>, monitor: Box<dyn ServerMonitor>) -> Self {
+        assert!(configuration.mtu >= fragmentation::MIN_MTU, "The MTU must be at least {} bytes to leave room for fragments", fragmentation::MIN_MTU);
+
         let max_connections = configuration.max_connections;
+        let pool = PacketPool::new(configuration.mtu);
+        let protocol = Protocol::new(configuration.protocol_id);
         Self {
             transport,
             configuration,
@@ -58,6 +74,11 @@ impl Server {
             ack_buffers: ConnectionDataList::new(max_connections),
             connection_token_to_connection: HashMap::new(),
             address_to_connection: HashMap::new(),
+            reassemblies: ConnectionDataList::new(max_connections),
+            fragment_ids: ConnectionDataList::new(max_connections),
+            send_queues: ConnectionDataList::new(max_connections),
+            pool,
+            protocol,
             monitor,
         }
     }
@@ -78,6 +99,13 @@ impl Server {
             self.timeouts.set(connection, self.configuration.reserved_timeout);
             self.replay_buffers.set(connection, ReplayBuffer::new());
             self.ack_buffers.set(connection, ReplayBuffer::new());
+            self.reassemblies.set(connection, Reassembly::new(
+                self.configuration.max_message_size,
+                self.configuration.max_fragment_groups,
+                self.configuration.fragment_timeout,
+            ));
+            self.fragment_ids.set(connection, 0);
+            self.send_queues.set(connection, SendQueue::new());
             self.connection_token_to_connection.insert(connection_token, connection);
 
             self.monitor.reserved();
@@ -95,7 +123,7 @@ impl Server {
         let mut events = Vec::new();
 
         while poll_again {
-            let mut buffer = [0; 1500];
+            let mut buffer = self.pool.acquire();
             match self.transport.poll(&mut buffer) {
                 Ok(Some((length, address))) => {
                     let packet = RawPacket::new(buffer, length);
@@ -129,6 +157,9 @@ impl Server {
                 self.heartbeats.remove(connection);
                 self.sequence_numbers.remove(connection);
                 self.secrets.remove(connection);
+                self.reassemblies.remove(connection);
+                self.fragment_ids.remove(connection);
+                self.send_queues.remove(connection);
 
                 self.connections.delete_connection(connection).unwrap();
                 self.monitor.disconnected();
@@ -138,6 +169,8 @@ impl Server {
                 self.timeouts.set(connection, timeout);
             }
 
+            self.reassemblies.get_mut(connection).expect("No reassembly set for connection").update();
+
             let state = self.states.get(connection).expect("No state set for connection").clone();
 
             if state == ConnectionState::Connected {
@@ -150,14 +183,112 @@ impl Server {
             }
         }
 
+        let (sent, errors) = self.flush();
+        events.extend(sent);
+        for error in errors {
+            println!("could not send queued messages: {}", error);
+        }
+
         events
     }
 
+    /// Creates an empty packet with a buffer from the pool, which is reused once the packet is sent.
+    pub fn create_packet(&self) -> OutgoingPacket {
+        OutgoingPacket::with_buffer(self.pool.acquire())
+    }
+
     /// Sends a packet to the given connection and returns the packet's sequence number
     pub fn send(&mut self, packet: OutgoingPacket, connection: Connection) -> Result<u64, Error> {
+        self.check_connected(connection)?;
+
+        self.send_internal(packet, connection, PacketType::Payload)
+    }
+
+    /// Serializes a message into a new packet, sends it to the given connection and returns the packet's sequence number
+    pub fn send_message(&mut self, message: &impl Serialize, connection: Connection) -> Result<u64, Error> {
+        let mut packet = self.create_packet();
+        packet.serialize(message)?;
+
+        self.send(packet, connection)
+    }
+
+    /// Queues a small message to be sent along with the other queued messages of the connection
+    /// on the next `flush` or `update`. Returns the number of the message, `Event::MessagesSent`
+    /// tells which packet carried it.
+    pub fn queue_message(&mut self, message: &impl Serialize, connection: Connection) -> Result<u64, Error> {
+        self.check_connected(connection)?;
+
+        let send_queue = self.send_queues.get_mut(connection).expect("No send queue for connection found");
+        Ok(send_queue.push(message, self.pool.get_mtu())?)
+    }
+
+    /// Sends the queued messages of all connections in as few packets as possible.
+    /// Returns an event for every packet that was sent and an error for every packet that wasn't,
+    /// a failed send doesn't hold back the other packets.
+    pub fn flush(&mut self) -> (Vec<Event>, Vec<Error>) {
+        let mut events = Vec::new();
+        let mut errors = Vec::new();
+
+        let connections: Vec<Connection> = self.connections.into_iter().collect();
+        for connection in connections {
+            let packets = match self.send_queues.get_mut(connection) {
+                Some(send_queue) => send_queue.pack(&self.pool),
+                None => continue,
+            };
+
+            for (packet, messages) in packets {
+                match self.send_internal(packet, connection, PacketType::Aggregate) {
+                    Ok(sequence_number) => events.push(Event::MessagesSent {
+                        connection,
+                        sequence_number,
+                        messages,
+                    }),
+                    Err(error) => errors.push(error),
+                }
+            }
+        }
+
+        (events, errors)
+    }
+
+    /// Serializes a message of up to `Configuration::max_message_size` bytes and sends it to the given connection,
+    /// split into fragments if it doesn't fit into a single packet. Returns the sequence numbers of all packets sent.
+    pub fn send_fragmented(&mut self, message: &impl Serialize, connection: Connection) -> Result<Vec<u64>, Error> {
+        use std::io::Write;
+
+        self.check_connected(connection)?;
+
+        let bytes = fragmentation::serialize_message(message, self.configuration.max_message_size)?;
+
+        if bytes.len() <= self.pool.get_mtu() - HEADER_SIZE {
+            let mut packet = self.create_packet();
+            packet.write_all(&bytes)?;
+            return Ok(vec![self.send_internal(packet, connection, PacketType::Payload)?]);
+        }
+
+        let id = *self.fragment_ids.get(connection).expect("No fragment id for connection found");
+        self.fragment_ids.set(connection, id.wrapping_add(1));
+
+        let mut sequence_numbers = Vec::new();
+        for packet in fragmentation::write_fragments(&bytes, id, &self.pool)? {
+            sequence_numbers.push(self.send_internal(packet, connection, PacketType::Fragment)?);
+        }
+
+        Ok(sequence_numbers)
+    }
+
+    /// Like `send_message`, but leaves out fields that don't exist in the connection's protocol version
+    pub fn send_message_versioned(&mut self, message: &impl Serialize, version: u32, connection: Connection) -> Result<u64, Error> {
+        let mut packet = self.create_packet();
+        packet.serialize_versioned(message, version)?;
+
+        self.send(packet, connection)
+    }
+
+    fn check_connected(&self, connection: Connection) -> Result<(), Error> {
         match self.get_connection_state(connection) {
             Some(ConnectionState::Connected) => {
-                Ok(self.send_internal(packet, connection, PacketType::Payload)?)
+                Ok(())
             },
             Some(ConnectionState::Reserved) => {
                 Err(ServerError::ConnectionNotReady.into())
@@ -175,7 +306,7 @@ impl Server {
 
         let (ack_sequence_number, ack_bits) = self.replay_buffers.get(connection).expect("no replay buffer for connection").get_ack_bits();
 
-        let raw = packet.write_header_and_sign(sequence_number, ack_sequence_number, ack_bits, packet_type.to_u8(), secret);
+        let raw = packet.write_header_and_sign(sequence_number, ack_sequence_number, ack_bits, packet_type.to_u8(), secret, self.protocol);
         let address = self.addresses.get(connection).expect("No address for connection found");
 
         // TODO check bytes sent?
@@ -217,12 +348,21 @@ impl Server {
             return;
         }
 
-        let connection_token = if let Ok(connection_token) = ConnectionToken::from_slice(packet.get_body()) {
+        // the connection message carries the token followed by the protocol id of the client
+        let body = packet.get_body();
+        let (token, protocol_id) = body.split_at(body.len().saturating_sub(8));
+
+        let connection_token = if let Ok(connection_token) = ConnectionToken::from_slice(token) {
             connection_token
         } else {
             println!("could not get connection token from connection message");
             return;
         };
+
+        let claimed = Protocol {
+            id: u64::from_le_bytes(protocol_id.try_into().expect("The protocol id follows the token")),
+            version: packet.get_header().version,
+        };
         
         let connection = if let Some(connection) = self.connection_token_to_connection.get(&connection_token) {
             connection.clone()
@@ -231,14 +371,25 @@ impl Server {
             return;
         };
 
-        let secret = self.secrets.get(connection).expect("no secret found for connection");
-        let packet = if let Some(packet) = packet.verify(&secret) {
-            packet
-        } else {
-            println!("connection packet was invalid");
-            return;
+        let secret = self.secrets.get(connection).expect("no secret found for connection").clone();
+        let packet = match packet.verify(&secret, claimed) {
+            Ok(packet) => packet,
+            Err(error) => {
+                println!("rejected connection packet: {}", error);
+                return;
+            },
         };
 
+        if claimed != self.protocol {
+            println!("rejected connection, the client speaks protocol {:#x} version {}, expected {:#x} version {}",
+                claimed.id, claimed.version, self.protocol.id, self.protocol.version);
+
+            if let Err(error) = self.send_rejection(&address, &secret, claimed) {
+                println!("could not send rejection: {}", error);
+            }
+            return;
+        }
+
         // --- here the packet is validated and we can begin to change state based on it ---
 
         let replay_buffer = self.replay_buffers.get_mut(connection).expect("no replay buffer for connection");
@@ -279,58 +430,120 @@ impl Server {
     fn handle_message(&mut self, connection: Connection, packet: RawPacket, events: &mut Vec<Event>) {
         let secret = self.secrets.get(connection).expect("No secret for connection");
 
-        if let Some(packet) = packet.verify(secret) {
+        match packet.verify(secret, self.protocol) {
+            Ok(packet) => {
+                let sequence_number = packet.get_sequence_number();
+                let replay_buffer = self.replay_buffers.get_mut(connection).expect("no replay buffer for connection");
 
-            let sequence_number = packet.get_sequence_number();
-            let replay_buffer = self.replay_buffers.get_mut(connection).expect("no replay buffer for connection");
+                if replay_buffer.acknowledge(sequence_number) {
 
-            if replay_buffer.acknowledge(sequence_number) {
+                    let ack_sequence_number = packet.get_ack_sequence_number();
+                    let ack_bits = packet.get_ack_bits();
 
-                let ack_sequence_number = packet.get_ack_sequence_number();
-                let ack_bits = packet.get_ack_bits();
+                    let ack_buffer = self.ack_buffers.get_mut(connection).expect("no replay buffer for connection");
+                    let acked = ack_buffer.set_ack_bits(ack_sequence_number, ack_bits);
 
-                let ack_buffer = self.ack_buffers.get_mut(connection).expect("no replay buffer for connection");
-                let acked = ack_buffer.set_ack_bits(ack_sequence_number, ack_bits);
+                    for sequence_number in acked {
+                        self.monitor.message_acknowledged();
+                        events.push(Event::MessageAcknowledged {
+                            connection,
+                            sequence_number,
+                        });
+                    }
+
+                    match packet.get_packet_type() {
+                        Some(PacketType::Payload) => {
+                            self.timeouts.set(connection, self.configuration.timeout);
+
+                            self.monitor.message_received();
+                            events.push(Event::Message {
+                                connection,
+                                sequence_number,
+                                payload: packet.into_payload(),
+                            });
+                        },
+                        Some(PacketType::Fragment) => {
+                            self.timeouts.set(connection, self.configuration.timeout);
+                            self.handle_fragment(connection, sequence_number, packet.into_payload(), events);
+                        },
+                        Some(PacketType::Aggregate) => {
+                            self.timeouts.set(connection, self.configuration.timeout);
+                            self.handle_aggregate(connection, sequence_number, packet.into_payload(), events);
+                        },
+                        Some(PacketType::Heartbeat) => {
+                            self.timeouts.set(connection, self.configuration.timeout);
+                        },
+                        Some(packet_type) => {
+                            println!("unexpected packet type {:?}", packet_type);
+                        },
+                        None => {
+                            println!("invalid packet type");
+                        }
+                    }
+                } else {
+                    println!("unusable sequence number");
+                }
+            },
+            Err(error) => {
+                println!("rejected packet: {}", error);
+            },
+        }
+    }
 
-                for sequence_number in acked {
-                    self.monitor.message_acknowledged();
-                    events.push(Event::MessageAcknowledged {
+    fn handle_aggregate(&mut self, connection: Connection, sequence_number: u64, aggregate: Payload, events: &mut Vec<Event>) {
+        match aggregation::unpack(aggregate.get_buffer()) {
+            Ok(messages) => {
+                for payload in aggregate.split(messages) {
+                    self.monitor.message_received();
+                    events.push(Event::Message {
                         connection,
                         sequence_number,
+                        payload,
                     });
                 }
+            },
+            Err(error) => {
+                println!("dropped aggregate packet: {}", error);
+            },
+        }
+    }
 
-                match packet.get_packet_type() {
-                    Some(PacketType::Payload) => {
-                        self.timeouts.set(connection, self.configuration.timeout);
+    fn handle_fragment(&mut self, connection: Connection, sequence_number: u64, fragment: Payload, events: &mut Vec<Event>) {
+        let reassembly = self.reassemblies.get_mut(connection).expect("No reassembly for connection");
+
+        match reassembly.insert(fragment.get_buffer()) {
+            Ok(Some(message)) => {
+                self.monitor.message_received();
+                events.push(Event::Message {
+                    connection,
+                    sequence_number,
+                    payload: Payload::from_bytes(message),
+                });
+            },
+            Ok(None) => {},
+            Err(error) => {
+                println!("dropped fragment: {}", error);
+            },
+        }
+    }
 
-                        self.monitor.message_received();
-                        events.push(Event::Message {
-                            connection,
-                            payload: packet.into_payload(),
-                        });
-                    },
-                    Some(PacketType::Heartbeat) => {
-                        self.timeouts.set(connection, self.configuration.timeout);
-                    },
-                    Some(packet_type) => {
-                        println!("unexpected packet type {:?}", packet_type);
-                    },
-                    None => {
-                        println!("invalid packet type");
-                    }
-                }
-            } else {
-                println!("unusable sequence number");
-            }
+    /// Tells a client of another protocol which protocol the server speaks.
+    /// The rejection is signed in the client's protocol, so the client can trust it.
+    fn send_rejection(&mut self, address: &SocketAddr, secret: &Secret, protocol: Protocol) -> Result<(), Error> {
+        use std::io::Write;
 
-        } else {
-            println!("got invalid packet");
-        }
+        let mut packet = self.create_packet();
+        packet.write_all(&self.protocol.id.to_le_bytes())?;
+        packet.write_all(&[self.protocol.version])?;
+
+        let raw = packet.write_header_and_sign(0, 0, [0; 4], PacketType::Rejected.to_u8(), secret, protocol);
+        self.transport.send(address, raw.get_buffer())?;
+
+        Ok(())
     }
 
     fn send_heartbeat_message(&mut self, connection: Connection) -> Result<(), Error> {
-        let packet = OutgoingPacket::new();
+        let packet = self.create_packet();
         self.send_internal(packet, connection, PacketType::Heartbeat)?;
 
         Ok(())
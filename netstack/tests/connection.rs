@@ -0,0 +1,293 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use netstack::client::{self, Client};
+use netstack::connection::Connection;
+use netstack::monitoring::{EmptyClientMonitor, ServerMonitor};
+use netstack::packets::DEFAULT_MTU;
+use netstack::security::{ConnectionToken, Secret};
+use netstack::server::{self, Server};
+use netstack::transport::{Transport, TransportError};
+
+type Datagrams = Rc<RefCell<HashMap<SocketAddr, VecDeque<(SocketAddr, Vec<u8>)>>>>;
+
+// Delivers datagrams between transports in memory, in order unless a test shuffles them.
+#[derive(Clone, Default)]
+struct Network {
+    datagrams: Datagrams,
+    failures: Rc<Cell<usize>>,
+}
+
+impl Network {
+    fn transport(&self, address: &str) -> Box<dyn Transport> {
+        Box::new(MemoryTransport {
+            address: address.parse().unwrap(),
+            datagrams: self.datagrams.clone(),
+            failures: self.failures.clone(),
+        })
+    }
+
+    fn fail_sends(&self, count: usize) {
+        self.failures.set(count);
+    }
+
+    fn reverse(&self) {
+        for queue in self.datagrams.borrow_mut().values_mut() {
+            queue.make_contiguous().reverse();
+        }
+    }
+}
+
+struct MemoryTransport {
+    address: SocketAddr,
+    datagrams: Datagrams,
+    failures: Rc<Cell<usize>>,
+}
+
+impl Transport for MemoryTransport {
+    fn poll(&mut self, buffer: &mut [u8]) -> Result<Option<(usize, SocketAddr)>, TransportError> {
+        let datagram = self.datagrams.borrow_mut().get_mut(&self.address).and_then(|queue| queue.pop_front());
+
+        Ok(datagram.map(|(from, bytes)| {
+            let length = bytes.len().min(buffer.len());
+            buffer[0..length].copy_from_slice(&bytes[0..length]);
+            (length, from)
+        }))
+    }
+
+    fn send(&mut self, address: &SocketAddr, buffer: &[u8]) -> Result<usize, TransportError> {
+        if self.failures.get() > 0 {
+            self.failures.set(self.failures.get() - 1);
+            return Err(io::Error::other("network is down").into());
+        }
+
+        self.datagrams.borrow_mut().entry(*address).or_default().push_back((self.address, buffer.to_vec()));
+        Ok(buffer.len())
+    }
+}
+
+struct EmptyServerMonitor;
+
+impl ServerMonitor for EmptyServerMonitor {
+    fn tick(&mut self) { }
+    fn reserved(&mut self) { }
+    fn connected(&mut self) { }
+    fn disconnected(&mut self) { }
+    fn message_received(&mut self) { }
+    fn message_sent(&mut self) { }
+    fn message_acknowledged(&mut self) { }
+}
+
+struct Peers {
+    network: Network,
+    server: Server,
+    client: Client,
+    on_server: Connection,
+    on_client: Connection,
+}
+
+const SERVER_ADDRESS: &str = "127.0.0.1:9000";
+const CLIENT_ADDRESS: &str = "127.0.0.1:9001";
+
+fn peers(server_protocol_id: u64, client_protocol_id: u64, client_mtu: usize) -> (Network, Server, Client, Connection, Connection) {
+    let network = Network::default();
+    let mut server = Server::new(server::Configuration {
+        protocol_id: server_protocol_id,
+        max_connections: 4,
+        timeout: 100,
+        reserved_timeout: 100,
+        heartbeat: 50,
+        mtu: DEFAULT_MTU,
+        max_message_size: 16 * 1024,
+        max_fragment_groups: 4,
+        fragment_timeout: 10,
+    }, network.transport(SERVER_ADDRESS), Box::new(EmptyServerMonitor));
+    let mut client = Client::new(client::Configuration {
+        protocol_id: client_protocol_id,
+        max_connections: 1,
+        timeout: 100,
+        heartbeat: 50,
+        mtu: client_mtu,
+        max_message_size: 16 * 1024,
+        max_fragment_groups: 4,
+        fragment_timeout: 10,
+    }, network.transport(CLIENT_ADDRESS), Box::new(EmptyClientMonitor::new()));
+
+    let secret = Secret::from_bytes([0x7; 32]);
+    let token = ConnectionToken::from_bytes([0x3; 32]);
+    let on_server = server.reserve(secret.clone(), token.clone()).unwrap();
+    let on_client = client.connect(SERVER_ADDRESS.parse().unwrap(), secret, token).unwrap();
+
+    (network, server, client, on_server, on_client)
+}
+
+fn connect() -> Peers {
+    connect_with_mtu(DEFAULT_MTU)
+}
+
+fn connect_with_mtu(client_mtu: usize) -> Peers {
+    let (network, mut server, mut client, on_server, on_client) = peers(7, 7, client_mtu);
+
+    assert!(server.update().iter().any(|event| matches!(event, server::Event::Connected { .. })));
+    server.send_message(&0u8, on_server).unwrap();
+    assert!(client.update().iter().any(|event| matches!(event, client::Event::Connected { .. })));
+
+    Peers { network, server, client, on_server, on_client }
+}
+
+fn messages(events: Vec<server::Event>) -> Vec<Vec<u16>> {
+    events.into_iter().filter_map(|event| match event {
+        server::Event::Message { payload, .. } => Some(payload.deserialize::<Vec<u16>>().unwrap()),
+        _ => None,
+    }).collect()
+}
+
+#[test]
+fn it_delivers_a_message_larger_than_a_packet() {
+    let mut peers = connect();
+    let world: Vec<u16> = (0..4000).collect();
+
+    let sequence_numbers = peers.client.send_fragmented(&world, peers.on_client).unwrap();
+    assert_eq!(sequence_numbers.len(), 6, "The roughly 8 KB message takes 6 packets");
+
+    assert_eq!(messages(peers.server.update()), vec![world]);
+}
+
+#[test]
+fn it_sends_a_small_message_in_a_single_packet() {
+    let mut peers = connect();
+
+    let sequence_numbers = peers.server.send_fragmented(&vec![1u16, 2, 3], peers.on_server).unwrap();
+    assert_eq!(sequence_numbers.len(), 1);
+
+    let events = peers.client.update();
+    let payload = events.into_iter().find_map(|event| match event {
+        client::Event::Message { payload, .. } => Some(payload),
+        _ => None,
+    }).expect("The client receives the message");
+    assert_eq!(payload.deserialize::<Vec<u16>>().unwrap(), vec![1, 2, 3]);
+}
+
+#[test]
+fn it_reassembles_fragments_of_a_peer_with_a_smaller_mtu() {
+    let mut peers = connect_with_mtu(576);
+    let world: Vec<u16> = (0..4000).collect();
+
+    let sequence_numbers = peers.client.send_fragmented(&world, peers.on_client).unwrap();
+    assert_eq!(sequence_numbers.len(), 16, "The fragments are sized for the client's MTU");
+
+    assert_eq!(messages(peers.server.update()), vec![world]);
+}
+
+#[test]
+fn it_reassembles_fragments_that_arrive_out_of_order() {
+    let mut peers = connect();
+    let world: Vec<u16> = (0..2000).rev().collect();
+
+    peers.client.send_fragmented(&world, peers.on_client).unwrap();
+    peers.network.reverse();
+
+    assert_eq!(messages(peers.server.update()), vec![world]);
+}
+
+#[test]
+fn it_refuses_to_send_a_message_larger_than_the_maximum() {
+    let mut peers = connect();
+    let world: Vec<u16> = (0..10_000).collect();
+
+    assert!(peers.client.send_fragmented(&world, peers.on_client).is_err());
+    assert!(messages(peers.server.update()).is_empty());
+}
+
+#[test]
+fn it_packs_queued_messages_into_one_packet() {
+    let mut peers = connect();
+
+    for tick in 0..20u16 {
+        assert_eq!(peers.client.queue_message(&vec![tick], peers.on_client).unwrap(), tick as u64);
+    }
+    assert!(messages(peers.server.update()).is_empty(), "Nothing is sent before the flush");
+
+    let (sent, errors) = peers.client.flush();
+    assert!(errors.is_empty());
+    assert_eq!(sent.len(), 1);
+    let sequence_number = match &sent[0] {
+        client::Event::MessagesSent { sequence_number, messages, .. } => {
+            assert_eq!(messages, &(0..20));
+            *sequence_number
+        },
+        _ => panic!("Expected the queued messages to be sent"),
+    };
+
+    let events = peers.server.update();
+    let carried_by = events.iter().filter_map(|event| match event {
+        server::Event::Message { sequence_number, .. } => Some(*sequence_number),
+        _ => None,
+    });
+    assert!(carried_by.eq(std::iter::repeat_n(sequence_number, 20)), "Every message reports the packet that carried it");
+
+    let expected: Vec<Vec<u16>> = (0..20).map(|tick| vec![tick]).collect();
+    assert_eq!(messages(events), expected);
+
+    peers.server.send_message(&0u8, peers.on_server).unwrap();
+    let acknowledged = peers.client.update().into_iter().any(|event| match event {
+        client::Event::MessageAcknowledged { sequence_number: acked, .. } => acked == sequence_number,
+        _ => false,
+    });
+    assert!(acknowledged, "The packet carrying the queued messages gets acknowledged");
+}
+
+#[test]
+fn it_flushes_queued_messages_on_update() {
+    let mut peers = connect();
+
+    peers.server.queue_message(&"hello", peers.on_server).unwrap();
+    peers.server.queue_message(&"world", peers.on_server).unwrap();
+
+    let sent = peers.server.update().into_iter().filter(|event| matches!(event, server::Event::MessagesSent { .. })).count();
+    assert_eq!(sent, 1);
+
+    let received: Vec<String> = peers.client.update().into_iter().filter_map(|event| match event {
+        client::Event::Message { payload, .. } => Some(payload.deserialize::<String>().unwrap()),
+        _ => None,
+    }).collect();
+    assert_eq!(received, vec!["hello", "world"]);
+}
+
+#[test]
+fn it_keeps_flushing_after_a_failed_send() {
+    let mut peers = connect();
+
+    for _ in 0..4 {
+        peers.client.queue_message(&vec![7u16; 600], peers.on_client).unwrap();
+    }
+
+    peers.network.fail_sends(1);
+    let (sent, errors) = peers.client.flush();
+    assert_eq!(errors.len(), 1);
+
+    let messages: Vec<_> = sent.into_iter().map(|event| match event {
+        client::Event::MessagesSent { messages, .. } => messages,
+        _ => panic!("Expected the queued messages to be sent"),
+    }).collect();
+    assert_eq!(messages, vec![2..4], "The second packet is still sent");
+
+    assert_eq!(self::messages(peers.server.update()), vec![vec![7; 600]; 2]);
+}
+
+#[test]
+fn it_rejects_a_client_of_another_protocol() {
+    let (_network, mut server, mut client, _, on_client) = peers(7, 8, DEFAULT_MTU);
+
+    assert!(!server.update().iter().any(|event| matches!(event, server::Event::Connected { .. })));
+
+    let error = client.update().into_iter().find_map(|event| match event {
+        client::Event::ConnectionFailed { connection, error } if connection == on_client => Some(error),
+        _ => None,
+    }).expect("The connection fails");
+
+    assert!(matches!(error, client::ClientError::ProtocolMismatch { protocol_id: 8, server_protocol_id: 7, .. }));
+    assert!(client.send_message(&0u8, on_client).is_err());
+}
@@ -0,0 +1,746 @@
+use std::collections::HashMap;
+use netstack::serialization::{BitReader, BitWriter, DeltaDeserialize, DeltaSerialize, Deserialize, DeserializeOwned, Reader, SerializationError, LATEST_VERSION, Serialize, Writer, assert_max_encoded_size};
+use netstack::messages::{Message, message_id, serialize_message};
+use netstack::packets::{PacketBuffer, Payload, RawPacket, DEFAULT_MTU, HEADER_SIZE, MAX_PAYLOAD_SIZE};
+use netstack::schema::{DefinitionKind, Encoding, Registry, Schema, Type};
+use netstack_derive::{DeltaDeserialize, DeltaSerialize, Deserialize, Message, Schema, Serialize};
+
+fn round_trip<T>(value: &T) -> T where T: Serialize + DeserializeOwned {
+    let mut buffer = [0; MAX_PAYLOAD_SIZE];
+    let length = {
+        let mut writer = Writer::new(&mut buffer);
+        value.serialize(&mut writer).expect("It serializes the value");
+        writer.get_position()
+    };
+
+    let mut reader = Reader::new(&buffer[0..length]);
+    T::deserialize(&mut reader).expect("It deserializes the value")
+}
+
+fn bit_round_trip<T>(value: &T) -> T where T: Serialize + DeserializeOwned {
+    let mut buffer = [0; 1024];
+    let length = {
+        let mut writer = BitWriter::new(&mut buffer);
+        value.serialize(&mut writer).expect("It serializes the value");
+        writer.get_position()
+    };
+
+    let mut reader = BitReader::new(&buffer[0..length]);
+    T::deserialize(&mut reader).expect("It deserializes the value")
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub struct Greeting {
+    pub id: u32,
+    pub to: String,
+    pub message: String,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub struct Inventory {
+    pub owner: Option<Greeting>,
+    pub items: Vec<(u16, u8)>,
+    pub hotbar: [Option<u16>; 4],
+    pub tags: HashMap<String, bool>,
+    pub nested: Box<Vec<i64>>,
+}
+
+#[test]
+fn it_round_trips_a_derived_struct() {
+    let greeting = Greeting {
+        id: 15,
+        to: "World".to_owned(),
+        message: "Hello".to_owned(),
+    };
+
+    assert_eq!(round_trip(&greeting), greeting);
+}
+
+#[test]
+fn it_round_trips_nested_std_types() {
+    let mut tags = HashMap::new();
+    tags.insert("rare".to_owned(), true);
+
+    let inventory = Inventory {
+        owner: Some(Greeting {
+            id: 1,
+            to: "a".to_owned(),
+            message: "b".to_owned(),
+        }),
+        items: vec![(1, 2), (300, 4)],
+        hotbar: [Some(1), None, Some(2), None],
+        tags,
+        nested: Box::new(vec![-1, 0, 1]),
+    };
+
+    assert_eq!(round_trip(&inventory), inventory);
+    assert_eq!(bit_round_trip(&inventory), inventory);
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Chat<'a> {
+    pub id: u32,
+    pub from: &'a str,
+    pub data: &'a [u8],
+    pub mentions: Vec<&'a str>,
+}
+
+#[test]
+fn it_borrows_fields_from_the_buffer() {
+    let chat = Chat {
+        id: 7,
+        from: "Alice",
+        data: &[0x1, 0x2, 0x3],
+        mentions: vec!["Bob", "Carol"],
+    };
+
+    let mut buffer = [0; 64];
+    let length = {
+        let mut writer = Writer::new(&mut buffer);
+        chat.serialize(&mut writer).unwrap();
+        writer.get_position()
+    };
+
+    let bytes = &buffer[0..length];
+    let mut reader = Reader::new(bytes);
+    let result = Chat::deserialize(&mut reader).unwrap();
+
+    assert_eq!(result, chat);
+    assert!(bytes.as_ptr_range().contains(&result.from.as_ptr()));
+    assert!(bytes.as_ptr_range().contains(&result.data.as_ptr()));
+}
+
+#[test]
+fn it_borrows_fields_from_a_bit_packed_buffer() {
+    let chat = Chat {
+        id: 7,
+        from: "Alice",
+        data: &[0x1, 0x2, 0x3],
+        mentions: vec![],
+    };
+
+    let mut buffer = [0; 64];
+    let length = {
+        let mut writer = BitWriter::new(&mut buffer);
+        chat.serialize(&mut writer).unwrap();
+        writer.get_position()
+    };
+
+    let mut reader = BitReader::new(&buffer[0..length]);
+    assert_eq!(Chat::deserialize(&mut reader).unwrap(), chat);
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub enum Command {
+    Stop,
+    Move(i16, i16),
+    Say { to: Option<u32>, text: String },
+    Fire(),
+    Spawn { kind: u8 },
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum Text<'a> {
+    Borrowed(&'a str),
+    Empty,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum Never {}
+
+#[test]
+fn it_round_trips_enums() {
+    let commands = vec![
+        Command::Stop,
+        Command::Move(-3, 400),
+        Command::Say { to: Some(1), text: "hi".to_owned() },
+        Command::Fire(),
+        Command::Spawn { kind: 7 },
+    ];
+
+    assert_eq!(round_trip(&commands), commands);
+    assert_eq!(bit_round_trip(&commands), commands);
+}
+
+#[test]
+fn it_writes_a_compact_discriminant() {
+    let mut buffer = [0; 16];
+    let length = {
+        let mut writer = Writer::new(&mut buffer);
+        Command::Spawn { kind: 7 }.serialize(&mut writer).unwrap();
+        writer.get_position()
+    };
+    assert_eq!(&buffer[0..length], &[0x04, 0x07]);
+
+    // five variants fit into three bits
+    assert_eq!(Command::Stop.encoded_bits().unwrap(), 3);
+    assert_eq!(Text::Empty.encoded_bits().unwrap(), 1);
+}
+
+#[test]
+fn it_rejects_unknown_discriminants() {
+    let buffer = [0x05];
+    let mut reader = Reader::new(&buffer);
+
+    assert!(matches!(Command::deserialize(&mut reader), Err(SerializationError::InvalidDiscriminant { value: 5 })));
+    assert!(matches!(Never::deserialize(&mut Reader::new(&[])), Err(SerializationError::InvalidDiscriminant { value: 0 })));
+}
+
+#[test]
+fn it_borrows_from_the_buffer_in_enums() {
+    let buffer = [0x00, 0x02, b'h', b'i'];
+    let mut reader = Reader::new(&buffer);
+
+    assert_eq!(Text::deserialize(&mut reader).unwrap(), Text::Borrowed("hi"));
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize, DeltaSerialize, DeltaDeserialize, Schema)]
+pub struct EntityId(pub u32);
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Heartbeat;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Message, Schema)]
+pub struct Snapshot<T> where T: Clone {
+    pub tick: u64,
+    pub entities: Vec<(EntityId, T)>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum Update<'a, T> where T: Clone {
+    Full(Snapshot<T>),
+    Note(&'a str, T),
+}
+
+#[test]
+fn it_round_trips_tuple_and_unit_structs() {
+    assert_eq!(round_trip(&EntityId(300)), EntityId(300));
+    assert_eq!(round_trip(&Heartbeat), Heartbeat);
+    assert_eq!(Heartbeat.encoded_size().unwrap(), 0);
+    assert_eq!(EntityId(300).encoded_size().unwrap(), 2);
+}
+
+#[test]
+fn it_round_trips_a_vec_of_unit_structs() {
+    let heartbeats = vec![Heartbeat, Heartbeat, Heartbeat];
+
+    assert_eq!(round_trip(&heartbeats), heartbeats);
+    assert_eq!(bit_round_trip(&heartbeats), heartbeats);
+    assert_eq!(round_trip(&(vec![[0u8; 0]; 5], 7u8)), (vec![[]; 5], 7));
+}
+
+#[test]
+fn it_round_trips_generic_types() {
+    let snapshot = Snapshot {
+        tick: 12,
+        entities: vec![(EntityId(1), [1.5f32, 2.0]), (EntityId(2), [0.0, -1.0])],
+    };
+
+    assert_eq!(round_trip(&snapshot), snapshot);
+    assert_eq!(bit_round_trip(&snapshot), snapshot);
+}
+
+#[test]
+fn it_round_trips_generic_types_with_lifetimes() {
+    let update = Update::Note("hello", Some(5u16));
+
+    let mut buffer = [0; 32];
+    let length = {
+        let mut writer = Writer::new(&mut buffer);
+        update.serialize(&mut writer).unwrap();
+        writer.get_position()
+    };
+
+    let mut reader = Reader::new(&buffer[0..length]);
+    assert_eq!(Update::<Option<u16>>::deserialize(&mut reader).unwrap(), update);
+}
+
+mod as_text {
+    use netstack::serialization::{Deserializer, SerializationError, Serializer};
+
+    pub fn serialize(value: &u32, serializer: &mut impl Serializer) -> Result<(), SerializationError> {
+        serializer.serialize_string(&value.to_string())
+    }
+
+    pub fn deserialize<'de>(deserializer: &mut impl Deserializer<'de>) -> Result<u32, SerializationError> {
+        deserializer.deserialize_str()?
+            .parse()
+            .map_err(|_| SerializationError::Custom { message: "not a number".to_owned() })
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub struct Cached {
+    pub id: u16,
+    #[netstack(skip)]
+    pub render_cache: Vec<u8>,
+    #[netstack(skip, default = "42")]
+    pub local_only: u32,
+    #[netstack(with = "as_text")]
+    pub score: u32,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum Tagged {
+    Entity(u8, #[netstack(skip)] Option<String>),
+    Score {
+        #[netstack(with = "as_text")]
+        value: u32,
+    },
+}
+
+#[test]
+fn it_skips_fields_and_fills_in_defaults() {
+    let cached = Cached {
+        id: 1,
+        render_cache: vec![1, 2, 3],
+        local_only: 7,
+        score: 1234,
+    };
+
+    let expected = Cached {
+        id: 1,
+        render_cache: vec![],
+        local_only: 42,
+        score: 1234,
+    };
+
+    assert_eq!(round_trip(&cached), expected);
+    assert_eq!(cached.encoded_size().unwrap(), 1 + 1 + 4);
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LocalState {
+    #[netstack(skip)]
+    pub history: Vec<String>,
+    #[netstack(skip)]
+    pub frame: u64,
+}
+
+#[test]
+fn it_bounds_collections_of_elements_without_fields() {
+    let states = vec![LocalState::default(), LocalState::default()];
+    assert_eq!(round_trip(&states), states);
+
+    // a length of u32::MAX would allocate and construct billions of elements from just 5 bytes
+    let buffer = [0xff, 0xff, 0xff, 0xff, 0x0f];
+    let mut reader = Reader::new(&buffer);
+    assert!(matches!(Vec::<LocalState>::deserialize(&mut reader), Err(SerializationError::LengthExceedsMaximum { .. })));
+
+    let mut reader = BitReader::new(&buffer);
+    assert!(matches!(Vec::<LocalState>::deserialize(&mut reader), Err(SerializationError::LengthExceedsMaximum { .. })));
+}
+
+#[test]
+fn it_uses_custom_codecs() {
+    let mut buffer = [0; 16];
+    let length = {
+        let mut writer = Writer::new(&mut buffer);
+        Tagged::Score { value: 99 }.serialize(&mut writer).unwrap();
+        writer.get_position()
+    };
+
+    assert_eq!(&buffer[0..length], &[0x01, 0x02, b'9', b'9']);
+    assert_eq!(round_trip(&Tagged::Entity(3, Some("local".to_owned()))), Tagged::Entity(3, None));
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Schema)]
+pub struct PlayerState {
+    #[netstack(bits = 5)]
+    pub weapon: u8,
+    #[netstack(range = 0..=100)]
+    pub health: u32,
+    #[netstack(range = -8..=7)]
+    pub lean: i8,
+    pub crouching: bool,
+    #[netstack(quantize(min = -512.0, max = 512.0, precision = 0.01))]
+    pub x: f32,
+}
+
+#[test]
+fn it_packs_fields_with_encoding_attributes() {
+    let state = PlayerState {
+        weapon: 17,
+        health: 100,
+        lean: -8,
+        crouching: true,
+        x: -123.456,
+    };
+
+    // 5 + 7 + 4 + 1 + 17 bits
+    assert_eq!(state.encoded_bits().unwrap(), 34);
+
+    let result = bit_round_trip(&state);
+    assert_eq!((result.weapon, result.health, result.lean, result.crouching), (17, 100, -8, true));
+    assert!((result.x - state.x).abs() <= 0.005 + f32::EPSILON * 512.0);
+
+    let result = round_trip(&state);
+    assert_eq!((result.weapon, result.health, result.lean), (17, 100, -8));
+}
+
+#[test]
+fn it_rejects_values_outside_of_the_attribute_range() {
+    let state = PlayerState {
+        weapon: 32,
+        health: 0,
+        lean: 0,
+        crouching: false,
+        x: 0.0,
+    };
+
+    assert!(matches!(state.encoded_bits(), Err(SerializationError::BitsOverflow { .. })));
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, DeltaSerialize, DeltaDeserialize)]
+pub struct Transform {
+    #[netstack(quantize(min = -512.0, max = 512.0, precision = 0.01))]
+    pub x: f32,
+    #[netstack(quantize(min = -512.0, max = 512.0, precision = 0.01))]
+    pub y: f32,
+    #[netstack(bits = 9)]
+    pub rotation: u16,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, DeltaSerialize, DeltaDeserialize)]
+pub enum Activity {
+    Idle,
+    Walking { speed: u8, target: Option<(i32, i32)> },
+    Talking(String),
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, DeltaSerialize, DeltaDeserialize)]
+pub struct WorldSnapshot {
+    pub tick: u64,
+    pub players: Vec<Transform>,
+    pub activity: Activity,
+    #[netstack(skip)]
+    pub interpolated: bool,
+}
+
+fn delta_round_trip<T>(value: &T, baseline: Option<&T>) -> (T, usize)
+    where T: DeltaSerialize + for<'de> DeltaDeserialize<'de>
+{
+    let mut buffer = [0; 1024];
+    let length = {
+        let mut writer = BitWriter::new(&mut buffer);
+        value.serialize_with_baseline(baseline, &mut writer).expect("It serializes the delta");
+        writer.get_bit_position()
+    };
+
+    let mut reader = BitReader::new(&buffer[0..length.div_ceil(8)]);
+    let result = T::deserialize_with_baseline(baseline, &mut reader).expect("It deserializes the delta");
+
+    (result, length)
+}
+
+fn snapshot() -> WorldSnapshot {
+    WorldSnapshot {
+        tick: 100,
+        players: vec![
+            Transform { x: 1.0, y: 2.0, rotation: 90 },
+            Transform { x: -10.0, y: 20.0, rotation: 180 },
+        ],
+        activity: Activity::Walking { speed: 3, target: Some((10, -10)) },
+        interpolated: false,
+    }
+}
+
+#[test]
+fn it_only_sends_changed_fields() {
+    let baseline = snapshot();
+    let mut value = snapshot();
+    value.tick = 101;
+    value.players[1].rotation = 181;
+
+    let (result, bits) = delta_round_trip(&value, Some(&baseline));
+    assert_eq!(result, value);
+
+    // flag, field mask, tick, kept and appended players, player mask, transform mask and rotation
+    assert_eq!(bits, 1 + 3 + 8 + 8 + 8 + 2 + 3 + 9);
+    assert!(bits < value.encoded_bits().unwrap());
+}
+
+#[test]
+fn it_recurses_into_nested_types() {
+    let baseline = snapshot();
+    let mut value = snapshot();
+    value.activity = Activity::Walking { speed: 3, target: Some((11, -10)) };
+    assert_eq!(delta_round_trip(&value, Some(&baseline)).0, value);
+
+    value.activity = Activity::Talking("hi".to_owned());
+    assert_eq!(delta_round_trip(&value, Some(&baseline)).0, value);
+
+    value.activity = Activity::Idle;
+    assert_eq!(delta_round_trip(&value, Some(&baseline)).0, value);
+}
+
+#[test]
+fn it_sends_the_full_value_without_a_baseline() {
+    let value = snapshot();
+    let (result, bits) = delta_round_trip(&value, None);
+
+    // quantized fields come back the same way as without a delta
+    assert_eq!(result, bit_round_trip(&value));
+    assert_eq!(bits, 1 + value.encoded_bits().unwrap());
+}
+
+#[test]
+fn it_fills_in_skipped_fields_in_deltas() {
+    let mut baseline = snapshot();
+    baseline.interpolated = true;
+
+    let (result, _) = delta_round_trip(&snapshot(), Some(&baseline));
+    assert!(!result.interpolated);
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, DeltaSerialize, DeltaDeserialize, Schema)]
+pub struct Profile {
+    pub name: String,
+    #[netstack(until = 3)]
+    pub legacy_score: u32,
+    #[netstack(since = 2, default = "String::from(\"en\")")]
+    pub locale: String,
+    #[netstack(since = 3, bits = 4)]
+    pub rank: u8,
+}
+
+fn versioned_round_trip(value: &Profile, version: u32) -> (Profile, usize) {
+    let mut buffer = [0; 1024];
+    let length = {
+        let mut writer = Writer::new(&mut buffer).with_version(version);
+        value.serialize(&mut writer).expect("It serializes the value");
+        writer.get_position()
+    };
+
+    let mut reader = Reader::new(&buffer[0..length]).with_version(version);
+    let result = Profile::deserialize(&mut reader).expect("It deserializes the value");
+    assert_eq!(reader.get_position(), length);
+
+    (result, length)
+}
+
+fn profile() -> Profile {
+    Profile {
+        name: "ada".to_owned(),
+        legacy_score: 42,
+        locale: "de".to_owned(),
+        rank: 9,
+    }
+}
+
+#[test]
+fn it_writes_the_fields_of_a_protocol_version() {
+    let value = profile();
+
+    let (result, length) = versioned_round_trip(&value, 1);
+    assert_eq!(result, Profile { locale: "en".to_owned(), rank: 0, ..value.clone() });
+    assert_eq!(length, 4 + 1);
+
+    let (result, length) = versioned_round_trip(&value, 2);
+    assert_eq!(result, Profile { rank: 0, ..value.clone() });
+    assert_eq!(length, 4 + 1 + 3);
+
+    let (result, length) = versioned_round_trip(&value, 3);
+    assert_eq!(result, Profile { legacy_score: 0, ..value.clone() });
+    assert_eq!(length, 4 + 3 + 1);
+}
+
+#[test]
+fn it_uses_the_latest_version_by_default() {
+    let value = profile();
+    let (result, _) = versioned_round_trip(&value, LATEST_VERSION);
+
+    assert_eq!(round_trip(&value), result);
+    assert_eq!(value.encoded_size().unwrap(), 4 + 3 + 1);
+}
+
+#[test]
+fn it_leaves_out_missing_fields_in_deltas() {
+    let baseline = Profile { legacy_score: 0, locale: "en".to_owned(), rank: 0, ..profile() };
+    let value = profile();
+
+    let mut buffer = [0; 1024];
+    let length = {
+        let mut writer = BitWriter::new(&mut buffer).with_version(1);
+        value.serialize_delta(&baseline, &mut writer).expect("It serializes the delta");
+        writer.get_bit_position()
+    };
+
+    // the field mask, followed by legacy_score
+    assert_eq!(length, 4 + 8);
+
+    let mut reader = BitReader::new(&buffer[0..length.div_ceil(8)]).with_version(1);
+    let result = Profile::deserialize_delta(&baseline, &mut reader).expect("It deserializes the delta");
+    assert_eq!(result, Profile { locale: "en".to_owned(), rank: 0, ..value });
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Message)]
+#[netstack(id = 7)]
+pub struct Join {
+    pub name: String,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Message, Schema)]
+pub enum Input {
+    Jump,
+    Move(i8, i8),
+}
+
+fn message_payload<M>(message: &M) -> Payload where M: Message {
+    let mut buffer = PacketBuffer::unpooled(DEFAULT_MTU);
+    let length = {
+        let mut writer = Writer::new(&mut buffer[HEADER_SIZE..]);
+        serialize_message(message, &mut writer).expect("It serializes the message");
+        writer.get_position()
+    };
+
+    let mut packet = RawPacket::new(buffer, HEADER_SIZE + length);
+    packet.get_header_mut().body_length = length as u16;
+    Payload::from_raw_packet(packet)
+}
+
+#[test]
+fn it_derives_message_ids() {
+    assert_eq!(Join::ID, 7);
+    assert_eq!(Input::ID, message_id("Input"));
+    assert_eq!(Snapshot::<u8>::ID, Snapshot::<String>::ID);
+}
+
+#[test]
+fn it_decodes_messages_by_id() {
+    let payload = message_payload(&Input::Move(-1, 1));
+
+    assert_eq!(payload.get_message_id().unwrap(), Input::ID);
+    assert_eq!(payload.deserialize_message::<Input>().unwrap(), Input::Move(-1, 1));
+    assert!(matches!(
+        payload.deserialize_message::<Join>(),
+        Err(SerializationError::UnexpectedMessageId { expected: 7, found }) if found == Input::ID
+    ));
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, DeltaSerialize, DeltaDeserialize, Clone, Schema)]
+pub struct Lobby {
+    #[netstack(max_len = 32)]
+    pub name: String,
+    #[netstack(max_len = 200)]
+    pub players: Vec<EntityId>,
+    #[netstack(skip)]
+    pub cache: Vec<u8>,
+}
+
+const _: () = assert_max_encoded_size::<Lobby>(MAX_PAYLOAD_SIZE);
+const _: () = assert_max_encoded_size::<PlayerState>(MAX_PAYLOAD_SIZE);
+
+#[test]
+fn it_calculates_the_maximum_encoded_size() {
+    assert_eq!(EntityId::MAX_ENCODED_SIZE, Some(5));
+    assert_eq!(Heartbeat::MAX_ENCODED_SIZE, Some(0));
+    assert_eq!(Never::MAX_ENCODED_SIZE, Some(0));
+    assert_eq!(PlayerState::MAX_ENCODED_SIZE, Some(1 + 1 + 1 + 1 + 3));
+    assert_eq!(Snapshot::<u8>::MAX_ENCODED_SIZE, None);
+    assert_eq!(Command::MAX_ENCODED_SIZE, None);
+    assert_eq!(Input::MAX_ENCODED_SIZE, Some(1 + 2));
+    assert_eq!(Lobby::MAX_ENCODED_SIZE, Some(1 + 32 + 2 + 200 * 5));
+}
+
+#[test]
+fn it_stays_within_the_maximum_encoded_size() {
+    let lobby = Lobby {
+        name: "x".repeat(32),
+        players: vec![EntityId(u32::MAX); 200],
+        cache: Vec::new(),
+    };
+    assert_eq!(lobby.encoded_size().unwrap(), Lobby::MAX_ENCODED_SIZE.unwrap());
+    assert_eq!(round_trip(&lobby), lobby);
+
+    let state = PlayerState { weapon: 31, health: 100, lean: -8, crouching: true, x: 512.0 };
+    assert_eq!(state.encoded_size().unwrap(), PlayerState::MAX_ENCODED_SIZE.unwrap());
+}
+
+#[test]
+fn it_rejects_fields_longer_than_their_maximum_length() {
+    let lobby = Lobby { name: "x".repeat(33), players: Vec::new(), cache: Vec::new() };
+    let mut buffer = [0; 1024];
+
+    let result = lobby.serialize(&mut Writer::new(&mut buffer));
+    assert!(matches!(result, Err(SerializationError::LengthExceedsMaximum { length: 33, max: 32 })));
+
+    let length = {
+        let mut writer = Writer::new(&mut buffer);
+        ("x".repeat(33), Vec::<EntityId>::new()).serialize(&mut writer).unwrap();
+        writer.get_position()
+    };
+    let result = Lobby::deserialize(&mut Reader::new(&buffer[0..length]));
+    assert!(matches!(result, Err(SerializationError::LengthExceedsMaximum { length: 33, max: 32 })));
+
+    // the length prefix alone fails, before any of the players is read
+    let result = Lobby::deserialize(&mut Reader::new(&[0x00, 0xc9, 0x01]));
+    assert!(matches!(result, Err(SerializationError::LengthExceedsMaximum { length: 201, max: 200 })));
+
+    let baseline = Lobby { name: String::new(), players: Vec::new(), cache: Vec::new() };
+    let result = lobby.serialize_delta(&baseline, &mut Writer::new(&mut buffer));
+    assert!(matches!(result, Err(SerializationError::LengthExceedsMaximum { length: 33, max: 32 })));
+}
+
+#[test]
+fn it_registers_nested_schemas() {
+    let mut registry = Registry::new();
+    registry.register::<Inventory>().register::<Snapshot<u8>>().register::<Snapshot<String>>();
+
+    let names: Vec<_> = registry.get_definitions().map(|definition| definition.name.as_str()).collect();
+    assert_eq!(names, vec!["EntityId", "Greeting", "Inventory", "Snapshot<String>", "Snapshot<u8>"]);
+
+    let inventory = registry.get_definition("Inventory").unwrap();
+    match &inventory.kind {
+        DefinitionKind::Struct { fields } => {
+            let types: Vec<_> = fields.iter().map(|field| field.field_type.to_string()).collect();
+            assert_eq!(types, vec!["Option<Greeting>", "Vec<(u16, u8)>", "[Option<u16>; 4]", "Map<String, bool>", "Vec<i64>"]);
+        },
+        _ => panic!("Inventory is a struct"),
+    }
+}
+
+#[test]
+fn it_describes_field_attributes_in_schemas() {
+    let mut registry = Registry::new();
+    registry.register::<PlayerState>().register::<Profile>().register::<Cached>().register::<Lobby>();
+
+    let fields = |name: &str| match &registry.get_definition(name).unwrap().kind {
+        DefinitionKind::Struct { fields } => fields.clone(),
+        _ => panic!("{} is a struct", name),
+    };
+
+    let state = fields("PlayerState");
+    assert_eq!(state[0].encoding, Some(Encoding::Bits { bits: 5 }));
+    assert_eq!(state[1].encoding, Some(Encoding::Range { min: 0, max: 100, bits: 7 }));
+    assert_eq!(state[4].encoding, Some(Encoding::Quantize { min: -512.0, max: 512.0, precision: 0.01, bits: 17 }));
+
+    let profile = fields("Profile");
+    assert_eq!((profile[1].since, profile[1].until), (None, Some(3)));
+    assert_eq!((profile[3].since, profile[3].until), (Some(3), None));
+
+    let cached = fields("Cached");
+    assert_eq!(cached.len(), 2);
+    assert_eq!(cached[1].field_type, Type::Custom("as_text".to_owned()));
+
+    assert_eq!(fields("Lobby")[0].max_len, Some(32));
+}
+
+#[test]
+fn it_exports_enum_schemas_with_message_ids() {
+    let mut registry = Registry::new();
+    registry.register_message::<Input>().register::<Command>();
+
+    match &registry.get_definition("Command").unwrap().kind {
+        DefinitionKind::Enum { discriminant_bits, variants } => {
+            assert_eq!(*discriminant_bits, 3);
+            assert_eq!(variants[2].name, "Say");
+            assert_eq!(variants[2].fields[0].field_type, Type::Option(Box::new(Type::U32)));
+        },
+        _ => panic!("Command is an enum"),
+    }
+
+    assert_eq!(Input::get_type(), Type::Named("Input".to_owned()));
+    assert_eq!(registry.get_definition("Input").unwrap().message_id, Some(Input::ID));
+    assert!(registry.to_json().contains(&format!("\"message_id\": {}", Input::ID)));
+}
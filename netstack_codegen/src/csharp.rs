@@ -0,0 +1,348 @@
+//! C# classes with a static `Read` and an instance `Write` method for every definition.
+//!
+//! Structs become sealed classes, enums become an abstract class with a nested class per variant.
+//! Fields that don't exist in the protocol version of the reader keep their default value.
+
+use netstack::schema::{Definition, DefinitionKind, Encoding, Field, Type, Variant};
+use crate::output::Output;
+use crate::{pascal_case, type_identifier, CodegenError};
+
+const RUNTIME: &str = include_str!("runtime/Netstack.cs");
+
+pub fn generate(definitions: &[Definition], namespace: &str) -> Result<String, CodegenError> {
+    if namespace.is_empty() {
+        return Err(CodegenError::InvalidArguments { message: "C# code needs a namespace".to_owned() });
+    }
+
+    let mut output = Output::new("    ");
+    output.line("// Generated by netstack-codegen, do not edit.");
+    output.line("using System;");
+    output.line("using System.Collections.Generic;");
+    output.line("using System.Text;");
+    output.line("");
+    output.line(format!("namespace {}", namespace));
+    output.open("{");
+    output.raw(RUNTIME);
+
+    for definition in definitions {
+        output.line("");
+        match &definition.kind {
+            DefinitionKind::Struct { fields } => structure(&mut output, definition, fields),
+            DefinitionKind::Enum { discriminant_bits, variants } => enumeration(&mut output, definition, *discriminant_bits, variants),
+        }
+    }
+
+    output.close("}");
+    Ok(output.finish())
+}
+
+fn structure(output: &mut Output, definition: &Definition, fields: &[Field]) {
+    let name = type_identifier(&definition.name);
+
+    output.line(format!("/// <summary>{}</summary>", escape(&definition.name)));
+    output.line(format!("public sealed class {}", name));
+    output.open("{");
+    message_id(output, definition);
+    field_declarations(output, fields);
+
+    output.line(format!("public static {} Read(NetstackReader reader)", name));
+    output.open("{");
+    output.line(format!("var value = new {}();", name));
+    read_fields(output, fields);
+    output.line("return value;");
+    output.close("}");
+    output.line("");
+
+    output.line("public void Write(NetstackWriter writer)");
+    output.open("{");
+    write_fields(output, fields);
+    output.close("}");
+    output.close("}");
+}
+
+fn enumeration(output: &mut Output, definition: &Definition, discriminant_bits: u32, variants: &[Variant]) {
+    let name = type_identifier(&definition.name);
+
+    output.line(format!("/// <summary>{}</summary>", escape(&definition.name)));
+    output.line(format!("public abstract class {}", name));
+    output.open("{");
+    message_id(output, definition);
+
+    output.line(format!("public static {} Read(NetstackReader reader)", name));
+    output.open("{");
+    output.line(format!("switch (reader.ReadBits({}))", discriminant_bits));
+    output.open("{");
+    for (index, variant) in variants.iter().enumerate() {
+        output.line(format!("case {}: return {}.ReadFields(reader);", index, variant.name));
+    }
+    output.line(format!("default: throw new NetstackException(\"Unknown variant of {}\");", definition.name));
+    output.close("}");
+    output.close("}");
+    output.line("");
+    output.line("public abstract void Write(NetstackWriter writer);");
+
+    for (index, variant) in variants.iter().enumerate() {
+        output.line("");
+        output.line(format!("public sealed class {} : {}", variant.name, name));
+        output.open("{");
+        field_declarations(output, &variant.fields);
+
+        output.line(format!("internal static {} ReadFields(NetstackReader reader)", variant.name));
+        output.open("{");
+        output.line(format!("var value = new {}();", variant.name));
+        read_fields(output, &variant.fields);
+        output.line("return value;");
+        output.close("}");
+        output.line("");
+
+        output.line("public override void Write(NetstackWriter writer)");
+        output.open("{");
+        output.line(format!("writer.WriteBits({}, {});", index, discriminant_bits));
+        write_fields(output, &variant.fields);
+        output.close("}");
+        output.close("}");
+    }
+
+    output.close("}");
+}
+
+fn message_id(output: &mut Output, definition: &Definition) {
+    if let Some(id) = definition.message_id {
+        output.line(format!("public const uint MessageId = {};", id));
+        output.line("");
+    }
+}
+
+fn field_declarations(output: &mut Output, fields: &[Field]) {
+    for field in fields {
+        if let Some(Encoding::Quantize { min, max, precision, .. }) = &field.encoding {
+            output.line(format!(
+                "private static readonly NetstackQuantization {}Quantization = new NetstackQuantization({}, {}, {});",
+                pascal_case(&field.name), float(*min), float(*max), float(*precision),
+            ));
+        }
+    }
+
+    for field in fields {
+        let name = pascal_case(&field.name);
+
+        match default_value(&field.field_type) {
+            Some(default) => output.line(format!("public {} {} = {};", type_name(&field.field_type), name, default)),
+            None => output.line(format!("public {} {};", type_name(&field.field_type), name)),
+        }
+    }
+
+    if !fields.is_empty() {
+        output.line("");
+    }
+}
+
+fn read_fields(output: &mut Output, fields: &[Field]) {
+    for field in fields {
+        let name = pascal_case(&field.name);
+        let value = match &field.encoding {
+            Some(Encoding::Bits { bits }) => format!("({})reader.ReadBits({})", type_name(&field.field_type), bits),
+            Some(Encoding::Range { min, max, .. }) => format!("({})reader.ReadRanged({}, {})", type_name(&field.field_type), long(*min), long(*max)),
+            Some(Encoding::Quantize { .. }) => format!("{}Quantization.Read(reader)", name),
+            None => read(&field.field_type, "reader", 0),
+        };
+
+        let statement = match field.max_len {
+            Some(max_len) => format!("value.{} = NetstackHelpers.CheckMaxLength({}, {}, {});", name, value, length(&field.field_type), max_len),
+            None => format!("value.{} = {};", name, value),
+        };
+
+        conditional(output, field, "reader", statement);
+    }
+}
+
+fn write_fields(output: &mut Output, fields: &[Field]) {
+    for field in fields {
+        let name = pascal_case(&field.name);
+        let statement = match &field.encoding {
+            Some(Encoding::Bits { bits }) => format!("writer.WriteBits({}, {});", name, bits),
+            Some(Encoding::Range { min, max, .. }) => format!("writer.WriteRanged((long){}, {}, {});", name, long(*min), long(*max)),
+            Some(Encoding::Quantize { .. }) => format!("{}Quantization.Write(writer, {});", name, name),
+            None => write(&field.field_type, "writer", &name, 0),
+        };
+
+        let statement = match field.max_len {
+            Some(max_len) => format!("NetstackHelpers.CheckMaxLength({}, {}, {}); {}", name, length(&field.field_type), max_len, statement),
+            None => statement,
+        };
+
+        conditional(output, field, "writer", statement);
+    }
+}
+
+/// Only reads or writes a field if it exists in the version of the reader or writer.
+fn conditional(output: &mut Output, field: &Field, target: &str, statement: String) {
+    let condition = match (field.since, field.until) {
+        (Some(since), Some(until)) => format!("{}.Version >= {} && {}.Version < {}", target, since, target, until),
+        (Some(since), None) => format!("{}.Version >= {}", target, since),
+        (None, Some(until)) => format!("{}.Version < {}", target, until),
+        (None, None) => return output.line(statement),
+    };
+
+    output.line(format!("if ({}) {{ {} }}", condition, statement));
+}
+
+fn type_name(field_type: &Type) -> String {
+    match field_type {
+        Type::Bool => "bool".to_owned(),
+        Type::U8 => "byte".to_owned(),
+        Type::I8 => "sbyte".to_owned(),
+        Type::U16 => "ushort".to_owned(),
+        Type::I16 => "short".to_owned(),
+        Type::U32 => "uint".to_owned(),
+        Type::I32 => "int".to_owned(),
+        Type::U64 => "ulong".to_owned(),
+        Type::I64 => "long".to_owned(),
+        Type::F32 => "float".to_owned(),
+        Type::F64 => "double".to_owned(),
+        Type::String => "string".to_owned(),
+        Type::Bytes => "byte[]".to_owned(),
+        Type::Option(inner) if is_value_type(inner) => format!("{}?", type_name(inner)),
+        Type::Option(inner) => type_name(inner),
+        Type::Vec(element) => format!("List<{}>", type_name(element)),
+        Type::Array(element, _) => format!("{}[]", type_name(element)),
+        Type::Map(key, value) => format!("Dictionary<{}, {}>", type_name(key), type_name(value)),
+        Type::Tuple(elements) => match elements.as_slice() {
+            [] => "ValueTuple".to_owned(),
+            [element] => format!("ValueTuple<{}>", type_name(element)),
+            elements => format!("({})", elements.iter().map(type_name).collect::<Vec<_>>().join(", ")),
+        },
+        Type::Named(name) => type_identifier(name),
+        Type::Custom(module) => unreachable!("custom field {} was rejected before", module),
+    }
+}
+
+fn is_value_type(field_type: &Type) -> bool {
+    !matches!(field_type, Type::String | Type::Bytes | Type::Vec(_) | Type::Array(..) | Type::Map(..) | Type::Named(_) | Type::Custom(_))
+}
+
+fn default_value(field_type: &Type) -> Option<String> {
+    match field_type {
+        Type::String => Some("\"\"".to_owned()),
+        Type::Bytes => Some("new byte[0]".to_owned()),
+        Type::Vec(_) | Type::Map(..) => Some(format!("new {}()", type_name(field_type))),
+        Type::Array(element, length) => match **element {
+            Type::Bytes | Type::Array(..) => None,
+            ref element => Some(format!("new {}[{}]", type_name(element), length)),
+        },
+        _ => None,
+    }
+}
+
+/// The length checked by `max_len`, strings are limited by their encoded length.
+fn length(field_type: &Type) -> &'static str {
+    match field_type {
+        Type::String => "NetstackHelpers.GetLength",
+        Type::Vec(_) | Type::Map(..) => "items => items.Count",
+        _ => "items => items.Length",
+    }
+}
+
+fn read(field_type: &Type, reader: &str, depth: usize) -> String {
+    let next_reader = format!("r{}", depth);
+
+    match field_type {
+        Type::Bool => format!("{}.ReadBool()", reader),
+        Type::U8 => format!("{}.ReadU8()", reader),
+        Type::I8 => format!("{}.ReadI8()", reader),
+        Type::U16 => format!("{}.ReadU16()", reader),
+        Type::I16 => format!("{}.ReadI16()", reader),
+        Type::U32 => format!("{}.ReadU32()", reader),
+        Type::I32 => format!("{}.ReadI32()", reader),
+        Type::U64 => format!("{}.ReadU64()", reader),
+        Type::I64 => format!("{}.ReadI64()", reader),
+        Type::F32 => format!("{}.ReadF32()", reader),
+        Type::F64 => format!("{}.ReadF64()", reader),
+        Type::String => format!("{}.ReadString()", reader),
+        Type::Bytes => format!("{}.ReadBytes()", reader),
+        Type::Option(inner) => format!(
+            "{}.{}({} => {})",
+            reader,
+            if is_value_type(inner) { "ReadOptionalValue" } else { "ReadOptional" },
+            next_reader,
+            read(inner, &next_reader, depth + 1),
+        ),
+        Type::Vec(element) => format!("{}.ReadList({} => {})", reader, next_reader, read(element, &next_reader, depth + 1)),
+        Type::Array(element, length) => format!("{}.ReadArray({}, {} => {})", reader, length, next_reader, read(element, &next_reader, depth + 1)),
+        Type::Map(key, value) => format!(
+            "{}.ReadMap({} => {}, {} => {})",
+            reader,
+            next_reader,
+            read(key, &next_reader, depth + 1),
+            next_reader,
+            read(value, &next_reader, depth + 1),
+        ),
+        Type::Tuple(elements) => match elements.as_slice() {
+            [] => "default(ValueTuple)".to_owned(),
+            [element] => format!("ValueTuple.Create({})", read(element, reader, depth)),
+            elements => format!("({})", elements.iter().map(|element| read(element, reader, depth)).collect::<Vec<_>>().join(", ")),
+        },
+        Type::Named(name) => format!("{}.Read({})", type_identifier(name), reader),
+        Type::Custom(module) => unreachable!("custom field {} was rejected before", module),
+    }
+}
+
+fn write(field_type: &Type, writer: &str, value: &str, depth: usize) -> String {
+    // lambdas get their own parameter names, C# doesn't allow them to shadow outer ones
+    let next_writer = format!("w{}", depth);
+    let next_value = format!("v{}", depth);
+    let lambda = |inner: &Type| format!(
+        "({}, {}) => {{ {} }}",
+        next_writer,
+        next_value,
+        write(inner, &next_writer, &next_value, depth + 1),
+    );
+
+    match field_type {
+        Type::Bool => format!("{}.WriteBool({});", writer, value),
+        Type::U8 => format!("{}.WriteU8({});", writer, value),
+        Type::I8 => format!("{}.WriteI8({});", writer, value),
+        Type::U16 => format!("{}.WriteU16({});", writer, value),
+        Type::I16 => format!("{}.WriteI16({});", writer, value),
+        Type::U32 => format!("{}.WriteU32({});", writer, value),
+        Type::I32 => format!("{}.WriteI32({});", writer, value),
+        Type::U64 => format!("{}.WriteU64({});", writer, value),
+        Type::I64 => format!("{}.WriteI64({});", writer, value),
+        Type::F32 => format!("{}.WriteF32({});", writer, value),
+        Type::F64 => format!("{}.WriteF64({});", writer, value),
+        Type::String => format!("{}.WriteString({});", writer, value),
+        Type::Bytes => format!("{}.WriteBytes({});", writer, value),
+        Type::Option(inner) => format!(
+            "{}.{}({}, {});",
+            writer,
+            if is_value_type(inner) { "WriteOptionalValue" } else { "WriteOptional" },
+            value,
+            lambda(inner),
+        ),
+        Type::Vec(element) => format!("{}.WriteList({}, {});", writer, value, lambda(element)),
+        Type::Array(element, length) => format!("{}.WriteArray({}, {}, {});", writer, value, length, lambda(element)),
+        Type::Map(key, map_value) => format!("{}.WriteMap({}, {}, {});", writer, value, lambda(key), lambda(map_value)),
+        Type::Tuple(elements) => elements.iter()
+            .enumerate()
+            .map(|(index, element)| write(element, writer, &format!("{}.Item{}", value, index + 1), depth))
+            .collect::<Vec<_>>()
+            .join(" "),
+        Type::Named(_) => format!("{}.Write({});", value, writer),
+        Type::Custom(module) => unreachable!("custom field {} was rejected before", module),
+    }
+}
+
+fn float(value: f32) -> String {
+    format!("{:?}f", value)
+}
+
+fn long(value: i64) -> String {
+    match value {
+        i64::MIN => "long.MinValue".to_owned(),
+        value => format!("{}L", value),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
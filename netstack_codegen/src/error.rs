@@ -0,0 +1,25 @@
+#[derive(Debug, Fail)]
+pub enum CodegenError {
+    #[fail(display = "Invalid schema: {}", message)]
+    InvalidSchema {
+        message: String,
+    },
+
+    #[fail(display = "Field {} of {} cannot be generated: {}", field, definition, reason)]
+    UnsupportedField {
+        definition: String,
+        field: String,
+        reason: String,
+    },
+
+    #[fail(display = "{}", message)]
+    InvalidArguments {
+        message: String,
+    },
+}
+
+impl CodegenError {
+    pub(crate) fn invalid_schema(message: impl Into<String>) -> Self {
+        CodegenError::InvalidSchema { message: message.into() }
+    }
+}
@@ -0,0 +1,181 @@
+//! Generates readers and writers for netstack messages in other languages.
+//!
+//! The input is the JSON exported by `netstack::schema::Registry`, the generated code reads and
+//! writes the byte aligned encoding of `netstack::serialization::Writer`.
+
+#[macro_use]
+extern crate failure;
+
+mod error;
+mod parse;
+mod output;
+pub mod csharp;
+pub mod typescript;
+
+pub use error::CodegenError;
+pub use parse::parse_schema;
+
+use netstack::schema::{Definition, DefinitionKind, Encoding, Field, Type};
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Language {
+    CSharp,
+    TypeScript,
+}
+
+impl Language {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "csharp" | "cs" => Some(Language::CSharp),
+            "typescript" | "ts" => Some(Language::TypeScript),
+            _ => None,
+        }
+    }
+}
+
+/// Generates code for every definition in the schema.
+/// `namespace` is only used by languages that have one, like C#.
+pub fn generate(schema: &str, language: Language, namespace: &str) -> Result<String, CodegenError> {
+    let definitions = parse_schema(schema)?;
+    generate_definitions(&definitions, language, namespace)
+}
+
+pub fn generate_definitions(definitions: &[Definition], language: Language, namespace: &str) -> Result<String, CodegenError> {
+    for definition in definitions {
+        match &definition.kind {
+            DefinitionKind::Struct { fields } => check_fields(&definition.name, fields)?,
+            DefinitionKind::Enum { variants, .. } => for variant in variants {
+                check_fields(&definition.name, &variant.fields)?;
+            },
+        }
+    }
+
+    match language {
+        Language::CSharp => csharp::generate(definitions, namespace),
+        Language::TypeScript => typescript::generate(definitions),
+    }
+}
+
+/// Rejects fields the generated code cannot read and write like the Rust side does.
+fn check_fields(definition: &str, fields: &[Field]) -> Result<(), CodegenError> {
+    for field in fields {
+        let unsupported = |reason: &str| CodegenError::UnsupportedField {
+            definition: definition.to_owned(),
+            field: field.name.clone(),
+            reason: reason.to_owned(),
+        };
+
+        check_type(&field.field_type).map_err(unsupported)?;
+
+        match (&field.encoding, &field.field_type) {
+            (None, _) => (),
+            (Some(Encoding::Bits { bits }), Type::U8) if *bits <= 8 => (),
+            (Some(Encoding::Bits { bits }), Type::U16) if *bits <= 16 => (),
+            (Some(Encoding::Bits { bits }), Type::U32) if *bits <= 32 => (),
+            (Some(Encoding::Bits { bits }), Type::U64) if *bits <= 64 => (),
+            (Some(Encoding::Range { min, max, .. }), Type::U8)
+            | (Some(Encoding::Range { min, max, .. }), Type::I8)
+            | (Some(Encoding::Range { min, max, .. }), Type::U16)
+            | (Some(Encoding::Range { min, max, .. }), Type::I16)
+            | (Some(Encoding::Range { min, max, .. }), Type::U32)
+            | (Some(Encoding::Range { min, max, .. }), Type::I32)
+            | (Some(Encoding::Range { min, max, .. }), Type::U64)
+            | (Some(Encoding::Range { min, max, .. }), Type::I64) if min < max => (),
+            (Some(Encoding::Quantize { .. }), Type::F32) => (),
+            (Some(_), _) => return Err(unsupported("the encoding does not fit the type")),
+        }
+    }
+
+    Ok(())
+}
+
+fn check_type(field_type: &Type) -> Result<(), &'static str> {
+    match field_type {
+        Type::Custom(_) => Err("it is written by a custom module"),
+        Type::Option(inner) => match **inner {
+            // both would be `null` in the generated code
+            Type::Option(_) => Err("nested options cannot be told apart"),
+            ref inner => check_type(inner),
+        },
+        Type::Vec(element) | Type::Array(element, _) => check_type(element),
+        Type::Map(key, value) => check_type(key).and_then(|_| check_type(value)),
+        Type::Tuple(elements) => elements.iter().try_for_each(check_type),
+        _ => Ok(()),
+    }
+}
+
+/// Turns the name of a definition like `Snapshot<Vec<u8>>` into an identifier like `SnapshotVecU8`.
+pub(crate) fn type_identifier(name: &str) -> String {
+    name.split(|character: char| !character.is_ascii_alphanumeric() && character != '_')
+        .filter(|part| !part.is_empty())
+        .map(capitalize)
+        .collect()
+}
+
+/// Turns a field name like `entity_id` into `EntityId`, tuple fields like `0` become `Item0`.
+pub(crate) fn pascal_case(name: &str) -> String {
+    if name.starts_with(|character: char| character.is_ascii_digit()) {
+        return format!("Item{}", name);
+    }
+
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(capitalize)
+        .collect()
+}
+
+/// Turns a field name like `entity_id` into `entityId`, tuple fields like `0` become `item0`.
+pub(crate) fn camel_case(name: &str) -> String {
+    let pascal = pascal_case(name);
+    let mut characters = pascal.chars();
+
+    match characters.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + characters.as_str(),
+        None => pascal,
+    }
+}
+
+fn capitalize(part: &str) -> String {
+    let mut characters = part.chars();
+
+    match characters.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + characters.as_str(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_converts_names() {
+        assert_eq!(type_identifier("Snapshot<Vec<u8>>"), "SnapshotVecU8");
+        assert_eq!(type_identifier("Pair<String, [u8; 4]>"), "PairStringU84");
+        assert_eq!(pascal_case("entity_id"), "EntityId");
+        assert_eq!(pascal_case("0"), "Item0");
+        assert_eq!(camel_case("entity_id"), "entityId");
+        assert_eq!(camel_case("1"), "item1");
+    }
+
+    #[test]
+    fn it_rejects_fields_it_cannot_generate() {
+        let definition = |field: Field| vec![Definition {
+            name: "State".to_owned(),
+            message_id: None,
+            kind: DefinitionKind::Struct { fields: vec![field] },
+        }];
+
+        let custom = Field::new("position", Type::Custom("codec::position".to_owned()));
+        let nested = Field::new("target", Type::Option(Box::new(Type::Option(Box::new(Type::U8)))));
+        let mut quantized = Field::new("health", Type::U8);
+        quantized.encoding = Some(Encoding::Quantize { min: 0.0, max: 1.0, precision: 0.1, bits: 4 });
+
+        for field in [custom, nested, quantized] {
+            match generate_definitions(&definition(field), Language::TypeScript, "") {
+                Err(CodegenError::UnsupportedField { definition, .. }) => assert_eq!(definition, "State"),
+                result => panic!("expected an unsupported field, got {:?}", result),
+            }
+        }
+    }
+}
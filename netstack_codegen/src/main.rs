@@ -0,0 +1,64 @@
+use std::fs;
+use std::process;
+use netstack_codegen::{generate, CodegenError, Language};
+
+const USAGE: &str = "usage: netstack-codegen <schema.json> --lang <csharp|typescript> [--namespace <name>] [--output <file>]";
+
+struct Arguments {
+    schema: String,
+    language: Language,
+    namespace: String,
+    output: Option<String>,
+}
+
+fn parse_arguments(mut arguments: impl Iterator<Item = String>) -> Result<Arguments, CodegenError> {
+    let invalid = |message: String| CodegenError::InvalidArguments { message: format!("{}\n{}", message, USAGE) };
+
+    let mut schema = None;
+    let mut language = None;
+    let mut namespace = "Netstack.Messages".to_owned();
+    let mut output = None;
+
+    while let Some(argument) = arguments.next() {
+        let mut value = || arguments.next().ok_or_else(|| invalid(format!("missing value for {}", argument)));
+
+        match argument.as_str() {
+            "--lang" | "-l" => {
+                let name = value()?;
+                language = Some(Language::from_name(&name).ok_or_else(|| invalid(format!("unknown language {}", name)))?);
+            },
+            "--namespace" | "-n" => namespace = value()?,
+            "--output" | "-o" => output = Some(value()?),
+            _ if argument.starts_with('-') => return Err(invalid(format!("unknown option {}", argument))),
+            _ if schema.is_none() => schema = Some(argument),
+            _ => return Err(invalid(format!("unexpected argument {}", argument))),
+        }
+    }
+
+    Ok(Arguments {
+        schema: schema.ok_or_else(|| invalid("missing the schema file".to_owned()))?,
+        language: language.ok_or_else(|| invalid("missing --lang".to_owned()))?,
+        namespace,
+        output,
+    })
+}
+
+fn run() -> Result<(), failure::Error> {
+    let arguments = parse_arguments(std::env::args().skip(1))?;
+    let schema = fs::read_to_string(&arguments.schema)?;
+    let code = generate(&schema, arguments.language, &arguments.namespace)?;
+
+    match arguments.output {
+        Some(output) => fs::write(output, code)?,
+        None => print!("{}", code),
+    }
+
+    Ok(())
+}
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
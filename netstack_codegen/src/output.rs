@@ -0,0 +1,55 @@
+/// Collects generated lines with indentation.
+pub(crate) struct Output {
+    code: String,
+    indentation: usize,
+    unit: &'static str,
+}
+
+impl Output {
+    pub(crate) fn new(unit: &'static str) -> Self {
+        Self {
+            code: String::new(),
+            indentation: 0,
+            unit,
+        }
+    }
+
+    pub(crate) fn line(&mut self, line: impl AsRef<str>) {
+        let line = line.as_ref();
+
+        if !line.is_empty() {
+            for _ in 0..self.indentation {
+                self.code.push_str(self.unit);
+            }
+            self.code.push_str(line);
+        }
+
+        self.code.push('\n');
+    }
+
+    /// Writes a line and indents the following lines.
+    pub(crate) fn open(&mut self, line: impl AsRef<str>) {
+        self.line(line);
+        self.indentation += 1;
+    }
+
+    /// Stops indenting and writes a line.
+    pub(crate) fn close(&mut self, line: impl AsRef<str>) {
+        self.indentation -= 1;
+        self.line(line);
+    }
+
+    /// Stops indenting without writing a line.
+    pub(crate) fn dedent(&mut self) {
+        self.indentation -= 1;
+    }
+
+    /// Appends code that is already indented.
+    pub(crate) fn raw(&mut self, code: &str) {
+        self.code.push_str(code);
+    }
+
+    pub(crate) fn finish(self) -> String {
+        self.code
+    }
+}
@@ -0,0 +1,226 @@
+use serde_json::{Map, Value};
+use netstack::schema::{Definition, DefinitionKind, Encoding, Field, Type, Variant};
+use crate::CodegenError;
+
+/// Reads the definitions exported by `Registry::to_json`.
+pub fn parse_schema(json: &str) -> Result<Vec<Definition>, CodegenError> {
+    let document: Value = serde_json::from_str(json)
+        .map_err(|error| CodegenError::invalid_schema(format!("not valid JSON: {}", error)))?;
+
+    document.get("types")
+        .and_then(Value::as_array)
+        .ok_or_else(|| CodegenError::invalid_schema("expected an object with a `types` array"))?
+        .iter()
+        .map(definition)
+        .collect()
+}
+
+fn definition(value: &Value) -> Result<Definition, CodegenError> {
+    let object = object(value, "type definition")?;
+    let name = string(object, "name")?;
+    let message_id = optional_number(object, "message_id")?.map(|id| id as u32);
+
+    let kind = match string(object, "kind")?.as_str() {
+        "struct" => DefinitionKind::Struct {
+            fields: fields(object)?,
+        },
+        "enum" => DefinitionKind::Enum {
+            discriminant_bits: number(object, "discriminant_bits")? as u32,
+            variants: array(object, "variants")?
+                .iter()
+                .map(|variant| {
+                    let variant = self::object(variant, "variant")?;
+                    Ok(Variant {
+                        name: string(variant, "name")?,
+                        fields: fields(variant)?,
+                    })
+                }).collect::<Result<_, CodegenError>>()?,
+        },
+        kind => return Err(CodegenError::invalid_schema(format!("unknown kind `{}` of {}", kind, name))),
+    };
+
+    Ok(Definition { name, message_id, kind })
+}
+
+fn fields(object: &Map<String, Value>) -> Result<Vec<Field>, CodegenError> {
+    array(object, "fields")?
+        .iter()
+        .map(|field| {
+            let field = self::object(field, "field")?;
+            Ok(Field {
+                name: string(field, "name")?,
+                field_type: field_type(field.get("type").ok_or_else(|| CodegenError::invalid_schema("field without a `type`"))?)?,
+                encoding: field.get("encoding").map(encoding).transpose()?,
+                since: optional_number(field, "since")?.map(|since| since as u32),
+                until: optional_number(field, "until")?.map(|until| until as u32),
+                max_len: optional_number(field, "max_len")?.map(|max_len| max_len as usize),
+            })
+        }).collect()
+}
+
+fn field_type(value: &Value) -> Result<Type, CodegenError> {
+    if let Some(name) = value.as_str() {
+        return match name {
+            "bool" => Ok(Type::Bool),
+            "u8" => Ok(Type::U8),
+            "i8" => Ok(Type::I8),
+            "u16" => Ok(Type::U16),
+            "i16" => Ok(Type::I16),
+            "u32" => Ok(Type::U32),
+            "i32" => Ok(Type::I32),
+            "u64" => Ok(Type::U64),
+            "i64" => Ok(Type::I64),
+            "f32" => Ok(Type::F32),
+            "f64" => Ok(Type::F64),
+            "String" => Ok(Type::String),
+            "Bytes" => Ok(Type::Bytes),
+            _ => Err(CodegenError::invalid_schema(format!("unknown type `{}`", name))),
+        };
+    }
+
+    let object = object(value, "type")?;
+    let boxed = |key: &str| -> Result<Box<Type>, CodegenError> {
+        Ok(Box::new(field_type(&object[key])?))
+    };
+
+    if object.contains_key("option") {
+        Ok(Type::Option(boxed("option")?))
+    } else if object.contains_key("vec") {
+        Ok(Type::Vec(boxed("vec")?))
+    } else if object.contains_key("array") {
+        Ok(Type::Array(boxed("array")?, number(object, "length")? as usize))
+    } else if let Some(Value::Array(pair)) = object.get("map") {
+        match pair.as_slice() {
+            [key, value] => Ok(Type::Map(Box::new(field_type(key)?), Box::new(field_type(value)?))),
+            _ => Err(CodegenError::invalid_schema("a map needs a key and a value type")),
+        }
+    } else if let Some(Value::Array(elements)) = object.get("tuple") {
+        Ok(Type::Tuple(elements.iter().map(field_type).collect::<Result<_, _>>()?))
+    } else if object.contains_key("ref") {
+        Ok(Type::Named(string(object, "ref")?))
+    } else if object.contains_key("custom") {
+        Ok(Type::Custom(string(object, "custom")?))
+    } else {
+        Err(CodegenError::invalid_schema(format!("unknown type {}", value)))
+    }
+}
+
+fn encoding(value: &Value) -> Result<Encoding, CodegenError> {
+    let object = object(value, "encoding")?;
+
+    if let Some(bits) = object.get("bits") {
+        let bits = self::object(bits, "bits encoding")?;
+        Ok(Encoding::Bits { bits: number(bits, "bits")? as u32 })
+    } else if let Some(range) = object.get("range") {
+        let range = self::object(range, "range encoding")?;
+        Ok(Encoding::Range {
+            min: integer(range, "min")?,
+            max: integer(range, "max")?,
+            bits: number(range, "bits")? as u32,
+        })
+    } else if let Some(quantize) = object.get("quantize") {
+        let quantize = self::object(quantize, "quantize encoding")?;
+        Ok(Encoding::Quantize {
+            min: float(quantize, "min")?,
+            max: float(quantize, "max")?,
+            precision: float(quantize, "precision")?,
+            bits: number(quantize, "bits")? as u32,
+        })
+    } else {
+        Err(CodegenError::invalid_schema(format!("unknown encoding {}", value)))
+    }
+}
+
+fn object<'a>(value: &'a Value, what: &str) -> Result<&'a Map<String, Value>, CodegenError> {
+    value.as_object().ok_or_else(|| CodegenError::invalid_schema(format!("expected a {} object, found {}", what, value)))
+}
+
+fn array<'a>(object: &'a Map<String, Value>, key: &str) -> Result<&'a Vec<Value>, CodegenError> {
+    object.get(key)
+        .and_then(Value::as_array)
+        .ok_or_else(|| CodegenError::invalid_schema(format!("expected an array `{}`", key)))
+}
+
+fn string(object: &Map<String, Value>, key: &str) -> Result<String, CodegenError> {
+    object.get(key)
+        .and_then(Value::as_str)
+        .map(str::to_owned)
+        .ok_or_else(|| CodegenError::invalid_schema(format!("expected a string `{}`", key)))
+}
+
+fn number(object: &Map<String, Value>, key: &str) -> Result<u64, CodegenError> {
+    optional_number(object, key)?.ok_or_else(|| CodegenError::invalid_schema(format!("expected a number `{}`", key)))
+}
+
+fn optional_number(object: &Map<String, Value>, key: &str) -> Result<Option<u64>, CodegenError> {
+    match object.get(key) {
+        None => Ok(None),
+        Some(value) => value.as_u64()
+            .map(Some)
+            .ok_or_else(|| CodegenError::invalid_schema(format!("expected a positive integer `{}`, found {}", key, value))),
+    }
+}
+
+fn integer(object: &Map<String, Value>, key: &str) -> Result<i64, CodegenError> {
+    object.get(key)
+        .and_then(Value::as_i64)
+        .ok_or_else(|| CodegenError::invalid_schema(format!("expected an integer `{}`", key)))
+}
+
+fn float(object: &Map<String, Value>, key: &str) -> Result<f32, CodegenError> {
+    object.get(key)
+        .and_then(Value::as_f64)
+        .map(|value| value as f32)
+        .ok_or_else(|| CodegenError::invalid_schema(format!("expected a number `{}`", key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use netstack::schema::Registry;
+
+    #[test]
+    fn it_reads_what_the_registry_exports() {
+        let mut registry = Registry::new();
+        let mut health = Field::new("health", Type::U8);
+        health.encoding = Some(Encoding::Range { min: -8, max: 100, bits: 7 });
+        health.since = Some(2);
+        let mut x = Field::new("x", Type::F32);
+        x.encoding = Some(Encoding::Quantize { min: -512.0, max: 512.0, precision: 0.01, bits: 17 });
+
+        let state = Definition {
+            name: "State".to_owned(),
+            message_id: Some(3),
+            kind: DefinitionKind::Struct {
+                fields: vec![
+                    health,
+                    x,
+                    Field::new("tags", Type::Map(Box::new(Type::String), Box::new(Type::Tuple(vec![Type::Bool, Type::Bytes])))),
+                    Field::new("hotbar", Type::Array(Box::new(Type::Option(Box::new(Type::Named("Item".to_owned())))), 4)),
+                ],
+            },
+        };
+        let item = Definition {
+            name: "Item".to_owned(),
+            message_id: None,
+            kind: DefinitionKind::Enum {
+                discriminant_bits: 1,
+                variants: vec![
+                    Variant { name: "Empty".to_owned(), fields: Vec::new() },
+                    Variant { name: "Stack".to_owned(), fields: vec![Field::new("0", Type::Vec(Box::new(Type::U16)))] },
+                ],
+            },
+        };
+        registry.add_definition(state.clone());
+        registry.add_definition(item.clone());
+
+        assert_eq!(parse_schema(&registry.to_json()).unwrap(), vec![item, state]);
+    }
+
+    #[test]
+    fn it_rejects_invalid_schemas() {
+        assert!(parse_schema("[]").is_err());
+        assert!(parse_schema(r#"{ "types": [{ "name": "A", "kind": "union" }] }"#).is_err());
+        assert!(parse_schema(r#"{ "types": [{ "name": "A", "kind": "struct", "fields": [{ "name": "a", "type": "u128" }] }] }"#).is_err());
+    }
+}
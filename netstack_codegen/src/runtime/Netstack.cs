@@ -0,0 +1,331 @@
+    public sealed class NetstackException : Exception
+    {
+        public NetstackException(string message) : base(message) { }
+    }
+
+    public static class NetstackHelpers
+    {
+        public const uint LatestVersion = uint.MaxValue;
+
+        private static readonly UTF8Encoding Utf8 = new UTF8Encoding(false, true);
+
+        public static int GetLength(string value) => Utf8.GetByteCount(value);
+
+        public static byte[] Encode(string value) => Utf8.GetBytes(value);
+
+        public static string Decode(byte[] bytes)
+        {
+            try
+            {
+                return Utf8.GetString(bytes);
+            }
+            catch (ArgumentException)
+            {
+                throw new NetstackException("Invalid UTF-8");
+            }
+        }
+
+        public static T CheckMaxLength<T>(T value, Func<T, int> length, int max)
+        {
+            var actual = length(value);
+            if (actual > max) throw new NetstackException($"Length {actual} exceeds the maximum of {max}");
+            return value;
+        }
+
+        public static int BitsRequired(long min, long max)
+        {
+            var range = unchecked((ulong)(max - min));
+            var bits = 0;
+            while (range != 0)
+            {
+                bits++;
+                range >>= 1;
+            }
+            return bits;
+        }
+    }
+
+    /// <summary>Writes values like netstack's byte aligned Writer.</summary>
+    public sealed class NetstackWriter
+    {
+        private readonly List<byte> buffer = new List<byte>();
+
+        public NetstackWriter(uint version = NetstackHelpers.LatestVersion)
+        {
+            Version = version;
+        }
+
+        /// <summary>The protocol version of the receiving side.</summary>
+        public uint Version { get; }
+
+        public byte[] ToArray() => buffer.ToArray();
+
+        public void WriteBool(bool value) => buffer.Add(value ? (byte)1 : (byte)0);
+        public void WriteU8(byte value) => buffer.Add(value);
+        public void WriteI8(sbyte value) => buffer.Add(unchecked((byte)value));
+        public void WriteU16(ushort value) => WriteVarUInt(value);
+        public void WriteI16(short value) => WriteVarInt(value);
+        public void WriteU32(uint value) => WriteVarUInt(value);
+        public void WriteI32(int value) => WriteVarInt(value);
+        public void WriteU64(ulong value) => WriteVarUInt(value);
+        public void WriteI64(long value) => WriteVarInt(value);
+
+        public void WriteF32(float value) => WriteLittleEndian(BitConverter.GetBytes(value));
+        public void WriteF64(double value) => WriteLittleEndian(BitConverter.GetBytes(value));
+
+        public void WriteString(string value) => WriteBytes(NetstackHelpers.Encode(value));
+
+        public void WriteBytes(byte[] value)
+        {
+            WriteVarUInt((ulong)value.Length);
+            buffer.AddRange(value);
+        }
+
+        public void WriteBits(ulong value, int bits)
+        {
+            if (bits < 64 && value >> bits != 0) throw new NetstackException($"{value} does not fit into {bits} bits");
+            for (var i = 0; i < (bits + 7) / 8; i++) buffer.Add(unchecked((byte)(value >> (8 * i))));
+        }
+
+        public void WriteRanged(long value, long min, long max)
+        {
+            if (value < min || value > max) throw new NetstackException($"{value} is not within {min}..={max}");
+            WriteBits(unchecked((ulong)(value - min)), NetstackHelpers.BitsRequired(min, max));
+        }
+
+        public void WriteOptional<T>(T value, Action<NetstackWriter, T> write) where T : class
+        {
+            WriteBool(value != null);
+            if (value != null) write(this, value);
+        }
+
+        public void WriteOptionalValue<T>(T? value, Action<NetstackWriter, T> write) where T : struct
+        {
+            WriteBool(value.HasValue);
+            if (value.HasValue) write(this, value.Value);
+        }
+
+        public void WriteList<T>(List<T> values, Action<NetstackWriter, T> write)
+        {
+            WriteVarUInt((ulong)values.Count);
+            foreach (var value in values) write(this, value);
+        }
+
+        public void WriteArray<T>(T[] values, int length, Action<NetstackWriter, T> write)
+        {
+            if (values.Length != length) throw new NetstackException($"Expected {length} elements, found {values.Length}");
+            foreach (var value in values) write(this, value);
+        }
+
+        public void WriteMap<K, V>(Dictionary<K, V> values, Action<NetstackWriter, K> writeKey, Action<NetstackWriter, V> writeValue)
+        {
+            WriteVarUInt((ulong)values.Count);
+            foreach (var entry in values)
+            {
+                writeKey(this, entry.Key);
+                writeValue(this, entry.Value);
+            }
+        }
+
+        private void WriteVarUInt(ulong value)
+        {
+            do
+            {
+                var next = (byte)(value & 0x7f);
+                value >>= 7;
+                if (value != 0) next |= 0x80;
+                buffer.Add(next);
+            } while (value != 0);
+        }
+
+        private void WriteVarInt(long value) => WriteVarUInt(unchecked((ulong)((value << 1) ^ (value >> 63))));
+
+        private void WriteLittleEndian(byte[] bytes)
+        {
+            if (!BitConverter.IsLittleEndian) Array.Reverse(bytes);
+            buffer.AddRange(bytes);
+        }
+    }
+
+    /// <summary>Reads values like netstack's byte aligned Reader.</summary>
+    public sealed class NetstackReader
+    {
+        private const int MaxPreallocation = 1024;
+        private const int MaxZeroSizedLength = 1024;
+
+        private readonly byte[] buffer;
+        private int position;
+
+        public NetstackReader(byte[] buffer, uint version = NetstackHelpers.LatestVersion)
+        {
+            this.buffer = buffer;
+            Version = version;
+        }
+
+        /// <summary>The protocol version of the sending side.</summary>
+        public uint Version { get; }
+
+        public int Remaining => buffer.Length - position;
+
+        public bool ReadBool()
+        {
+            var value = Next();
+            if (value > 1) throw new NetstackException($"Invalid bool {value}");
+            return value == 1;
+        }
+
+        public byte ReadU8() => Next();
+        public sbyte ReadI8() => unchecked((sbyte)Next());
+        public ushort ReadU16() => (ushort)ReadVarUInt(ushort.MaxValue);
+        public short ReadI16() => (short)ReadVarInt(short.MinValue, short.MaxValue);
+        public uint ReadU32() => (uint)ReadVarUInt(uint.MaxValue);
+        public int ReadI32() => (int)ReadVarInt(int.MinValue, int.MaxValue);
+        public ulong ReadU64() => ReadVarUInt(ulong.MaxValue);
+        public long ReadI64() => ReadVarInt(long.MinValue, long.MaxValue);
+
+        public float ReadF32() => BitConverter.ToSingle(ReadLittleEndian(4), 0);
+        public double ReadF64() => BitConverter.ToDouble(ReadLittleEndian(8), 0);
+
+        public string ReadString() => NetstackHelpers.Decode(ReadBytes());
+
+        public byte[] ReadBytes() => Take(ReadLength());
+
+        public ulong ReadBits(int bits)
+        {
+            var bytes = Take((bits + 7) / 8);
+            ulong value = 0;
+            for (var i = 0; i < bytes.Length; i++) value |= (ulong)bytes[i] << (8 * i);
+            if (bits < 64 && value >> bits != 0) throw new NetstackException($"{value} does not fit into {bits} bits");
+            return value;
+        }
+
+        public long ReadRanged(long min, long max)
+        {
+            var value = unchecked(min + (long)ReadBits(NetstackHelpers.BitsRequired(min, max)));
+            if (value < min || value > max) throw new NetstackException($"{value} is not within {min}..={max}");
+            return value;
+        }
+
+        public T ReadOptional<T>(Func<NetstackReader, T> read) where T : class => ReadBool() ? read(this) : null;
+
+        public T? ReadOptionalValue<T>(Func<NetstackReader, T> read) where T : struct => ReadBool() ? read(this) : (T?)null;
+
+        public List<T> ReadList<T>(Func<NetstackReader, T> read)
+        {
+            var length = ReadLength();
+            var values = new List<T>(Math.Min(length, MaxPreallocation));
+            for (var i = 0; i < length; i++) values.Add(read(this));
+            return values;
+        }
+
+        public T[] ReadArray<T>(int length, Func<NetstackReader, T> read)
+        {
+            var values = new T[length];
+            for (var i = 0; i < length; i++) values[i] = read(this);
+            return values;
+        }
+
+        public Dictionary<K, V> ReadMap<K, V>(Func<NetstackReader, K> readKey, Func<NetstackReader, V> readValue)
+        {
+            var length = ReadLength();
+            var values = new Dictionary<K, V>(Math.Min(length, MaxPreallocation));
+            for (var i = 0; i < length; i++)
+            {
+                var key = readKey(this);
+                values[key] = readValue(this);
+            }
+            return values;
+        }
+
+        private byte Next()
+        {
+            if (position >= buffer.Length) throw new NetstackException("Unexpected end of the buffer");
+            return buffer[position++];
+        }
+
+        private byte[] Take(int length)
+        {
+            if (length > Remaining) throw new NetstackException("Unexpected end of the buffer");
+            var bytes = new byte[length];
+            Array.Copy(buffer, position, bytes, 0, length);
+            position += length;
+            return bytes;
+        }
+
+        private byte[] ReadLittleEndian(int length)
+        {
+            var bytes = Take(length);
+            if (!BitConverter.IsLittleEndian) Array.Reverse(bytes);
+            return bytes;
+        }
+
+        /// <summary>Elements take up at least one byte unless they have no fields, those are limited like netstack's MAX_ZERO_SIZED_LENGTH.</summary>
+        private int ReadLength()
+        {
+            var length = ReadVarUInt(ulong.MaxValue);
+            if (length > (ulong)Remaining && length > MaxZeroSizedLength)
+            {
+                throw new NetstackException($"Length {length} exceeds the remaining {Remaining} bytes");
+            }
+            return (int)length;
+        }
+
+        private ulong ReadVarUInt(ulong max)
+        {
+            ulong value = 0;
+            for (var i = 0; i < 10; i++)
+            {
+                var next = Next();
+                // the last byte only has room for the single remaining bit of a u64
+                if (i == 9 && next > 1) break;
+                value |= (ulong)(next & 0x7f) << (7 * i);
+                if ((next & 0x80) == 0)
+                {
+                    if (value > max) break;
+                    return value;
+                }
+            }
+            throw new NetstackException("Variable length integer overflow");
+        }
+
+        private long ReadVarInt(long min, long max)
+        {
+            var encoded = ReadVarUInt(ulong.MaxValue);
+            var value = unchecked((long)(encoded >> 1) ^ -(long)(encoded & 1));
+            if (value < min || value > max) throw new NetstackException("Variable length integer overflow");
+            return value;
+        }
+    }
+
+    public sealed class NetstackQuantization
+    {
+        private readonly double min;
+        private readonly double max;
+        private readonly double stepSize;
+        private readonly ulong steps;
+        private readonly int bits;
+
+        public NetstackQuantization(float min, float max, float precision)
+        {
+            this.min = min;
+            this.max = max;
+            steps = Math.Max(1UL, (ulong)Math.Ceiling((this.max - this.min) / precision));
+            stepSize = (this.max - this.min) / steps;
+            bits = NetstackHelpers.BitsRequired(0, (long)steps);
+        }
+
+        public void Write(NetstackWriter writer, float value)
+        {
+            if (float.IsNaN(value) || float.IsInfinity(value)) throw new NetstackException($"{value} cannot be quantized");
+            var clamped = Math.Min(Math.Max(value, min), max);
+            var step = (ulong)Math.Round((clamped - min) / stepSize, MidpointRounding.AwayFromZero);
+            writer.WriteBits(Math.Min(step, steps), bits);
+        }
+
+        public float Read(NetstackReader reader)
+        {
+            var step = reader.ReadBits(bits);
+            if (step > steps) throw new NetstackException($"{step} is not within 0..={steps}");
+            return (float)Math.Min(min + step * stepSize, max);
+        }
+    }
@@ -0,0 +1,303 @@
+//! TypeScript types with a `readX` and `writeX` function for every definition.
+//!
+//! Structs become interfaces, enums become unions discriminated by `kind`. 64 bit integers are
+//! `bigint`s, every other number is a `number`. Fields that don't exist in the protocol version of
+//! the reader are `undefined`.
+
+use netstack::schema::{Definition, DefinitionKind, Encoding, Field, Type, Variant};
+use crate::output::Output;
+use crate::{camel_case, type_identifier, CodegenError};
+
+const RUNTIME: &str = include_str!("runtime/netstack.ts");
+
+pub fn generate(definitions: &[Definition]) -> Result<String, CodegenError> {
+    let mut output = Output::new("  ");
+    output.line("// Generated by netstack-codegen, do not edit.");
+    output.line("");
+    output.raw(RUNTIME);
+
+    for definition in definitions {
+        output.line("");
+        match &definition.kind {
+            DefinitionKind::Struct { fields } => structure(&mut output, definition, fields),
+            DefinitionKind::Enum { discriminant_bits, variants } => enumeration(&mut output, definition, *discriminant_bits, variants),
+        }
+    }
+
+    Ok(output.finish())
+}
+
+fn structure(output: &mut Output, definition: &Definition, fields: &[Field]) {
+    let name = type_identifier(&definition.name);
+
+    output.line(format!("/** {} */", definition.name));
+    output.open(format!("export interface {} {{", name));
+    field_declarations(output, fields);
+    output.close("}");
+    message_id(output, definition);
+    quantizations(output, &name, fields);
+    output.line("");
+
+    output.open(format!("export function read{}(reader: NetstackReader): {} {{", name, name));
+    output.open("return {");
+    read_fields(output, &name, fields);
+    output.close("};");
+    output.close("}");
+    output.line("");
+
+    output.open(format!("export function write{}(writer: NetstackWriter, value: {}): void {{", name, name));
+    write_fields(output, &name, fields);
+    output.close("}");
+}
+
+fn enumeration(output: &mut Output, definition: &Definition, discriminant_bits: u32, variants: &[Variant]) {
+    let name = type_identifier(&definition.name);
+
+    output.line(format!("/** {} */", definition.name));
+    if variants.is_empty() {
+        output.line(format!("export type {} = never;", name));
+    } else {
+        output.open(format!("export type {} =", name));
+        for (index, variant) in variants.iter().enumerate() {
+            output.open("| {");
+            output.line(format!("kind: \"{}\";", variant.name));
+            field_declarations(output, &variant.fields);
+            output.close(if index + 1 == variants.len() { "};" } else { "}" });
+        }
+        output.dedent();
+    }
+    message_id(output, definition);
+    for variant in variants {
+        quantizations(output, &format!("{}{}", name, variant.name), &variant.fields);
+    }
+    output.line("");
+
+    output.open(format!("export function read{}(reader: NetstackReader): {} {{", name, name));
+    output.open(format!("switch (Number(reader.readBits({}))) {{", discriminant_bits));
+    for (index, variant) in variants.iter().enumerate() {
+        output.open(format!("case {}:", index));
+        output.open("return {");
+        output.line(format!("kind: \"{}\",", variant.name));
+        read_fields(output, &format!("{}{}", name, variant.name), &variant.fields);
+        output.close("};");
+        output.dedent();
+    }
+    output.open("default:");
+    output.line(format!("throw new NetstackError(\"Unknown variant of {}\");", definition.name));
+    output.dedent();
+    output.close("}");
+    output.close("}");
+    output.line("");
+
+    output.open(format!("export function write{}(writer: NetstackWriter, value: {}): void {{", name, name));
+    if !variants.is_empty() {
+        output.open("switch (value.kind) {");
+        for (index, variant) in variants.iter().enumerate() {
+            output.open(format!("case \"{}\":", variant.name));
+            output.line(format!("writer.writeBits({}n, {});", index, discriminant_bits));
+            write_fields(output, &format!("{}{}", name, variant.name), &variant.fields);
+            output.line("break;");
+            output.dedent();
+        }
+        output.close("}");
+    }
+    output.close("}");
+}
+
+fn message_id(output: &mut Output, definition: &Definition) {
+    if let Some(id) = definition.message_id {
+        output.line("");
+        output.line(format!("export const {}MessageId = {};", type_identifier(&definition.name), id));
+    }
+}
+
+fn quantizations(output: &mut Output, prefix: &str, fields: &[Field]) {
+    let mut first = true;
+
+    for field in fields {
+        if let Some(Encoding::Quantize { min, max, precision, .. }) = &field.encoding {
+            if first {
+                output.line("");
+                first = false;
+            }
+            output.line(format!(
+                "const {} = new NetstackQuantization({:?}, {:?}, {:?});",
+                quantization(prefix, field), min, max, precision,
+            ));
+        }
+    }
+}
+
+fn quantization(prefix: &str, field: &Field) -> String {
+    format!("{}{}Quantization", camel_case(prefix), capitalize(&camel_case(&field.name)))
+}
+
+fn capitalize(name: &str) -> String {
+    let mut characters = name.chars();
+
+    match characters.next() {
+        Some(first) => first.to_ascii_uppercase().to_string() + characters.as_str(),
+        None => String::new(),
+    }
+}
+
+fn field_declarations(output: &mut Output, fields: &[Field]) {
+    for field in fields {
+        let optional = if field.since.is_some() || field.until.is_some() { "?" } else { "" };
+        output.line(format!("{}{}: {};", camel_case(&field.name), optional, type_name(&field.field_type)));
+    }
+}
+
+fn read_fields(output: &mut Output, prefix: &str, fields: &[Field]) {
+    for field in fields {
+        let value = match &field.encoding {
+            Some(Encoding::Bits { bits }) => number(&field.field_type, format!("reader.readBits({})", bits)),
+            Some(Encoding::Range { min, max, .. }) => number(&field.field_type, format!("reader.readRanged({}n, {}n)", min, max)),
+            Some(Encoding::Quantize { .. }) => format!("{}.read(reader)", quantization(prefix, field)),
+            None => read(&field.field_type, "reader", 0),
+        };
+
+        let value = match field.max_len {
+            Some(max_len) => format!("checkMaxLength({}, {})", value, max_len),
+            None => value,
+        };
+
+        let value = match condition(field, "reader") {
+            Some(condition) => format!("{} ? {} : undefined", condition, value),
+            None => value,
+        };
+
+        output.line(format!("{}: {},", camel_case(&field.name), value));
+    }
+}
+
+fn write_fields(output: &mut Output, prefix: &str, fields: &[Field]) {
+    for field in fields {
+        let name = camel_case(&field.name);
+        let value = match condition(field, "writer") {
+            Some(_) => format!("required(value.{}, \"{}\")", name, name),
+            None => format!("value.{}", name),
+        };
+
+        let statement = match &field.encoding {
+            Some(Encoding::Bits { bits }) => format!("writer.writeBits(BigInt({}), {});", value, bits),
+            Some(Encoding::Range { min, max, .. }) => format!("writer.writeRanged(BigInt({}), {}n, {}n);", value, min, max),
+            Some(Encoding::Quantize { .. }) => format!("{}.write(writer, {});", quantization(prefix, field), value),
+            None => write(&field.field_type, "writer", &value, 0),
+        };
+
+        let statement = match field.max_len {
+            Some(max_len) => format!("checkMaxLength({}, {}); {}", value, max_len, statement),
+            None => statement,
+        };
+
+        match condition(field, "writer") {
+            Some(condition) => output.line(format!("if ({}) {{ {} }}", condition, statement)),
+            None => output.line(statement),
+        }
+    }
+}
+
+/// Fields only exist in some protocol versions if they have `since` or `until`.
+fn condition(field: &Field, target: &str) -> Option<String> {
+    match (field.since, field.until) {
+        (Some(since), Some(until)) => Some(format!("{}.version >= {} && {}.version < {}", target, since, target, until)),
+        (Some(since), None) => Some(format!("{}.version >= {}", target, since)),
+        (None, Some(until)) => Some(format!("{}.version < {}", target, until)),
+        (None, None) => None,
+    }
+}
+
+fn type_name(field_type: &Type) -> String {
+    match field_type {
+        Type::Bool => "boolean".to_owned(),
+        Type::U8 | Type::I8 | Type::U16 | Type::I16 | Type::U32 | Type::I32 | Type::F32 | Type::F64 => "number".to_owned(),
+        Type::U64 | Type::I64 => "bigint".to_owned(),
+        Type::String => "string".to_owned(),
+        Type::Bytes => "Uint8Array".to_owned(),
+        Type::Option(inner) => format!("{} | null", type_name(inner)),
+        Type::Vec(element) | Type::Array(element, _) => format!("Array<{}>", type_name(element)),
+        Type::Map(key, value) => format!("Map<{}, {}>", type_name(key), type_name(value)),
+        Type::Tuple(elements) => format!("[{}]", elements.iter().map(type_name).collect::<Vec<_>>().join(", ")),
+        Type::Named(name) => type_identifier(name),
+        Type::Custom(module) => unreachable!("custom field {} was rejected before", module),
+    }
+}
+
+/// Encoded integers are read as `bigint`s, only 64 bit fields keep them.
+fn number(field_type: &Type, value: String) -> String {
+    match field_type {
+        Type::U64 | Type::I64 => value,
+        _ => format!("Number({})", value),
+    }
+}
+
+fn read(field_type: &Type, reader: &str, depth: usize) -> String {
+    let next_reader = format!("r{}", depth);
+    let lambda = |inner: &Type| format!("({}) => {}", next_reader, read(inner, &next_reader, depth + 1));
+
+    match field_type {
+        Type::Bool => format!("{}.readBool()", reader),
+        Type::U8 => format!("{}.readU8()", reader),
+        Type::I8 => format!("{}.readI8()", reader),
+        Type::U16 => format!("{}.readU16()", reader),
+        Type::I16 => format!("{}.readI16()", reader),
+        Type::U32 => format!("{}.readU32()", reader),
+        Type::I32 => format!("{}.readI32()", reader),
+        Type::U64 => format!("{}.readU64()", reader),
+        Type::I64 => format!("{}.readI64()", reader),
+        Type::F32 => format!("{}.readF32()", reader),
+        Type::F64 => format!("{}.readF64()", reader),
+        Type::String => format!("{}.readString()", reader),
+        Type::Bytes => format!("{}.readBytes()", reader),
+        Type::Option(inner) => format!("{}.readOptional({})", reader, lambda(inner)),
+        Type::Vec(element) => format!("{}.readList({})", reader, lambda(element)),
+        Type::Array(element, length) => format!("{}.readArray({}, {})", reader, length, lambda(element)),
+        Type::Map(key, value) => format!("{}.readMap({}, {})", reader, lambda(key), lambda(value)),
+        Type::Tuple(elements) => format!(
+            "[{}] as {}",
+            elements.iter().map(|element| read(element, reader, depth)).collect::<Vec<_>>().join(", "),
+            type_name(field_type),
+        ),
+        Type::Named(name) => format!("read{}({})", type_identifier(name), reader),
+        Type::Custom(module) => unreachable!("custom field {} was rejected before", module),
+    }
+}
+
+fn write(field_type: &Type, writer: &str, value: &str, depth: usize) -> String {
+    let next_writer = format!("w{}", depth);
+    let next_value = format!("v{}", depth);
+    let lambda = |inner: &Type| format!(
+        "({}, {}) => {{ {} }}",
+        next_writer,
+        next_value,
+        write(inner, &next_writer, &next_value, depth + 1),
+    );
+
+    match field_type {
+        Type::Bool => format!("{}.writeBool({});", writer, value),
+        Type::U8 => format!("{}.writeU8({});", writer, value),
+        Type::I8 => format!("{}.writeI8({});", writer, value),
+        Type::U16 => format!("{}.writeU16({});", writer, value),
+        Type::I16 => format!("{}.writeI16({});", writer, value),
+        Type::U32 => format!("{}.writeU32({});", writer, value),
+        Type::I32 => format!("{}.writeI32({});", writer, value),
+        Type::U64 => format!("{}.writeU64({});", writer, value),
+        Type::I64 => format!("{}.writeI64({});", writer, value),
+        Type::F32 => format!("{}.writeF32({});", writer, value),
+        Type::F64 => format!("{}.writeF64({});", writer, value),
+        Type::String => format!("{}.writeString({});", writer, value),
+        Type::Bytes => format!("{}.writeBytes({});", writer, value),
+        Type::Option(inner) => format!("{}.writeOptional({}, {});", writer, value, lambda(inner)),
+        Type::Vec(element) => format!("{}.writeList({}, {});", writer, value, lambda(element)),
+        Type::Array(element, length) => format!("{}.writeArray({}, {}, {});", writer, value, length, lambda(element)),
+        Type::Map(key, map_value) => format!("{}.writeMap({}, {}, {});", writer, value, lambda(key), lambda(map_value)),
+        Type::Tuple(elements) => elements.iter()
+            .enumerate()
+            .map(|(index, element)| write(element, writer, &format!("{}[{}]", value, index), depth))
+            .collect::<Vec<_>>()
+            .join(" "),
+        Type::Named(name) => format!("write{}({}, {});", type_identifier(name), writer, value),
+        Type::Custom(module) => unreachable!("custom field {} was rejected before", module),
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+use netstack::serialization::{Serialize, Writer, LATEST_VERSION};
+use netstack::schema::Registry;
+use netstack_codegen::{generate, parse_schema, Language};
+use netstack_derive::{Message, Schema, Serialize};
+use serde_json::json;
+
+// The files in tests/golden are checked in, so changes to the generated code and the wire format
+// show up in review. Run the tests with NETSTACK_UPDATE_GOLDEN=1 to regenerate them.
+fn check_golden(name: &str, actual: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(name);
+
+    if env::var_os("NETSTACK_UPDATE_GOLDEN").is_some() {
+        fs::write(&path, actual).expect("It writes the golden file");
+        return;
+    }
+
+    let expected = fs::read_to_string(&path).expect("It reads the golden file");
+    assert!(expected == actual, "{} is out of date, run the tests with NETSTACK_UPDATE_GOLDEN=1 to regenerate it", name);
+}
+
+#[derive(Serialize, Schema)]
+struct Transform {
+    #[netstack(quantize(min = -512.0, max = 512.0, precision = 0.01))]
+    x: f32,
+    #[netstack(quantize(min = -512.0, max = 512.0, precision = 0.01))]
+    y: f32,
+    #[netstack(range = -8..=7)]
+    layer: i8,
+    rotation: f32,
+}
+
+#[derive(Serialize, Schema)]
+enum Command {
+    Stop,
+    Move(Transform),
+    Say {
+        #[netstack(max_len = 32)]
+        text: String,
+        volume: Option<u8>,
+    },
+    Equip(#[netstack(bits = 5)] u8),
+}
+
+#[derive(Serialize, Message, Schema)]
+#[netstack(id = 1)]
+struct Join {
+    name: String,
+    #[netstack(since = 2)]
+    locale: String,
+    #[netstack(until = 3)]
+    legacy_flags: u16,
+    token: u64,
+    offset: i64,
+}
+
+#[derive(Serialize, Message, Schema)]
+struct Snapshot<T> {
+    tick: u32,
+    entities: Vec<(u16, T)>,
+    hotbar: [Option<u8>; 3],
+    tags: BTreeMap<String, bool>,
+    commands: Vec<Command>,
+}
+
+#[derive(Serialize, Schema)]
+struct Chunk<'a> {
+    id: i32,
+    data: &'a [u8],
+    ratio: f64,
+}
+
+fn schema() -> String {
+    let mut registry = Registry::new();
+    registry.register_message::<Join>();
+    registry.register_message::<Snapshot<Transform>>();
+    registry.register::<Chunk>();
+    registry.to_json()
+}
+
+fn bytes<T: Serialize>(value: &T, version: u32) -> String {
+    let mut buffer = [0; 1024];
+    let mut writer = Writer::new(&mut buffer).with_version(version);
+    value.serialize(&mut writer).expect("It serializes the value");
+    let length = writer.get_position();
+
+    buffer[0..length].iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn transform(x: f32, y: f32, layer: i8, rotation: f32) -> Transform {
+    Transform { x, y, layer, rotation }
+}
+
+fn join() -> Join {
+    Join {
+        name: "Ferris".to_owned(),
+        locale: "de".to_owned(),
+        legacy_flags: 300,
+        token: u64::MAX,
+        offset: -2,
+    }
+}
+
+#[test]
+fn it_exports_the_schema() {
+    let schema = schema();
+
+    check_golden("schema.json", &schema);
+    assert_eq!(parse_schema(&schema).unwrap().len(), 5);
+}
+
+#[test]
+fn it_generates_csharp() {
+    check_golden("Messages.cs", &generate(&schema(), Language::CSharp, "Netstack.Messages").unwrap());
+}
+
+#[test]
+fn it_generates_typescript() {
+    check_golden("messages.ts", &generate(&schema(), Language::TypeScript, "").unwrap());
+}
+
+// Generated readers and writers are expected to produce and accept exactly these bytes.
+#[test]
+fn it_writes_the_byte_vectors() {
+    let mut tags = BTreeMap::new();
+    tags.insert("admin".to_owned(), true);
+    tags.insert("muted".to_owned(), false);
+
+    let snapshot = Snapshot {
+        tick: 70_000,
+        entities: vec![
+            (1, transform(-512.0, 512.0, -8, 0.5)),
+            (300, transform(1.234, -0.005, 7, -1.0)),
+        ],
+        hotbar: [Some(3), None, Some(255)],
+        tags,
+        commands: vec![
+            Command::Stop,
+            Command::Move(transform(100.0, -100.0, 0, 3.25)),
+            Command::Say { text: "héllo".to_owned(), volume: Some(10) },
+            Command::Say { text: String::new(), volume: None },
+            Command::Equip(31),
+        ],
+    };
+    let chunk = Chunk {
+        id: -70_000,
+        data: &[0, 1, 2, 255],
+        ratio: 0.1,
+    };
+
+    let vectors = json!({
+        "vectors": [
+            { "type": "Join", "version": 1, "description": "without the locale", "hex": bytes(&join(), 1) },
+            { "type": "Join", "version": 2, "description": "with the locale and legacy flags", "hex": bytes(&join(), 2) },
+            { "type": "Join", "version": 3, "description": "without the legacy flags", "hex": bytes(&join(), 3) },
+            { "type": "Join", "version": LATEST_VERSION, "description": "the latest version", "hex": bytes(&join(), LATEST_VERSION) },
+            { "type": "Snapshot<Transform>", "version": LATEST_VERSION, "description": "every kind of field", "hex": bytes(&snapshot, LATEST_VERSION) },
+            { "type": "Chunk", "version": LATEST_VERSION, "description": "bytes, negative integers and doubles", "hex": bytes(&chunk, LATEST_VERSION) },
+        ],
+    });
+
+    check_golden("vectors.json", &(serde_json::to_string_pretty(&vectors).unwrap() + "\n"));
+}
+
+// Reads and writes the byte vectors with the generated TypeScript. Node runs TypeScript from version 22.7 on,
+// older versions skip the test.
+#[test]
+fn it_reads_and_writes_the_byte_vectors_in_typescript() {
+    const NODE_FLAGS: [&str; 2] = ["--experimental-transform-types", "--no-warnings"];
+
+    let runs_typescript = process::Command::new("node").args(NODE_FLAGS).args(["-e", ""]).output()
+        .map(|output| output.status.success())
+        .unwrap_or(false);
+    if !runs_typescript {
+        eprintln!("skipped, node 22.7 or later is needed to run the generated TypeScript");
+        return;
+    }
+
+    let tests = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let directory = env::temp_dir().join(format!("netstack_codegen_vectors_{}", process::id()));
+    fs::create_dir_all(&directory).expect("It creates the directory");
+    fs::copy(tests.join("golden").join("messages.ts"), directory.join("messages.mts")).expect("It copies the messages");
+    fs::copy(tests.join("typescript").join("vectors.mjs"), directory.join("vectors.mjs")).expect("It copies the script");
+
+    let output = process::Command::new("node").args(NODE_FLAGS)
+        .arg(directory.join("vectors.mjs"))
+        .arg(tests.join("golden").join("vectors.json"))
+        .output()
+        .expect("It runs node");
+    fs::remove_dir_all(&directory).ok();
+
+    assert!(output.status.success(), "The TypeScript doesn't match the byte vectors:\n{}", String::from_utf8_lossy(&output.stderr));
+}
@@ -0,0 +1,469 @@
+// Generated by netstack-codegen, do not edit.
+
+export const LATEST_VERSION = 0xffffffff;
+
+export class NetstackError extends Error {}
+
+const MAX_ZERO_SIZED_LENGTH = 1024;
+
+const utf8Encoder = new TextEncoder();
+const utf8Decoder = new TextDecoder("utf-8", { fatal: true });
+
+function lengthOf(value: string | Uint8Array | Array<unknown> | Map<unknown, unknown>): number {
+  if (typeof value === "string") return utf8Encoder.encode(value).length;
+  if (value instanceof Map) return value.size;
+  return value.length;
+}
+
+function checkMaxLength<T extends string | Uint8Array | Array<unknown> | Map<unknown, unknown>>(value: T, max: number): T {
+  const length = lengthOf(value);
+  if (length > max) throw new NetstackError(`Length ${length} exceeds the maximum of ${max}`);
+  return value;
+}
+
+function required<T>(value: T | undefined, name: string): T {
+  if (value === undefined) throw new NetstackError(`Field ${name} is required in this version`);
+  return value;
+}
+
+export function bitsRequired(min: bigint, max: bigint): number {
+  let range = BigInt.asUintN(64, max - min);
+  let bits = 0;
+  while (range !== 0n) {
+    bits++;
+    range >>= 1n;
+  }
+  return bits;
+}
+
+/** Writes values like netstack's byte aligned Writer. */
+export class NetstackWriter {
+  private buffer = new Uint8Array(64);
+  private length = 0;
+
+  /** `version` is the protocol version of the receiving side. */
+  constructor(readonly version: number = LATEST_VERSION) {}
+
+  toBytes(): Uint8Array {
+    return this.buffer.slice(0, this.length);
+  }
+
+  writeBool(value: boolean): void { this.writeU8(value ? 1 : 0); }
+  writeU8(value: number): void { this.reserve(1)[0] = value; }
+  writeI8(value: number): void { this.writeU8(value & 0xff); }
+  writeU16(value: number): void { this.writeVarUInt(BigInt(value)); }
+  writeI16(value: number): void { this.writeVarInt(BigInt(value)); }
+  writeU32(value: number): void { this.writeVarUInt(BigInt(value)); }
+  writeI32(value: number): void { this.writeVarInt(BigInt(value)); }
+  writeU64(value: bigint): void { this.writeVarUInt(BigInt.asUintN(64, value)); }
+  writeI64(value: bigint): void { this.writeVarInt(BigInt.asIntN(64, value)); }
+
+  writeF32(value: number): void {
+    const bytes = this.reserve(4);
+    new DataView(bytes.buffer, bytes.byteOffset, 4).setFloat32(0, value, true);
+  }
+
+  writeF64(value: number): void {
+    const bytes = this.reserve(8);
+    new DataView(bytes.buffer, bytes.byteOffset, 8).setFloat64(0, value, true);
+  }
+
+  writeString(value: string): void { this.writeBytes(utf8Encoder.encode(value)); }
+
+  writeBytes(value: Uint8Array): void {
+    this.writeVarUInt(BigInt(value.length));
+    this.reserve(value.length).set(value);
+  }
+
+  writeBits(value: bigint, bits: number): void {
+    if (value < 0n || value >> BigInt(bits) !== 0n) throw new NetstackError(`${value} does not fit into ${bits} bits`);
+    const bytes = this.reserve(Math.ceil(bits / 8));
+    for (let i = 0; i < bytes.length; i++) bytes[i] = Number((value >> BigInt(8 * i)) & 0xffn);
+  }
+
+  writeRanged(value: bigint, min: bigint, max: bigint): void {
+    if (value < min || value > max) throw new NetstackError(`${value} is not within ${min}..=${max}`);
+    this.writeBits(value - min, bitsRequired(min, max));
+  }
+
+  writeOptional<T>(value: T | null, write: (writer: NetstackWriter, value: T) => void): void {
+    this.writeBool(value !== null);
+    if (value !== null) write(this, value);
+  }
+
+  writeList<T>(values: Array<T>, write: (writer: NetstackWriter, value: T) => void): void {
+    this.writeVarUInt(BigInt(values.length));
+    for (const value of values) write(this, value);
+  }
+
+  writeArray<T>(values: Array<T>, length: number, write: (writer: NetstackWriter, value: T) => void): void {
+    if (values.length !== length) throw new NetstackError(`Expected ${length} elements, found ${values.length}`);
+    for (const value of values) write(this, value);
+  }
+
+  writeMap<K, V>(
+    values: Map<K, V>,
+    writeKey: (writer: NetstackWriter, key: K) => void,
+    writeValue: (writer: NetstackWriter, value: V) => void,
+  ): void {
+    this.writeVarUInt(BigInt(values.size));
+    for (const [key, value] of values) {
+      writeKey(this, key);
+      writeValue(this, value);
+    }
+  }
+
+  private writeVarUInt(value: bigint): void {
+    do {
+      let next = Number(value & 0x7fn);
+      value >>= 7n;
+      if (value !== 0n) next |= 0x80;
+      this.writeU8(next);
+    } while (value !== 0n);
+  }
+
+  private writeVarInt(value: bigint): void {
+    this.writeVarUInt(BigInt.asUintN(64, (value << 1n) ^ (value >> 63n)));
+  }
+
+  private reserve(length: number): Uint8Array {
+    if (this.length + length > this.buffer.length) {
+      const buffer = new Uint8Array(Math.max(this.buffer.length * 2, this.length + length));
+      buffer.set(this.buffer.subarray(0, this.length));
+      this.buffer = buffer;
+    }
+    const bytes = this.buffer.subarray(this.length, this.length + length);
+    this.length += length;
+    return bytes;
+  }
+}
+
+/** Reads values like netstack's byte aligned Reader. */
+export class NetstackReader {
+  private position = 0;
+
+  /** `version` is the protocol version of the sending side. */
+  constructor(private readonly buffer: Uint8Array, readonly version: number = LATEST_VERSION) {}
+
+  get remaining(): number {
+    return this.buffer.length - this.position;
+  }
+
+  readBool(): boolean {
+    const value = this.readU8();
+    if (value > 1) throw new NetstackError(`Invalid bool ${value}`);
+    return value === 1;
+  }
+
+  readU8(): number { return this.take(1)[0]; }
+  readI8(): number { return (this.readU8() << 24) >> 24; }
+  readU16(): number { return Number(this.readVarUInt(0xffffn)); }
+  readI16(): number { return Number(this.readVarInt(-0x8000n, 0x7fffn)); }
+  readU32(): number { return Number(this.readVarUInt(0xffffffffn)); }
+  readI32(): number { return Number(this.readVarInt(-0x80000000n, 0x7fffffffn)); }
+  readU64(): bigint { return this.readVarUInt(0xffffffffffffffffn); }
+  readI64(): bigint { return this.readVarInt(-0x8000000000000000n, 0x7fffffffffffffffn); }
+
+  readF32(): number {
+    const bytes = this.take(4);
+    return new DataView(bytes.buffer, bytes.byteOffset, 4).getFloat32(0, true);
+  }
+
+  readF64(): number {
+    const bytes = this.take(8);
+    return new DataView(bytes.buffer, bytes.byteOffset, 8).getFloat64(0, true);
+  }
+
+  readString(): string {
+    try {
+      return utf8Decoder.decode(this.readBytes());
+    } catch (error) {
+      throw new NetstackError("Invalid UTF-8");
+    }
+  }
+
+  readBytes(): Uint8Array { return this.take(this.readLength()).slice(); }
+
+  readBits(bits: number): bigint {
+    const bytes = this.take(Math.ceil(bits / 8));
+    let value = 0n;
+    for (let i = 0; i < bytes.length; i++) value |= BigInt(bytes[i]) << BigInt(8 * i);
+    if (value >> BigInt(bits) !== 0n) throw new NetstackError(`${value} does not fit into ${bits} bits`);
+    return value;
+  }
+
+  readRanged(min: bigint, max: bigint): bigint {
+    const value = BigInt.asIntN(64, min + this.readBits(bitsRequired(min, max)));
+    if (value < min || value > max) throw new NetstackError(`${value} is not within ${min}..=${max}`);
+    return value;
+  }
+
+  readOptional<T>(read: (reader: NetstackReader) => T): T | null {
+    return this.readBool() ? read(this) : null;
+  }
+
+  readList<T>(read: (reader: NetstackReader) => T): Array<T> {
+    const length = this.readLength();
+    const values = new Array<T>();
+    for (let i = 0; i < length; i++) values.push(read(this));
+    return values;
+  }
+
+  readArray<T>(length: number, read: (reader: NetstackReader) => T): Array<T> {
+    const values = new Array<T>();
+    for (let i = 0; i < length; i++) values.push(read(this));
+    return values;
+  }
+
+  readMap<K, V>(readKey: (reader: NetstackReader) => K, readValue: (reader: NetstackReader) => V): Map<K, V> {
+    const length = this.readLength();
+    const values = new Map<K, V>();
+    for (let i = 0; i < length; i++) {
+      const key = readKey(this);
+      values.set(key, readValue(this));
+    }
+    return values;
+  }
+
+  private take(length: number): Uint8Array {
+    if (length > this.remaining) throw new NetstackError("Unexpected end of the buffer");
+    const bytes = this.buffer.subarray(this.position, this.position + length);
+    this.position += length;
+    return bytes;
+  }
+
+  /** Elements take up at least one byte unless they have no fields, those are limited like netstack's MAX_ZERO_SIZED_LENGTH. */
+  private readLength(): number {
+    const length = this.readVarUInt(0xffffffffffffffffn);
+    if (length > BigInt(this.remaining) && length > BigInt(MAX_ZERO_SIZED_LENGTH)) {
+      throw new NetstackError(`Length ${length} exceeds the remaining ${this.remaining} bytes`);
+    }
+    return Number(length);
+  }
+
+  private readVarUInt(max: bigint): bigint {
+    let value = 0n;
+    for (let i = 0; i < 10; i++) {
+      const next = this.readU8();
+      // the last byte only has room for the single remaining bit of a u64
+      if (i === 9 && next > 1) break;
+      value |= BigInt(next & 0x7f) << BigInt(7 * i);
+      if ((next & 0x80) === 0) {
+        if (value > max) break;
+        return value;
+      }
+    }
+    throw new NetstackError("Variable length integer overflow");
+  }
+
+  private readVarInt(min: bigint, max: bigint): bigint {
+    const encoded = this.readVarUInt(0xffffffffffffffffn);
+    const value = (encoded >> 1n) ^ -(encoded & 1n);
+    if (value < min || value > max) throw new NetstackError("Variable length integer overflow");
+    return value;
+  }
+}
+
+/** Quantizes floats exactly like netstack's FloatQuantization. */
+export class NetstackQuantization {
+  private readonly min: number;
+  private readonly max: number;
+  private readonly steps: number;
+  private readonly stepSize: number;
+  private readonly bits: number;
+
+  constructor(min: number, max: number, precision: number) {
+    this.min = Math.fround(min);
+    this.max = Math.fround(max);
+    this.steps = Math.max(1, Math.ceil((this.max - this.min) / Math.fround(precision)));
+    this.stepSize = (this.max - this.min) / this.steps;
+    this.bits = bitsRequired(0n, BigInt(this.steps));
+  }
+
+  write(writer: NetstackWriter, value: number): void {
+    if (!Number.isFinite(value)) throw new NetstackError(`${value} cannot be quantized`);
+    const clamped = Math.min(Math.max(Math.fround(value), this.min), this.max);
+    // the value is never below min, so rounding half up is the same as rounding away from zero
+    const step = Math.min(Math.round((clamped - this.min) / this.stepSize), this.steps);
+    writer.writeBits(BigInt(step), this.bits);
+  }
+
+  read(reader: NetstackReader): number {
+    const step = Number(reader.readBits(this.bits));
+    if (step > this.steps) throw new NetstackError(`${step} is not within 0..=${this.steps}`);
+    return Math.fround(Math.min(this.min + step * this.stepSize, this.max));
+  }
+}
+
+/** Chunk */
+export interface Chunk {
+  id: number;
+  data: Uint8Array;
+  ratio: number;
+}
+
+export function readChunk(reader: NetstackReader): Chunk {
+  return {
+    id: reader.readI32(),
+    data: reader.readBytes(),
+    ratio: reader.readF64(),
+  };
+}
+
+export function writeChunk(writer: NetstackWriter, value: Chunk): void {
+  writer.writeI32(value.id);
+  writer.writeBytes(value.data);
+  writer.writeF64(value.ratio);
+}
+
+/** Command */
+export type Command =
+  | {
+    kind: "Stop";
+  }
+  | {
+    kind: "Move";
+    item0: Transform;
+  }
+  | {
+    kind: "Say";
+    text: string;
+    volume: number | null;
+  }
+  | {
+    kind: "Equip";
+    item0: number;
+  };
+
+export function readCommand(reader: NetstackReader): Command {
+  switch (Number(reader.readBits(2))) {
+    case 0:
+      return {
+        kind: "Stop",
+      };
+    case 1:
+      return {
+        kind: "Move",
+        item0: readTransform(reader),
+      };
+    case 2:
+      return {
+        kind: "Say",
+        text: checkMaxLength(reader.readString(), 32),
+        volume: reader.readOptional((r0) => r0.readU8()),
+      };
+    case 3:
+      return {
+        kind: "Equip",
+        item0: Number(reader.readBits(5)),
+      };
+    default:
+      throw new NetstackError("Unknown variant of Command");
+  }
+}
+
+export function writeCommand(writer: NetstackWriter, value: Command): void {
+  switch (value.kind) {
+    case "Stop":
+      writer.writeBits(0n, 2);
+      break;
+    case "Move":
+      writer.writeBits(1n, 2);
+      writeTransform(writer, value.item0);
+      break;
+    case "Say":
+      writer.writeBits(2n, 2);
+      checkMaxLength(value.text, 32); writer.writeString(value.text);
+      writer.writeOptional(value.volume, (w0, v0) => { w0.writeU8(v0); });
+      break;
+    case "Equip":
+      writer.writeBits(3n, 2);
+      writer.writeBits(BigInt(value.item0), 5);
+      break;
+  }
+}
+
+/** Join */
+export interface Join {
+  name: string;
+  locale?: string;
+  legacyFlags?: number;
+  token: bigint;
+  offset: bigint;
+}
+
+export const JoinMessageId = 1;
+
+export function readJoin(reader: NetstackReader): Join {
+  return {
+    name: reader.readString(),
+    locale: reader.version >= 2 ? reader.readString() : undefined,
+    legacyFlags: reader.version < 3 ? reader.readU16() : undefined,
+    token: reader.readU64(),
+    offset: reader.readI64(),
+  };
+}
+
+export function writeJoin(writer: NetstackWriter, value: Join): void {
+  writer.writeString(value.name);
+  if (writer.version >= 2) { writer.writeString(required(value.locale, "locale")); }
+  if (writer.version < 3) { writer.writeU16(required(value.legacyFlags, "legacyFlags")); }
+  writer.writeU64(value.token);
+  writer.writeI64(value.offset);
+}
+
+/** Snapshot<Transform> */
+export interface SnapshotTransform {
+  tick: number;
+  entities: Array<[number, Transform]>;
+  hotbar: Array<number | null>;
+  tags: Map<string, boolean>;
+  commands: Array<Command>;
+}
+
+export const SnapshotTransformMessageId = 3459714989;
+
+export function readSnapshotTransform(reader: NetstackReader): SnapshotTransform {
+  return {
+    tick: reader.readU32(),
+    entities: reader.readList((r0) => [r0.readU16(), readTransform(r0)] as [number, Transform]),
+    hotbar: reader.readArray(3, (r0) => r0.readOptional((r1) => r1.readU8())),
+    tags: reader.readMap((r0) => r0.readString(), (r0) => r0.readBool()),
+    commands: reader.readList((r0) => readCommand(r0)),
+  };
+}
+
+export function writeSnapshotTransform(writer: NetstackWriter, value: SnapshotTransform): void {
+  writer.writeU32(value.tick);
+  writer.writeList(value.entities, (w0, v0) => { w0.writeU16(v0[0]); writeTransform(w0, v0[1]); });
+  writer.writeArray(value.hotbar, 3, (w0, v0) => { w0.writeOptional(v0, (w1, v1) => { w1.writeU8(v1); }); });
+  writer.writeMap(value.tags, (w0, v0) => { w0.writeString(v0); }, (w0, v0) => { w0.writeBool(v0); });
+  writer.writeList(value.commands, (w0, v0) => { writeCommand(w0, v0); });
+}
+
+/** Transform */
+export interface Transform {
+  x: number;
+  y: number;
+  layer: number;
+  rotation: number;
+}
+
+const transformXQuantization = new NetstackQuantization(-512.0, 512.0, 0.01);
+const transformYQuantization = new NetstackQuantization(-512.0, 512.0, 0.01);
+
+export function readTransform(reader: NetstackReader): Transform {
+  return {
+    x: transformXQuantization.read(reader),
+    y: transformYQuantization.read(reader),
+    layer: Number(reader.readRanged(-8n, 7n)),
+    rotation: reader.readF32(),
+  };
+}
+
+export function writeTransform(writer: NetstackWriter, value: Transform): void {
+  transformXQuantization.write(writer, value.x);
+  transformYQuantization.write(writer, value.y);
+  writer.writeRanged(BigInt(value.layer), -8n, 7n);
+  writer.writeF32(value.rotation);
+}
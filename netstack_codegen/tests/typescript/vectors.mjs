@@ -0,0 +1,27 @@
+// Reads every byte vector with the generated TypeScript and writes it again, the bytes must not change.
+// Run by tests/golden.rs next to a copy of golden/messages.ts named messages.mts.
+import { readFileSync } from "node:fs";
+import * as messages from "./messages.mts";
+
+const { vectors } = JSON.parse(readFileSync(process.argv[2], "utf8"));
+let failures = 0;
+
+for (const vector of vectors) {
+  const name = vector.type.replace(/[^A-Za-z0-9]/g, "");
+
+  try {
+    const reader = new messages.NetstackReader(Uint8Array.from(Buffer.from(vector.hex, "hex")), vector.version);
+    const value = messages[`read${name}`](reader);
+    if (reader.remaining !== 0) throw new Error(`${reader.remaining} bytes were not read`);
+
+    const writer = new messages.NetstackWriter(vector.version);
+    messages[`write${name}`](writer, value);
+    const written = Buffer.from(writer.toBytes()).toString("hex");
+    if (written !== vector.hex) throw new Error(`wrote ${written}`);
+  } catch (error) {
+    failures++;
+    console.error(`${vector.type} version ${vector.version} (${vector.description}): ${error.message}`);
+  }
+}
+
+process.exit(failures === 0 ? 0 : 1);
@@ -0,0 +1,397 @@
+use proc_macro2::Span;
+use syn::parse::ParseStream;
+use syn::{spanned::Spanned, Token};
+
+/// Options read from `#[netstack(...)]` on a field.
+#[derive(Default)]
+pub(crate) struct FieldAttributes {
+    /// The field is not sent and filled with its default when deserializing.
+    pub skip: bool,
+    /// An explicit default expression, otherwise `Default::default()` is used.
+    pub default: Option<syn::Expr>,
+    /// A module providing `serialize` and `deserialize` functions for the field.
+    pub with: Option<syn::Path>,
+    /// A compressed encoding replacing the field type's implementation.
+    pub encoding: Option<Encoding>,
+    /// The first protocol version the field is sent in.
+    pub since: Option<u32>,
+    /// The protocol version the field was removed in.
+    pub until: Option<u32>,
+    /// The maximum length of a collection field, bounds its encoded size.
+    pub max_len: Option<usize>,
+}
+
+/// Options read from `#[netstack(...)]` on a struct or enum.
+#[derive(Default)]
+pub(crate) struct ContainerAttributes {
+    /// An explicit message id, otherwise the id is calculated from the name of the type.
+    pub id: Option<u32>,
+}
+
+/// Compressed encodings for numeric fields.
+pub(crate) enum Encoding {
+    /// An unsigned integer written with exactly this many bits.
+    Bits(u32),
+    /// An integer within `min..=max`, written with the bits needed for the range.
+    Range(i64, i64),
+    /// An `f32` quantized with `FloatQuantization`.
+    Quantize {
+        min: f32,
+        max: f32,
+        precision: f32,
+    },
+}
+
+impl FieldAttributes {
+    pub(crate) fn from_field(field: &syn::Field) -> Result<Self, syn::Error> {
+        let mut attributes = Self::default();
+        let mut default_span = None;
+        let mut encoding_span = None;
+        let mut version_span = None;
+        let mut max_len_span = None;
+
+        for attribute in field.attrs.iter().filter(|attribute| attribute.path.is_ident("netstack")) {
+            attribute.parse_args_with(|input: ParseStream| {
+                loop {
+                    let key: syn::Ident = input.parse()?;
+
+                    match key.to_string().as_str() {
+                        "skip" => {
+                            if attributes.skip {
+                                return Err(syn::Error::new(key.span(), "duplicate `skip`"));
+                            }
+                            attributes.skip = true;
+                        },
+                        "default" => {
+                            if default_span.is_some() {
+                                return Err(syn::Error::new(key.span(), "duplicate `default`"));
+                            }
+                            default_span = Some(key.span());
+
+                            if input.peek(Token![=]) {
+                                input.parse::<Token![=]>()?;
+                                let value: syn::LitStr = input.parse()?;
+                                attributes.default = Some(value.parse().map_err(|error| {
+                                    syn::Error::new(value.span(), format!("invalid default expression: {}", error))
+                                })?);
+                            }
+                        },
+                        "with" => {
+                            if attributes.with.is_some() {
+                                return Err(syn::Error::new(key.span(), "duplicate `with`"));
+                            }
+
+                            input.parse::<Token![=]>()?;
+                            let value: syn::LitStr = input.parse()?;
+                            attributes.with = Some(value.parse().map_err(|error| {
+                                syn::Error::new(value.span(), format!("invalid module path: {}", error))
+                            })?);
+                        },
+                        "since" | "until" => {
+                            let slot = if key == "since" { &mut attributes.since } else { &mut attributes.until };
+                            if slot.is_some() {
+                                return Err(syn::Error::new(key.span(), format!("duplicate `{}`", key)));
+                            }
+
+                            input.parse::<Token![=]>()?;
+                            let value: syn::LitInt = input.parse()?;
+                            *slot = Some(value.base10_parse()?);
+                            version_span = Some(value.span());
+                        },
+                        "max_len" => {
+                            if attributes.max_len.is_some() {
+                                return Err(syn::Error::new(key.span(), "duplicate `max_len`"));
+                            }
+
+                            input.parse::<Token![=]>()?;
+                            let value: syn::LitInt = input.parse()?;
+                            attributes.max_len = Some(value.base10_parse()?);
+                            max_len_span = Some(key.span());
+                        },
+                        "bits" | "range" | "quantize" => {
+                            if encoding_span.is_some() {
+                                return Err(syn::Error::new(key.span(), "only one of `bits`, `range` or `quantize` can be used"));
+                            }
+                            encoding_span = Some(key.span());
+                            attributes.encoding = Some(parse_encoding(&key, input, &field.ty)?);
+                        },
+                        _ => return Err(syn::Error::new(
+                            key.span(),
+                            format!("unknown netstack attribute `{}`, expected `skip`, `default`, `with`, `since`, `until`, `max_len`, `bits`, `range` or `quantize`", key),
+                        )),
+                    }
+
+                    if input.is_empty() {
+                        return Ok(());
+                    }
+
+                    input.parse::<Token![,]>()?;
+
+                    if input.is_empty() {
+                        return Ok(());
+                    }
+                }
+            })?;
+        }
+
+        if let Some(span) = default_span {
+            if !attributes.skip && version_span.is_none() {
+                return Err(syn::Error::new(span, "`default` only applies to fields marked with `skip`, `since` or `until`"));
+            }
+        }
+
+        if let (Some(since), Some(until), Some(span)) = (attributes.since, attributes.until, version_span) {
+            if since >= until {
+                return Err(syn::Error::new(span, "`until` must be larger than `since`"));
+            }
+        }
+
+        if attributes.skip {
+            if let Some(with) = &attributes.with {
+                return Err(syn::Error::new(with.span(), "`with` cannot be combined with `skip`, skipped fields are not sent"));
+            }
+
+            if let Some(span) = encoding_span {
+                return Err(syn::Error::new(span, "skipped fields are not sent and cannot have an encoding"));
+            }
+
+            if let Some(span) = version_span {
+                return Err(syn::Error::new(span, "skipped fields are not sent in any version, remove `since` and `until`"));
+            }
+        }
+
+        if let (Some(with), Some(_)) = (&attributes.with, &attributes.encoding) {
+            return Err(syn::Error::new(with.span(), "`with` cannot be combined with `bits`, `range` or `quantize`"));
+        }
+
+        if let Some(span) = max_len_span {
+            if attributes.skip || attributes.with.is_some() || attributes.encoding.is_some() {
+                return Err(syn::Error::new(span, "`max_len` cannot be combined with `skip`, `with` or an encoding"));
+            }
+        }
+
+        Ok(attributes)
+    }
+
+    /// Returns a condition checking whether the field exists in `version`,
+    /// `None` if it exists in every version.
+    pub(crate) fn version_check(&self, version: &proc_macro2::TokenStream) -> Option<proc_macro2::TokenStream> {
+        match (self.since, self.until) {
+            (None, None) => None,
+            (Some(since), None) => Some(quote::quote! { #version >= #since }),
+            (None, Some(until)) => Some(quote::quote! { #version < #until }),
+            (Some(since), Some(until)) => Some(quote::quote! { (#since..#until).contains(&#version) }),
+        }
+    }
+
+    /// The expression a skipped or missing field is initialized with.
+    pub(crate) fn default_value(&self) -> proc_macro2::TokenStream {
+        match &self.default {
+            Some(expression) => quote::quote! { #expression },
+            None => quote::quote! { ::std::default::Default::default() },
+        }
+    }
+}
+
+impl Encoding {
+    /// Returns the number of bits a value takes up, `Writer` rounds them up to whole bytes.
+    pub(crate) fn get_bits(&self) -> u32 {
+        match self {
+            Encoding::Bits(bits) => *bits,
+            Encoding::Range(min, max) => bits_required(*min, *max),
+            // the same calculation as `FloatQuantization::new`
+            Encoding::Quantize { min, max, precision } => {
+                let steps = ((f64::from(*max) - f64::from(*min)) / f64::from(*precision)).ceil();
+                bits_required(0, (steps as i64).max(1))
+            },
+        }
+    }
+}
+
+fn bits_required(min: i64, max: i64) -> u32 {
+    let range = max.wrapping_sub(min) as u64;
+    64 - range.leading_zeros()
+}
+
+impl ContainerAttributes {
+    pub(crate) fn from_input(input: &syn::DeriveInput) -> Result<Self, syn::Error> {
+        let mut attributes = Self::default();
+
+        for attribute in input.attrs.iter().filter(|attribute| attribute.path.is_ident("netstack")) {
+            attribute.parse_args_with(|input: ParseStream| {
+                loop {
+                    let key: syn::Ident = input.parse()?;
+
+                    match key.to_string().as_str() {
+                        "id" => {
+                            if attributes.id.is_some() {
+                                return Err(syn::Error::new(key.span(), "duplicate `id`"));
+                            }
+
+                            input.parse::<Token![=]>()?;
+                            let value: syn::LitInt = input.parse()?;
+                            attributes.id = Some(value.base10_parse()?);
+                        },
+                        _ => return Err(syn::Error::new(
+                            key.span(),
+                            format!("unknown netstack attribute `{}`, expected `id`", key),
+                        )),
+                    }
+
+                    if input.is_empty() {
+                        return Ok(());
+                    }
+
+                    input.parse::<Token![,]>()?;
+
+                    if input.is_empty() {
+                        return Ok(());
+                    }
+                }
+            })?;
+        }
+
+        Ok(attributes)
+    }
+}
+
+fn parse_encoding(key: &syn::Ident, input: ParseStream, ty: &syn::Type) -> Result<Encoding, syn::Error> {
+    let type_name = primitive_name(ty);
+
+    match key.to_string().as_str() {
+        "bits" => {
+            input.parse::<Token![=]>()?;
+            let value: syn::LitInt = input.parse()?;
+            let bits: u32 = value.base10_parse()?;
+
+            let width = match type_name.as_deref() {
+                Some("u8") => 8,
+                Some("u16") => 16,
+                Some("u32") => 32,
+                Some("u64") => 64,
+                _ => return Err(syn::Error::new(ty.span(), "`bits` requires an unsigned integer field (u8, u16, u32 or u64)")),
+            };
+
+            if bits == 0 || bits > width {
+                return Err(syn::Error::new(value.span(), format!("`bits` must be between 1 and {} for this field", width)));
+            }
+
+            Ok(Encoding::Bits(bits))
+        },
+        "range" => {
+            input.parse::<Token![=]>()?;
+            let (min, min_span) = parse_integer(input)?;
+            input.parse::<Token![..=]>()?;
+            let (max, max_span) = parse_integer(input)?;
+
+            let (type_min, type_max) = match type_name.as_deref() {
+                Some("u8") => (0, i128::from(u8::MAX)),
+                Some("u16") => (0, i128::from(u16::MAX)),
+                Some("u32") => (0, i128::from(u32::MAX)),
+                Some("u64") => (0, i128::from(i64::MAX)),
+                Some("i8") => (i128::from(i8::MIN), i128::from(i8::MAX)),
+                Some("i16") => (i128::from(i16::MIN), i128::from(i16::MAX)),
+                Some("i32") => (i128::from(i32::MIN), i128::from(i32::MAX)),
+                Some("i64") => (i128::from(i64::MIN), i128::from(i64::MAX)),
+                _ => return Err(syn::Error::new(ty.span(), "`range` requires an integer field")),
+            };
+
+            if min < type_min || min > type_max {
+                return Err(syn::Error::new(min_span, format!("range start doesn't fit into {}..={}", type_min, type_max)));
+            }
+
+            if max < type_min || max > type_max {
+                return Err(syn::Error::new(max_span, format!("range end doesn't fit into {}..={}", type_min, type_max)));
+            }
+
+            if min > max {
+                return Err(syn::Error::new(max_span, "range end must not be smaller than its start"));
+            }
+
+            Ok(Encoding::Range(min as i64, max as i64))
+        },
+        _ => {
+            if type_name.as_deref() != Some("f32") {
+                return Err(syn::Error::new(ty.span(), "`quantize` requires an f32 field"));
+            }
+
+            let content;
+            syn::parenthesized!(content in input);
+
+            let mut min = None;
+            let mut max = None;
+            let mut precision = None;
+
+            while !content.is_empty() {
+                let name: syn::Ident = content.parse()?;
+                content.parse::<Token![=]>()?;
+                let value = parse_float(&content)?;
+
+                let slot = match name.to_string().as_str() {
+                    "min" => &mut min,
+                    "max" => &mut max,
+                    "precision" => &mut precision,
+                    _ => return Err(syn::Error::new(name.span(), "expected `min`, `max` or `precision`")),
+                };
+
+                if slot.is_some() {
+                    return Err(syn::Error::new(name.span(), format!("duplicate `{}`", name)));
+                }
+                *slot = Some(value);
+
+                if !content.is_empty() {
+                    content.parse::<Token![,]>()?;
+                }
+            }
+
+            let (min, max, precision) = match (min, max, precision) {
+                (Some(min), Some(max), Some(precision)) => (min, max, precision),
+                _ => return Err(syn::Error::new(key.span(), "`quantize` requires `min`, `max` and `precision`")),
+            };
+
+            if min.0 >= max.0 {
+                return Err(syn::Error::new(max.1, "`max` must be larger than `min`"));
+            }
+
+            if precision.0 <= 0.0 {
+                return Err(syn::Error::new(precision.1, "`precision` must be larger than zero"));
+            }
+
+            Ok(Encoding::Quantize { min: min.0 as f32, max: max.0 as f32, precision: precision.0 as f32 })
+        },
+    }
+}
+
+/// Returns the name of a primitive type like `u8`, other types return `None`.
+fn primitive_name(ty: &syn::Type) -> Option<String> {
+    match ty {
+        syn::Type::Path(path) if path.qself.is_none() => path.path.get_ident().map(|ident| ident.to_string()),
+        _ => None,
+    }
+}
+
+fn parse_integer(input: ParseStream) -> Result<(i128, Span), syn::Error> {
+    let negative = input.parse::<Option<Token![-]>>()?.is_some();
+    let value: syn::LitInt = input.parse()?;
+    let number: i128 = value.base10_parse()?;
+
+    Ok((if negative { -number } else { number }, value.span()))
+}
+
+fn parse_float(input: ParseStream) -> Result<(f64, Span), syn::Error> {
+    let negative = input.parse::<Option<Token![-]>>()?.is_some();
+
+    let (number, span) = if input.peek(syn::LitInt) {
+        let value: syn::LitInt = input.parse()?;
+        (value.base10_parse::<f64>()?, value.span())
+    } else {
+        let value: syn::LitFloat = input.parse()?;
+        (value.base10_parse::<f64>()?, value.span())
+    };
+
+    if !number.is_finite() {
+        return Err(syn::Error::new(span, "expected a finite number"));
+    }
+
+    Ok((if negative { -number } else { number }, span))
+}
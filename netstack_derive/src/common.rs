@@ -0,0 +1,66 @@
+use syn::{spanned::Spanned, DataEnum, Fields};
+use quote::quote;
+use crate::attributes::FieldAttributes;
+
+/// Returns the number of bits needed to encode the position of every variant.
+pub(crate) fn discriminant_bits(e: &DataEnum) -> Result<u32, syn::Error> {
+    // variants are encoded by their position, explicit values would suggest otherwise
+    if let Some(variant) = e.variants.iter().find(|variant| variant.discriminant.is_some()) {
+        let (_, expression) = variant.discriminant.as_ref().unwrap();
+        return Err(syn::Error::new(
+            expression.span(),
+            "explicit discriminants are not supported, variants are encoded by their position",
+        ));
+    }
+
+    let max = e.variants.len().saturating_sub(1) as u64;
+    Ok(64 - max.leading_zeros())
+}
+
+/// Adds a bound to every type parameter, `bound` receives the name of the parameter.
+pub(crate) fn with_bounds(generics: &syn::Generics, bound: impl Fn(&syn::Ident) -> syn::TypeParamBound) -> syn::Generics {
+    let mut generics = generics.clone();
+
+    for param in generics.type_params_mut() {
+        let parameter_bound = bound(&param.ident);
+        param.bounds.push(parameter_bound);
+    }
+
+    generics
+}
+
+/// Builds a struct or variant at `path` from one expression per field.
+pub(crate) fn construct(path: proc_macro2::TokenStream, fields: &Fields, values: Vec<proc_macro2::TokenStream>) -> proc_macro2::TokenStream {
+    match fields {
+        Fields::Named(_) => {
+            let names = fields.iter().map(|field| &field.ident);
+            quote! { #path { #(#names: #values),* } }
+        },
+        Fields::Unnamed(_) => quote! { #path(#(#values),*) },
+        Fields::Unit => quote! { #path },
+    }
+}
+
+/// Names the fields of a struct or variant in a match pattern, skipped fields are ignored with `_`.
+pub(crate) fn field_bindings(fields: &Fields, prefix: &str) -> Result<Vec<proc_macro2::TokenStream>, syn::Error> {
+    fields.iter()
+        .enumerate()
+        .map(|(index, field)| {
+            if FieldAttributes::from_field(field)?.skip {
+                Ok(quote! { _ })
+            } else {
+                let binding = quote::format_ident!("{}_{}", prefix, index);
+                Ok(quote! { #binding })
+            }
+        }).collect()
+}
+
+/// Adds the `'de` lifetime of the deserializer, borrowed fields are tied to it.
+pub(crate) fn with_de_lifetime(generics: &syn::Generics) -> syn::Generics {
+    let mut de_lifetime: syn::LifetimeDef = syn::parse_quote! { 'de };
+    de_lifetime.bounds.extend(generics.lifetimes().map(|lifetime| lifetime.lifetime.clone()));
+
+    let mut generics = generics.clone();
+    generics.params.insert(0, syn::GenericParam::Lifetime(de_lifetime));
+    generics
+}
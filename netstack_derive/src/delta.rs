@@ -0,0 +1,308 @@
+use syn::{spanned::Spanned, Data, DataEnum, DataStruct, Fields};
+use quote::{quote, quote_spanned};
+use crate::attributes::FieldAttributes;
+use crate::common::{construct, field_bindings, with_bounds, with_de_lifetime};
+use crate::deserialize::deserialize_field;
+use crate::serialize::{check_max_len, serialize_field};
+
+pub(crate) fn derive_delta_serialize_impl(input: syn::DeriveInput) -> Result<proc_macro2::TokenStream, syn::Error> {
+    match &input.data {
+        Data::Struct(s) => derive_serialize_struct(&input, s),
+        Data::Enum(e) => derive_serialize_enum(&input, e),
+        Data::Union(u) => Err(syn::Error::new(
+            u.union_token.span(),
+            "DeltaSerialize implementations cannot be derived from unions",
+        )),
+    }
+}
+
+pub(crate) fn derive_delta_deserialize_impl(input: syn::DeriveInput) -> Result<proc_macro2::TokenStream, syn::Error> {
+    match &input.data {
+        Data::Struct(s) => derive_deserialize_struct(&input, s),
+        Data::Enum(e) => derive_deserialize_enum(&input, e),
+        Data::Union(u) => Err(syn::Error::new(
+            u.union_token.span(),
+            "DeltaDeserialize implementations cannot be derived from unions",
+        )),
+    }
+}
+
+fn derive_serialize_struct(input: &syn::DeriveInput, s: &DataStruct) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let derive_type = &input.ident;
+    let generics = delta_serialize_bounds(&input.generics);
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+
+    let members = members(&s.fields);
+    let values = members.iter().map(|member| quote! { &self.#member }).collect();
+    let baselines = members.iter().map(|member| quote! { &baseline.#member }).collect();
+    let body = serialize_changed_fields(&s.fields, values, baselines)?;
+
+    let result = quote! {
+        impl #impl_generics netstack::serialization::DeltaSerialize for #derive_type #type_generics #where_clause {
+            fn serialize_delta(&self, baseline: &Self, serializer: &mut impl netstack::serialization::Serializer) -> Result<(), netstack::serialization::SerializationError> {
+                #body
+                Ok(())
+            }
+        }
+    };
+
+    Ok(result)
+}
+
+fn derive_serialize_enum(input: &syn::DeriveInput, e: &DataEnum) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let derive_type = &input.ident;
+    let generics = delta_serialize_bounds(&input.generics);
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+
+    let variants = e.variants.iter()
+        .map(|variant| {
+            let name = &variant.ident;
+            let values = field_bindings(&variant.fields, "field")?;
+            let baselines = field_bindings(&variant.fields, "baseline")?;
+            let body = serialize_changed_fields(&variant.fields, values.clone(), baselines.clone())?;
+
+            let pattern = construct(quote! { Self::#name }, &variant.fields, values);
+            let baseline_pattern = construct(quote! { Self::#name }, &variant.fields, baselines);
+
+            Ok(quote! {
+                (#pattern, #baseline_pattern) => {
+                    serializer.serialize_bool(&true)?;
+                    #body
+                }
+            })
+        }).collect::<Result<Vec<_>, syn::Error>>()?;
+
+    // a delta only helps if the variant stayed the same, otherwise the full value is sent
+    let body = if variants.is_empty() {
+        quote! { match *self {} }
+    } else {
+        quote! {
+            match (self, baseline) {
+                #(#variants)*
+                #[allow(unreachable_patterns)]
+                _ => {
+                    serializer.serialize_bool(&false)?;
+                    netstack::serialization::Serialize::serialize(self, serializer)?;
+                },
+            }
+
+            Ok(())
+        }
+    };
+
+    let result = quote! {
+        impl #impl_generics netstack::serialization::DeltaSerialize for #derive_type #type_generics #where_clause {
+            fn serialize_delta(&self, baseline: &Self, serializer: &mut impl netstack::serialization::Serializer) -> Result<(), netstack::serialization::SerializationError> {
+                #body
+            }
+        }
+    };
+
+    Ok(result)
+}
+
+fn derive_deserialize_struct(input: &syn::DeriveInput, s: &DataStruct) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let derive_type = &input.ident;
+    let (_, type_generics, where_clause) = input.generics.split_for_impl();
+    let generics = delta_deserialize_generics(&input.generics);
+    let (impl_generics, _, _) = generics.split_for_impl();
+
+    let baselines = members(&s.fields).iter().map(|member| quote! { &baseline.#member }).collect();
+    let (mask, values) = deserialize_changed_fields(&s.fields, baselines)?;
+    let value = construct(quote! { Self }, &s.fields, values);
+
+    let result = quote! {
+        impl #impl_generics netstack::serialization::DeltaDeserialize<'de> for #derive_type #type_generics #where_clause {
+            fn deserialize_delta(baseline: &Self, deserializer: &mut impl netstack::serialization::Deserializer<'de>) -> Result<Self, netstack::serialization::SerializationError> {
+                #mask
+                Ok(#value)
+            }
+        }
+    };
+
+    Ok(result)
+}
+
+fn derive_deserialize_enum(input: &syn::DeriveInput, e: &DataEnum) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let derive_type = &input.ident;
+    let (_, type_generics, where_clause) = input.generics.split_for_impl();
+    let generics = delta_deserialize_generics(&input.generics);
+    let (impl_generics, _, _) = generics.split_for_impl();
+
+    let variants = e.variants.iter()
+        .map(|variant| {
+            let name = &variant.ident;
+            let baselines = field_bindings(&variant.fields, "baseline")?;
+            let (mask, values) = deserialize_changed_fields(&variant.fields, baselines.clone())?;
+
+            let baseline_pattern = construct(quote! { Self::#name }, &variant.fields, baselines);
+            let value = construct(quote! { Self::#name }, &variant.fields, values);
+
+            Ok(quote! {
+                #baseline_pattern => {
+                    #mask
+                    Ok(#value)
+                },
+            })
+        }).collect::<Result<Vec<_>, syn::Error>>()?;
+
+    let body = if variants.is_empty() {
+        quote! { match *baseline {} }
+    } else {
+        quote! {
+            if !deserializer.deserialize_bool()? {
+                return <Self as netstack::serialization::Deserialize<'de>>::deserialize(deserializer);
+            }
+
+            match baseline {
+                #(#variants)*
+            }
+        }
+    };
+
+    let result = quote! {
+        impl #impl_generics netstack::serialization::DeltaDeserialize<'de> for #derive_type #type_generics #where_clause {
+            fn deserialize_delta(baseline: &Self, deserializer: &mut impl netstack::serialization::Deserializer<'de>) -> Result<Self, netstack::serialization::SerializationError> {
+                #body
+            }
+        }
+    };
+
+    Ok(result)
+}
+
+/// Writes a mask of the fields that differ from the baseline, followed by those fields.
+///
+/// `values` and `baselines` are references to the fields. Fields without an encoding attribute are
+/// written as deltas themselves, so nested types only send what changed within them as well.
+fn serialize_changed_fields(
+    fields: &Fields,
+    values: Vec<proc_macro2::TokenStream>,
+    baselines: Vec<proc_macro2::TokenStream>,
+) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let mut changed = Vec::new();
+    let mut writes = Vec::new();
+
+    for ((field, value), baseline) in fields.iter().zip(values).zip(baselines) {
+        let attributes = FieldAttributes::from_field(field)?;
+
+        if attributes.skip {
+            continue;
+        }
+
+        let index = changed.len();
+        let ty = &field.ty;
+        let version = quote! { netstack::serialization::Serializer::get_version(serializer) };
+        changed.push(match attributes.version_check(&version) {
+            Some(check) => quote! { #check && #value != #baseline },
+            None => quote! { #value != #baseline },
+        });
+
+        let write = if attributes.with.is_some() || attributes.encoding.is_some() {
+            serialize_field(field, &attributes, &value)
+        } else {
+            let check = check_max_len(field, &attributes, &value);
+            quote_spanned! { ty.span() =>
+                #check
+                netstack::serialization::DeltaSerialize::serialize_delta(#value, #baseline, serializer)?;
+            }
+        };
+
+        writes.push(quote! {
+            if changed[#index] {
+                #write
+            }
+        });
+    }
+
+    let count = changed.len();
+
+    Ok(quote! {
+        let changed: [bool; #count] = [#(#changed),*];
+        netstack::serialization::serialize_changed_mask(serializer, &changed)?;
+        #(#writes)*
+    })
+}
+
+/// Reads the mask written by `serialize_changed_fields` and returns one expression per field,
+/// unchanged fields are cloned from the baseline.
+fn deserialize_changed_fields(
+    fields: &Fields,
+    baselines: Vec<proc_macro2::TokenStream>,
+) -> Result<(proc_macro2::TokenStream, Vec<proc_macro2::TokenStream>), syn::Error> {
+    let mut count = 0usize;
+    let mut values = Vec::new();
+
+    for (field, baseline) in fields.iter().zip(baselines) {
+        let attributes = FieldAttributes::from_field(field)?;
+
+        if attributes.skip {
+            values.push(attributes.default_value());
+            continue;
+        }
+
+        let index = count;
+        let ty = &field.ty;
+        count += 1;
+
+        let read = if attributes.with.is_some() || attributes.encoding.is_some() {
+            deserialize_field(field, &attributes)
+        } else {
+            let check = check_max_len(field, &attributes, &quote! { &value });
+            quote_spanned! { ty.span() =>
+                {
+                    let value = <#ty as netstack::serialization::DeltaDeserialize<'de>>::deserialize_delta(#baseline, deserializer)?;
+                    #check
+                    value
+                }
+            }
+        };
+
+        let value = quote! {
+            if changed[#index] {
+                #read
+            } else {
+                ::std::clone::Clone::clone(#baseline)
+            }
+        };
+
+        // fields missing in the version keep their place in the mask but are never marked as changed
+        let version = quote! { netstack::serialization::Deserializer::get_version(deserializer) };
+        values.push(match attributes.version_check(&version) {
+            Some(check) => {
+                let default = attributes.default_value();
+                quote! { if #check { #value } else { #default } }
+            },
+            None => value,
+        });
+    }
+
+    let mask = if count == 0 {
+        quote! {}
+    } else {
+        quote! {
+            let changed = netstack::serialization::deserialize_changed_mask(deserializer, #count)?;
+        }
+    };
+
+    Ok((mask, values))
+}
+
+fn members(fields: &Fields) -> Vec<syn::Member> {
+    fields.iter()
+        .enumerate()
+        .map(|(index, field)| match &field.ident {
+            Some(name) => syn::Member::Named(name.clone()),
+            None => syn::Member::Unnamed(index.into()),
+        }).collect()
+}
+
+fn delta_serialize_bounds(generics: &syn::Generics) -> syn::Generics {
+    let generics = with_bounds(generics, |_| syn::parse_quote! { netstack::serialization::DeltaSerialize });
+    with_bounds(&generics, |_| syn::parse_quote! { ::std::cmp::PartialEq })
+}
+
+fn delta_deserialize_generics(generics: &syn::Generics) -> syn::Generics {
+    let generics = with_bounds(generics, |_| syn::parse_quote! { netstack::serialization::DeltaDeserialize<'de> });
+    with_de_lifetime(&with_bounds(&generics, |_| syn::parse_quote! { ::std::clone::Clone }))
+}
@@ -1,4 +1,6 @@
-use syn::{spanned::Spanned, Data, DataEnum, DataStruct};
+use syn::{spanned::Spanned, Data, DataEnum, DataStruct, Fields};
+use crate::attributes::{Encoding, FieldAttributes};
+use crate::common::{construct, discriminant_bits, with_bounds, with_de_lifetime};
 use quote::{quote, quote_spanned};
 
 pub(crate) fn derive_deserialize_impl(input: syn::DeriveInput) -> Result<proc_macro2::TokenStream, syn::Error> {
@@ -14,22 +16,21 @@ pub(crate) fn derive_deserialize_impl(input: syn::DeriveInput) -> Result<proc_ma
 
 fn derive_struct(input: &syn::DeriveInput, s: &DataStruct) -> Result<proc_macro2::TokenStream, syn::Error> {
     let derive_type = &input.ident;
+    let (_, type_generics, where_clause) = input.generics.split_for_impl();
+    let generics = deserialize_generics(&input.generics);
+    let (impl_generics, _, _) = generics.split_for_impl();
 
-    let fields: Vec<proc_macro2::TokenStream> = s.fields.iter()
-        .map(|field| {
-            let name = &field.ident;
-            let ty = &field.ty;
-            quote_spanned! { ty.span() => #name: #ty::deserialize(deserializer)? }
-        }).collect();
+    let value = construct(quote! { Self }, &s.fields, deserialize_fields(&s.fields)?);
+    let min_size = min_encoded_size(&s.fields)?;
 
     let result = quote! {
-        impl netstack::serialization::Deserialize for #derive_type {
-            type Item = #derive_type;
+        impl #impl_generics netstack::serialization::Deserialize<'de> for #derive_type #type_generics #where_clause {
+            type Item = Self;
+
+            const MIN_ENCODED_SIZE: usize = #min_size;
 
-            fn deserialize(deserializer: &mut impl netstack::serialization::Deserializer) -> Result<Self::Item, netstack::serialization::SerializationError> {
-                Ok(Self::Item {
-                    #(#fields),*
-                })
+            fn deserialize(deserializer: &mut impl netstack::serialization::Deserializer<'de>) -> Result<Self::Item, netstack::serialization::SerializationError> {
+                Ok(#value)
             }
         }
     };
@@ -37,7 +38,127 @@ fn derive_struct(input: &syn::DeriveInput, s: &DataStruct) -> Result<proc_macro2
     Ok(result)
 }
 
-fn derive_enum(input: &syn::DeriveInput, _s: &DataEnum) -> Result<proc_macro2::TokenStream, syn::Error> {
-    dbg!(&input.ident);
-    unimplemented!()
+fn derive_enum(input: &syn::DeriveInput, e: &DataEnum) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let derive_type = &input.ident;
+    let (_, type_generics, where_clause) = input.generics.split_for_impl();
+    let generics = deserialize_generics(&input.generics);
+    let (impl_generics, _, _) = generics.split_for_impl();
+    let bits = discriminant_bits(e)?;
+
+    // a single variant has no discriminant, so only its fields take up space
+    let min_size = match e.variants.first() {
+        Some(variant) if bits == 0 => min_encoded_size(&variant.fields)?,
+        _ => {
+            let size = (bits as usize).div_ceil(8).max(1);
+            quote! { #size }
+        },
+    };
+
+    let variants = e.variants.iter()
+        .enumerate()
+        .map(|(index, variant)| {
+            let name = &variant.ident;
+            let index = index as u64;
+
+            let value = construct(quote! { Self::#name }, &variant.fields, deserialize_fields(&variant.fields)?);
+
+            Ok(quote! { #index => Ok(#value), })
+        }).collect::<Result<Vec<_>, syn::Error>>()?;
+
+    let result = quote! {
+        impl #impl_generics netstack::serialization::Deserialize<'de> for #derive_type #type_generics #where_clause {
+            type Item = Self;
+
+            const MIN_ENCODED_SIZE: usize = #min_size;
+
+            fn deserialize(deserializer: &mut impl netstack::serialization::Deserializer<'de>) -> Result<Self::Item, netstack::serialization::SerializationError> {
+                match deserializer.deserialize_bits(#bits)? {
+                    #(#variants)*
+                    value => Err(netstack::serialization::SerializationError::InvalidDiscriminant { value }),
+                }
+            }
+        }
+    };
+
+    Ok(result)
+}
+
+/// Reads every field that isn't skipped, skipped fields and fields missing in the deserializer's
+/// version get their default value.
+fn deserialize_fields(fields: &Fields) -> Result<Vec<proc_macro2::TokenStream>, syn::Error> {
+    fields.iter()
+        .map(|field| {
+            let attributes = FieldAttributes::from_field(field)?;
+
+            if attributes.skip {
+                return Ok(attributes.default_value());
+            }
+
+            let read = deserialize_field(field, &attributes);
+            let version = quote! { netstack::serialization::Deserializer::get_version(deserializer) };
+
+            match attributes.version_check(&version) {
+                Some(check) => {
+                    let default = attributes.default_value();
+                    Ok(quote! { if #check { #read } else { #default } })
+                },
+                None => Ok(read),
+            }
+        }).collect()
+}
+
+/// Sums up the smallest number of bytes the fields take up, fields that may be missing count as zero.
+fn min_encoded_size(fields: &Fields) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let sizes = fields.iter()
+        .map(|field| {
+            let attributes = FieldAttributes::from_field(field)?;
+            let ty = &field.ty;
+
+            if attributes.skip || attributes.with.is_some() || attributes.since.is_some() || attributes.until.is_some() {
+                return Ok(quote! { 0 });
+            }
+
+            let bits = match &attributes.encoding {
+                Some(Encoding::Bits(bits)) => *bits,
+                Some(Encoding::Range(min, max)) => 64 - (max.wrapping_sub(*min) as u64).leading_zeros(),
+                // the quantization has at least one step
+                Some(Encoding::Quantize { .. }) => 1,
+                None => return Ok(quote_spanned! { ty.span() => <#ty as netstack::serialization::Deserialize<'de>>::MIN_ENCODED_SIZE }),
+            };
+
+            let size = (bits as usize).div_ceil(8);
+            Ok(quote! { #size })
+        }).collect::<Result<Vec<_>, syn::Error>>()?;
+
+    Ok(quote! { 0usize #(.saturating_add(#sizes))* })
+}
+
+/// Reads a single field.
+pub(crate) fn deserialize_field(field: &syn::Field, attributes: &FieldAttributes) -> proc_macro2::TokenStream {
+    let ty = &field.ty;
+
+    // the attributes were checked to fit the field type, the casts can't truncate
+    match (&attributes.with, &attributes.encoding) {
+        (Some(with), _) => quote_spanned! { with.span() => #with::deserialize(deserializer)? },
+        (None, Some(Encoding::Bits(bits))) => quote_spanned! { ty.span() =>
+            netstack::serialization::Deserializer::deserialize_bits(deserializer, #bits)? as #ty
+        },
+        (None, Some(Encoding::Range(min, max))) => quote_spanned! { ty.span() =>
+            netstack::serialization::Deserializer::deserialize_ranged(deserializer, #min, #max)? as #ty
+        },
+        (None, Some(Encoding::Quantize { min, max, precision })) => quote_spanned! { ty.span() =>
+            netstack::serialization::FloatQuantization::new(#min, #max, #precision)?.deserialize(deserializer)?
+        },
+        (None, None) => match attributes.max_len {
+            Some(max_len) => quote_spanned! { ty.span() =>
+                <#ty as netstack::serialization::DeserializeBounded<'de>>::deserialize_bounded(deserializer, #max_len)?
+            },
+            None => quote_spanned! { ty.span() => <#ty as netstack::serialization::Deserialize<'de>>::deserialize(deserializer)? },
+        },
+    }
+}
+
+/// Requires every type parameter to be deserializable from the `'de` lifetime.
+fn deserialize_generics(generics: &syn::Generics) -> syn::Generics {
+    with_de_lifetime(&with_bounds(generics, |name| syn::parse_quote! { netstack::serialization::Deserialize<'de, Item = #name> }))
 }
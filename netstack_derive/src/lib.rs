@@ -3,8 +3,13 @@ extern crate proc_macro;
 use proc_macro::TokenStream;
 use syn::parse_macro_input;
 
+mod attributes;
+mod common;
 mod serialize;
 mod deserialize;
+mod delta;
+mod message;
+mod schema;
 
 #[proc_macro_derive(Serialize, attributes(netstack))]
 pub fn derive_serialize(input: TokenStream) -> TokenStream {
@@ -21,3 +26,35 @@ pub fn derive_deserialize(input: TokenStream) -> TokenStream {
         .unwrap_or_else(|err| err.to_compile_error())
         .into()
 }
+
+#[proc_macro_derive(DeltaSerialize, attributes(netstack))]
+pub fn derive_delta_serialize(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    delta::derive_delta_serialize_impl(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+#[proc_macro_derive(DeltaDeserialize, attributes(netstack))]
+pub fn derive_delta_deserialize(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    delta::derive_delta_deserialize_impl(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+#[proc_macro_derive(Message, attributes(netstack))]
+pub fn derive_message(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    message::derive_message_impl(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
+
+#[proc_macro_derive(Schema, attributes(netstack))]
+pub fn derive_schema(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    schema::derive_schema_impl(input)
+        .unwrap_or_else(|err| err.to_compile_error())
+        .into()
+}
@@ -0,0 +1,27 @@
+use crate::attributes::ContainerAttributes;
+use crate::common::with_bounds;
+use quote::quote;
+
+pub(crate) fn derive_message_impl(input: syn::DeriveInput) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let derive_type = &input.ident;
+    let attributes = ContainerAttributes::from_input(&input)?;
+    let generics = with_bounds(&input.generics, |_| syn::parse_quote! { netstack::serialization::Serialize });
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+
+    // hashing only the name keeps the id stable when the type moves to another module or crate
+    let id = match attributes.id {
+        Some(id) => quote! { #id },
+        None => {
+            let name = derive_type.to_string();
+            quote! { netstack::messages::message_id(#name) }
+        },
+    };
+
+    let result = quote! {
+        impl #impl_generics netstack::messages::Message for #derive_type #type_generics #where_clause {
+            const ID: u32 = #id;
+        }
+    };
+
+    Ok(result)
+}
@@ -0,0 +1,166 @@
+use syn::{spanned::Spanned, Data, Fields};
+use quote::{quote, quote_spanned};
+use crate::attributes::{Encoding, FieldAttributes};
+use crate::common::{discriminant_bits, with_bounds};
+
+pub(crate) fn derive_schema_impl(input: syn::DeriveInput) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let derive_type = &input.ident;
+    let generics = with_bounds(&input.generics, |_| syn::parse_quote! { netstack::schema::Schema });
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+
+    let (kind, nested) = match &input.data {
+        Data::Struct(s) => {
+            let (fields, nested) = fields(&s.fields)?;
+            let kind = quote! { netstack::schema::DefinitionKind::Struct { fields: vec![#(#fields),*] } };
+            (kind, nested)
+        },
+        Data::Enum(e) => {
+            let bits = discriminant_bits(e)?;
+            let mut variants = Vec::new();
+            let mut nested = Vec::new();
+
+            for variant in &e.variants {
+                let name = variant.ident.to_string();
+                let (fields, variant_nested) = fields(&variant.fields)?;
+
+                variants.push(quote! {
+                    netstack::schema::Variant {
+                        name: #name.to_owned(),
+                        fields: vec![#(#fields),*],
+                    }
+                });
+                nested.extend(variant_nested);
+            }
+
+            let kind = quote! {
+                netstack::schema::DefinitionKind::Enum {
+                    discriminant_bits: #bits,
+                    variants: vec![#(#variants),*],
+                }
+            };
+            (kind, nested)
+        },
+        Data::Union(u) => return Err(syn::Error::new(
+            u.union_token.span(),
+            "Schema implementations cannot be derived from unions",
+        )),
+    };
+
+    let name = type_name(&input);
+
+    // the definition is added before the nested types, so recursive types terminate
+    let result = quote! {
+        impl #impl_generics netstack::schema::Schema for #derive_type #type_generics #where_clause {
+            fn get_type() -> netstack::schema::Type {
+                netstack::schema::Type::Named(#name)
+            }
+
+            fn register(registry: &mut netstack::schema::Registry) {
+                let definition = netstack::schema::Definition {
+                    name: #name,
+                    message_id: None,
+                    kind: #kind,
+                };
+
+                if registry.add_definition(definition) {
+                    #(#nested)*
+                }
+            }
+        }
+    };
+
+    Ok(result)
+}
+
+/// Generic types are named after their arguments, e.g. `Snapshot<u8>`, so every instance gets its own definition.
+fn type_name(input: &syn::DeriveInput) -> proc_macro2::TokenStream {
+    let name = input.ident.to_string();
+    let arguments: Vec<_> = input.generics.params.iter()
+        .filter_map(|param| match param {
+            syn::GenericParam::Type(param) => {
+                let ident = &param.ident;
+                Some(quote! { <#ident as netstack::schema::Schema>::get_type().to_string() })
+            },
+            syn::GenericParam::Const(param) => {
+                let ident = &param.ident;
+                Some(quote! { #ident.to_string() })
+            },
+            syn::GenericParam::Lifetime(_) => None,
+        }).collect();
+
+    if arguments.is_empty() {
+        quote! { #name.to_owned() }
+    } else {
+        quote! { format!("{}<{}>", #name, [#(#arguments),*].join(", ")) }
+    }
+}
+
+/// Returns a `Field` for every field that is sent and the calls registering their types.
+fn fields(fields: &Fields) -> Result<(Vec<proc_macro2::TokenStream>, Vec<proc_macro2::TokenStream>), syn::Error> {
+    let mut result = Vec::new();
+    let mut nested = Vec::new();
+
+    for (index, field) in fields.iter().enumerate() {
+        let attributes = FieldAttributes::from_field(field)?;
+
+        if attributes.skip {
+            continue;
+        }
+
+        let ty = &field.ty;
+        let name = match &field.ident {
+            Some(ident) => ident.to_string(),
+            None => index.to_string(),
+        };
+
+        let field_type = match &attributes.with {
+            Some(with) => {
+                let module = quote!(#with).to_string().replace(' ', "");
+                quote! { netstack::schema::Type::Custom(#module.to_owned()) }
+            },
+            None => {
+                nested.push(quote_spanned! { ty.span() => <#ty as netstack::schema::Schema>::register(registry); });
+                quote_spanned! { ty.span() => <#ty as netstack::schema::Schema>::get_type() }
+            },
+        };
+
+        let encoding = match &attributes.encoding {
+            Some(encoding) => {
+                let bits = encoding.get_bits();
+                let value = match encoding {
+                    Encoding::Bits(_) => quote! { netstack::schema::Encoding::Bits { bits: #bits } },
+                    Encoding::Range(min, max) => quote! { netstack::schema::Encoding::Range { min: #min, max: #max, bits: #bits } },
+                    Encoding::Quantize { min, max, precision } => quote! {
+                        netstack::schema::Encoding::Quantize { min: #min, max: #max, precision: #precision, bits: #bits }
+                    },
+                };
+                quote! { Some(#value) }
+            },
+            None => quote! { None },
+        };
+
+        let since = optional(attributes.since);
+        let until = optional(attributes.until);
+        let max_len = optional(attributes.max_len);
+
+        result.push(quote! {
+            netstack::schema::Field {
+                name: #name.to_owned(),
+                field_type: #field_type,
+                encoding: #encoding,
+                since: #since,
+                until: #until,
+                max_len: #max_len,
+            }
+        });
+    }
+
+    Ok((result, nested))
+}
+
+fn optional<T: quote::ToTokens>(value: Option<T>) -> proc_macro2::TokenStream {
+    match value {
+        Some(value) => quote! { Some(#value) },
+        None => quote! { None },
+    }
+}
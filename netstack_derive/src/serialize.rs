@@ -1,4 +1,6 @@
-use syn::{spanned::Spanned, Data, DataEnum, DataStruct};
+use syn::{spanned::Spanned, Data, DataEnum, DataStruct, Fields};
+use crate::attributes::{Encoding, FieldAttributes};
+use crate::common::{construct, discriminant_bits, field_bindings, with_bounds};
 use quote::{quote, quote_spanned};
 
 pub(crate) fn derive_serialize_impl(input: syn::DeriveInput) -> Result<proc_macro2::TokenStream, syn::Error> {
@@ -14,26 +16,194 @@ pub(crate) fn derive_serialize_impl(input: syn::DeriveInput) -> Result<proc_macr
 
 fn derive_struct(input: &syn::DeriveInput, s: &DataStruct) -> Result<proc_macro2::TokenStream, syn::Error> {
     let derive_type = &input.ident;
+    let generics = serialize_bounds(&input.generics);
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
 
-    let fields: Vec<proc_macro2::TokenStream> = s.fields.iter()
-        .map(|field| {
-            let name = &field.ident;
-            let ty = &field.ty;
-            quote_spanned! { ty.span() => self.#name.serialize(serializer); }
+    let values = s.fields.iter()
+        .enumerate()
+        .map(|(index, field)| {
+            let member = match &field.ident {
+                Some(name) => syn::Member::Named(name.clone()),
+                None => syn::Member::Unnamed(index.into()),
+            };
+            quote! { &self.#member }
         }).collect();
 
+    let fields = serialize_fields(&s.fields, values)?;
+    let size = fields_size(&s.fields)?;
+
     let result = quote! {
-        impl netstack::serialization::Serialize for #derive_type {
-            fn serialize(&self, serializer: &mut impl netstack::serialization::Serializer) {
+        impl #impl_generics netstack::serialization::Serialize for #derive_type #type_generics #where_clause {
+            const MAX_ENCODED_SIZE: Option<usize> = #size;
+
+            fn serialize(&self, serializer: &mut impl netstack::serialization::Serializer) -> Result<(), netstack::serialization::SerializationError> {
                 #(#fields)*
+                Ok(())
+            }
+        }
+    };
+
+    Ok(result)
+}
+
+fn derive_enum(input: &syn::DeriveInput, e: &DataEnum) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let derive_type = &input.ident;
+    let generics = serialize_bounds(&input.generics);
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+    let bits = discriminant_bits(e)?;
+
+    let variants = e.variants.iter()
+        .enumerate()
+        .map(|(index, variant)| {
+            let name = &variant.ident;
+            let index = index as u64;
+            let bindings = field_bindings(&variant.fields, "field")?;
+            let fields = serialize_fields(&variant.fields, bindings.clone())?;
+
+            let pattern = construct(quote! { Self::#name }, &variant.fields, bindings);
+
+            Ok(quote! {
+                #pattern => {
+                    serializer.serialize_bits(&#index, #bits)?;
+                    #(#fields)*
+                }
+            })
+        }).collect::<Result<Vec<_>, syn::Error>>()?;
+
+    let sizes = e.variants.iter()
+        .map(|variant| fields_size(&variant.fields))
+        .collect::<Result<Vec<_>, syn::Error>>()?;
+    let discriminant_size = bits.div_ceil(8) as usize;
+    let size = quote! {
+        {
+            let size = Some(0);
+            #(let size = netstack::serialization::max_sizes(size, #sizes);)*
+            netstack::serialization::add_sizes(size, Some(#discriminant_size))
+        }
+    };
+
+    // an enum without variants has no values, there is nothing to write
+    let body = if variants.is_empty() {
+        quote! { match *self {} }
+    } else {
+        quote! {
+            match self {
+                #(#variants)*
             }
+
+            Ok(())
         }
     };
 
+    let result = quote! {
+        impl #impl_generics netstack::serialization::Serialize for #derive_type #type_generics #where_clause {
+            const MAX_ENCODED_SIZE: Option<usize> = #size;
+
+            fn serialize(&self, serializer: &mut impl netstack::serialization::Serializer) -> Result<(), netstack::serialization::SerializationError> {
+                #body
+            }
+        }
+    };
+
+    Ok(result)
+}
+
+fn serialize_bounds(generics: &syn::Generics) -> syn::Generics {
+    with_bounds(generics, |_| syn::parse_quote! { netstack::serialization::Serialize })
+}
+
+/// Writes every field that isn't skipped or missing in the serializer's version, `values` are references to the fields.
+fn serialize_fields(fields: &Fields, values: Vec<proc_macro2::TokenStream>) -> Result<Vec<proc_macro2::TokenStream>, syn::Error> {
+    let mut result = Vec::new();
+
+    for (field, value) in fields.iter().zip(values) {
+        let attributes = FieldAttributes::from_field(field)?;
+
+        if attributes.skip {
+            continue;
+        }
+
+        let write = serialize_field(field, &attributes, &value);
+        let version = quote! { netstack::serialization::Serializer::get_version(serializer) };
+
+        match attributes.version_check(&version) {
+            Some(check) => result.push(quote! {
+                if #check {
+                    #write
+                }
+            }),
+            None => result.push(write),
+        }
+    }
+
     Ok(result)
 }
 
-fn derive_enum(input: &syn::DeriveInput, _s: &DataEnum) -> Result<proc_macro2::TokenStream, syn::Error> {
-    dbg!(&input.ident);
-    unimplemented!()
+/// Returns the largest encoded size of the fields, fields of every version are included.
+fn fields_size(fields: &Fields) -> Result<proc_macro2::TokenStream, syn::Error> {
+    let mut sizes = Vec::new();
+
+    for field in fields {
+        let attributes = FieldAttributes::from_field(field)?;
+        let ty = &field.ty;
+
+        let size = match (&attributes.with, &attributes.encoding, attributes.max_len) {
+            (Some(_), _, _) => quote! { None },
+            (None, Some(encoding), _) => {
+                let bytes = encoding.get_bits().div_ceil(8) as usize;
+                quote! { Some(#bytes) }
+            },
+            (None, None, Some(max_len)) => quote_spanned! { ty.span() =>
+                netstack::serialization::length_prefixed_size(#max_len, <#ty as netstack::serialization::LengthPrefixed>::MAX_ELEMENT_SIZE)
+            },
+            (None, None, None) => quote_spanned! { ty.span() => <#ty as netstack::serialization::Serialize>::MAX_ENCODED_SIZE },
+        };
+
+        if !attributes.skip {
+            sizes.push(size);
+        }
+    }
+
+    Ok(quote! {
+        {
+            let size = Some(0);
+            #(let size = netstack::serialization::add_sizes(size, #sizes);)*
+            size
+        }
+    })
+}
+
+/// Rejects values longer than `#[netstack(max_len = N)]`, `value` is a reference to the field.
+pub(crate) fn check_max_len(field: &syn::Field, attributes: &FieldAttributes, value: &proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    let ty = &field.ty;
+
+    match attributes.max_len {
+        Some(max_len) => quote_spanned! { ty.span() =>
+            netstack::serialization::check_max_len(netstack::serialization::LengthPrefixed::get_length(#value), #max_len)?;
+        },
+        None => quote! {},
+    }
+}
+
+/// Writes a single field, `value` is a reference to it.
+pub(crate) fn serialize_field(field: &syn::Field, attributes: &FieldAttributes, value: &proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    let ty = &field.ty;
+    let check = check_max_len(field, attributes, value);
+
+    match (&attributes.with, &attributes.encoding) {
+        (Some(with), _) => quote_spanned! { with.span() => #with::serialize(#value, serializer)?; },
+        (None, Some(Encoding::Bits(bits))) => quote_spanned! { ty.span() =>
+            netstack::serialization::Serializer::serialize_bits(serializer, &u64::from(*#value), #bits)?;
+        },
+        (None, Some(Encoding::Range(min, max))) => quote_spanned! { ty.span() =>
+            netstack::serialization::Serializer::serialize_ranged(serializer, &(*#value as i64), #min, #max)?;
+        },
+        (None, Some(Encoding::Quantize { min, max, precision })) => quote_spanned! { ty.span() =>
+            netstack::serialization::FloatQuantization::new(#min, #max, #precision)?.serialize(#value, serializer)?;
+        },
+        (None, None) => quote_spanned! { ty.span() =>
+            #check
+            netstack::serialization::Serialize::serialize(#value, serializer)?;
+        },
+    }
 }